@@ -1,6 +1,8 @@
-use dds::D3DFormat;
+use dds::{D3DFormat, Dds, DxgiFormat};
 
-#[derive(Debug, Copy, Clone)]
+use crate::texture_header::{Endian, Swizzle, TextureFormat};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Format {
   Dxt1,
   Dxt3,
@@ -50,6 +52,82 @@ pub(crate) fn get_dds_format(format: &Format) -> D3DFormat {
   }
 }
 
+pub(crate) fn get_format_from_dds(format: &D3DFormat) -> Option<Format> {
+  match format {
+    D3DFormat::DXT1 => Some(Format::Dxt1),
+    D3DFormat::DXT3 => Some(Format::Dxt3),
+    D3DFormat::DXT5 => Some(Format::Dxt5),
+    D3DFormat::A8R8G8B8 => Some(Format::RGBA8),
+    _ => None,
+  }
+}
+
+/// How the components of a DDS format are interpreted, which the fetch constant
+/// expresses with `TextureSign`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Encoding {
+  Unorm,
+  Snorm,
+  Srgb,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct DdsFormat {
+  pub(crate) format: Format,
+  /// `None` without a DX10 header, which can't tell sRGB or SNORM data apart.
+  pub(crate) encoding: Option<Encoding>,
+  /// Red in the first byte of a pixel, surfaces store blue there like A8R8G8B8.
+  pub(crate) red_first: bool,
+}
+
+/// The format of `dds`, from the DX10 header if it has one.
+pub(crate) fn get_format_from_dds_file(dds: &Dds) -> Option<DdsFormat> {
+  let Some(header10) = &dds.header10 else {
+    let format = get_format_from_dds(&dds.get_d3d_format()?)?;
+    return Some(DdsFormat {
+      format,
+      encoding: None,
+      red_first: false,
+    });
+  };
+
+  let (format, encoding, red_first) = match header10.dxgi_format {
+    DxgiFormat::BC1_Typeless | DxgiFormat::BC1_UNorm => (Format::Dxt1, Encoding::Unorm, false),
+    DxgiFormat::BC1_UNorm_sRGB => (Format::Dxt1, Encoding::Srgb, false),
+    DxgiFormat::BC2_Typeless | DxgiFormat::BC2_UNorm => (Format::Dxt3, Encoding::Unorm, false),
+    DxgiFormat::BC2_UNorm_sRGB => (Format::Dxt3, Encoding::Srgb, false),
+    DxgiFormat::BC3_Typeless | DxgiFormat::BC3_UNorm => (Format::Dxt5, Encoding::Unorm, false),
+    DxgiFormat::BC3_UNorm_sRGB => (Format::Dxt5, Encoding::Srgb, false),
+    DxgiFormat::B8G8R8A8_Typeless | DxgiFormat::B8G8R8A8_UNorm => {
+      (Format::RGBA8, Encoding::Unorm, false)
+    }
+    DxgiFormat::B8G8R8A8_UNorm_sRGB => (Format::RGBA8, Encoding::Srgb, false),
+    DxgiFormat::R8G8B8A8_Typeless | DxgiFormat::R8G8B8A8_UNorm => {
+      (Format::RGBA8, Encoding::Unorm, true)
+    }
+    DxgiFormat::R8G8B8A8_UNorm_sRGB => (Format::RGBA8, Encoding::Srgb, true),
+    DxgiFormat::R8G8B8A8_SNorm => (Format::RGBA8, Encoding::Snorm, true),
+    // BC5 would be DXN on the 360, which `Format` can't untile or encode yet
+    _ => return None,
+  };
+
+  Some(DdsFormat {
+    format,
+    encoding: Some(encoding),
+    red_first,
+  })
+}
+
+pub(crate) fn get_format_from_texture(format: &TextureFormat) -> Option<Format> {
+  match format {
+    TextureFormat::Dxt1 => Some(Format::Dxt1),
+    TextureFormat::Dxt2_3 => Some(Format::Dxt3),
+    TextureFormat::Dxt4_5 => Some(Format::Dxt5),
+    TextureFormat::_8_8_8_8 => Some(Format::RGBA8),
+    _ => None,
+  }
+}
+
 pub(crate) fn get_format_data(format: &Format) -> FormatData {
   match format {
     Format::Dxt1 => DXT1,
@@ -58,3 +136,29 @@ pub(crate) fn get_format_data(format: &Format) -> FormatData {
     Format::RGBA8 => RGBA8,
   }
 }
+
+pub(crate) fn get_texture_format(format: &Format) -> TextureFormat {
+  match format {
+    Format::Dxt1 => TextureFormat::Dxt1,
+    Format::Dxt3 => TextureFormat::Dxt2_3,
+    Format::Dxt5 => TextureFormat::Dxt4_5,
+    Format::RGBA8 => TextureFormat::_8_8_8_8,
+  }
+}
+
+// Block compressed data is swapped per 16-bit word, packed pixels per dword.
+pub(crate) fn get_endian(format: &Format) -> Endian {
+  match format {
+    Format::Dxt1 | Format::Dxt3 | Format::Dxt5 => Endian::_8in16,
+    Format::RGBA8 => Endian::_8in32,
+  }
+}
+
+// Every supported format is sampled as ARGB, which is the identity swizzle.
+pub(crate) fn get_swizzle(format: &Format) -> [Swizzle; 4] {
+  match format {
+    Format::Dxt1 | Format::Dxt3 | Format::Dxt5 | Format::RGBA8 => {
+      [Swizzle::X, Swizzle::Y, Swizzle::Z, Swizzle::W]
+    }
+  }
+}
@@ -0,0 +1,564 @@
+use crate::format::get_format_data;
+use crate::Format;
+
+// https://learn.microsoft.com/en-us/windows/win32/direct3d10/d3d10-graphics-programming-guide-resources-block-compression
+
+/// Decodes linear surface data of `format` to RGBA8, cropped to `width` x `height`.
+pub(crate) fn decode(format: Format, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+  let format_data = get_format_data(&format);
+  let blocks_x = width.div_ceil(format_data.block_width) as usize;
+  let blocks_y = height.div_ceil(format_data.block_height) as usize;
+  let (width, height) = (width as usize, height as usize);
+
+  if let Format::RGBA8 = format {
+    // stored like A8R8G8B8 in DDS files
+    return data
+      .chunks_exact(4)
+      .take(width * height)
+      .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+      .collect();
+  }
+
+  let bytes_per_block = format_data.bytes_per_block as usize;
+  let mut output = vec![0; width * height * 4];
+
+  for (index, block) in data
+    .chunks_exact(bytes_per_block)
+    .take(blocks_x * blocks_y)
+    .enumerate()
+  {
+    let texels = decode_block(format, block);
+    let block_x = index % blocks_x * 4;
+    let block_y = index / blocks_x * 4;
+
+    for (i, texel) in texels.iter().enumerate() {
+      let x = block_x + i % 4;
+      let y = block_y + i / 4;
+
+      if x < width && y < height {
+        let offset = (y * width + x) * 4;
+        output[offset..offset + 4].copy_from_slice(texel);
+      }
+    }
+  }
+
+  output
+}
+
+fn decode_block(format: Format, block: &[u8]) -> [[u8; 4]; 16] {
+  match format {
+    Format::Dxt1 => decode_color(block, true),
+    Format::Dxt3 => {
+      let mut texels = decode_color(&block[8..], false);
+      let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+      for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = ((alpha >> (i * 4)) & 0xF) as u8 * 17;
+      }
+      texels
+    }
+    Format::Dxt5 => {
+      let mut texels = decode_color(&block[8..], false);
+      let alpha = decode_alpha(&block[..8]);
+      for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+      }
+      texels
+    }
+    Format::RGBA8 => unreachable!(),
+  }
+}
+
+fn expand_565(color: u16) -> [u8; 3] {
+  let r = (color >> 11) as u8 & 0x1F;
+  let g = (color >> 5) as u8 & 0x3F;
+  let b = color as u8 & 0x1F;
+  [
+    (r << 3) | (r >> 2),
+    (g << 2) | (g >> 4),
+    (b << 3) | (b >> 2),
+  ]
+}
+
+// DXT1 blocks with the first endpoint not above the second have three colors and
+// transparent black, the color part of DXT3 and DXT5 always has four
+fn decode_color(block: &[u8], dxt1: bool) -> [[u8; 4]; 16] {
+  let color0 = u16::from_le_bytes([block[0], block[1]]);
+  let color1 = u16::from_le_bytes([block[2], block[3]]);
+  let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+
+  let palette = color_palette(color0, color1, color0 > color1 || !dxt1);
+  std::array::from_fn(|i| palette[(indices >> (i * 2)) as usize & 3])
+}
+
+fn color_palette(color0: u16, color1: u16, four_colors: bool) -> [[u8; 4]; 4] {
+  let a = expand_565(color0);
+  let b = expand_565(color1);
+  let mix = |weight_a: u32, weight_b: u32, divisor: u32| -> [u8; 4] {
+    let channel =
+      |i: usize| ((a[i] as u32 * weight_a + b[i] as u32 * weight_b + divisor / 2) / divisor) as u8;
+    [channel(0), channel(1), channel(2), 255]
+  };
+
+  if four_colors {
+    [mix(1, 0, 1), mix(0, 1, 1), mix(2, 1, 3), mix(1, 2, 3)]
+  } else {
+    [mix(1, 0, 1), mix(0, 1, 1), mix(1, 1, 2), [0; 4]]
+  }
+}
+
+fn decode_alpha(block: &[u8]) -> [u8; 16] {
+  let indices = u64::from_le_bytes([
+    block[2], block[3], block[4], block[5], block[6], block[7], 0, 0,
+  ]);
+
+  let palette = alpha_palette(block[0], block[1]);
+  std::array::from_fn(|i| palette[(indices >> (i * 3)) as usize & 7])
+}
+
+// eight alpha values when the first endpoint is above the second, six and the
+// extremes otherwise
+fn alpha_palette(alpha0: u8, alpha1: u8) -> [u8; 8] {
+  let (alpha0, alpha1) = (alpha0 as u32, alpha1 as u32);
+
+  std::array::from_fn(|i| {
+    let i = i as u32;
+    match i {
+      0 => alpha0 as u8,
+      1 => alpha1 as u8,
+      _ if alpha0 > alpha1 => (((8 - i) * alpha0 + (i - 1) * alpha1 + 3) / 7) as u8,
+      6 => 0,
+      7 => 255,
+      _ => (((6 - i) * alpha0 + (i - 1) * alpha1 + 2) / 5) as u8,
+    }
+  })
+}
+
+/// How hard the encoder searches for the endpoints of a block.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Quality {
+  /// Endpoints from the bounding box of the colors.
+  Fast,
+  /// Endpoints along the principal axis of the colors, refined by least squares.
+  #[default]
+  Normal,
+  /// Also tries the neighbours of the refined endpoints until none is better.
+  Best,
+}
+
+// Weights of the color error, `[1.0; 3]` or luma weights for perceptual encoding.
+pub(crate) type Weights = [f32; 3];
+
+pub(crate) const PERCEPTUAL_WEIGHTS: Weights = [0.299, 0.587, 0.114];
+pub(crate) const UNIFORM_WEIGHTS: Weights = [1.0; 3];
+
+/// Encodes an RGBA8 image into linear surface data of `format`, the counterpart of
+/// [`decode`].
+pub(crate) fn encode(
+  format: Format,
+  width: u32,
+  height: u32,
+  rgba: &[u8],
+  quality: Quality,
+  weights: Weights,
+) -> Vec<u8> {
+  if let Format::RGBA8 = format {
+    return rgba
+      .chunks_exact(4)
+      .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+      .collect();
+  }
+
+  let format_data = get_format_data(&format);
+  let blocks_x = width.div_ceil(format_data.block_width) as usize;
+  let blocks_y = height.div_ceil(format_data.block_height) as usize;
+  let (width, height) = (width as usize, height as usize);
+
+  let mut output = Vec::with_capacity(blocks_x * blocks_y * format_data.bytes_per_block as usize);
+
+  for block_y in 0..blocks_y {
+    for block_x in 0..blocks_x {
+      // partial blocks repeat the last row and column
+      let texels: [[u8; 4]; 16] = std::array::from_fn(|i| {
+        let x = (block_x * 4 + i % 4).min(width - 1);
+        let y = (block_y * 4 + i / 4).min(height - 1);
+        let offset = (y * width + x) * 4;
+        rgba[offset..offset + 4].try_into().unwrap()
+      });
+
+      match format {
+        Format::Dxt1 => output.extend(encode_color(&texels, true, quality, weights)),
+        Format::Dxt3 => {
+          let mut alpha = 0_u64;
+          for (i, texel) in texels.iter().enumerate() {
+            alpha |= ((texel[3] as u64 * 15 + 127) / 255) << (i * 4);
+          }
+          output.extend(alpha.to_le_bytes());
+          output.extend(encode_color(&texels, false, quality, weights));
+        }
+        Format::Dxt5 => {
+          output.extend(encode_alpha(&texels.map(|texel| texel[3]), quality));
+          output.extend(encode_color(&texels, false, quality, weights));
+        }
+        Format::RGBA8 => unreachable!(),
+      }
+    }
+  }
+
+  output
+}
+
+struct ColorFit {
+  color0: u16,
+  color1: u16,
+  indices: u32,
+  error: f32,
+}
+
+fn encode_color(texels: &[[u8; 4]; 16], dxt1: bool, quality: Quality, weights: Weights) -> [u8; 8] {
+  // DXT1 can only store alpha as transparent black, in the three color mode
+  let transparent = texels.map(|texel| dxt1 && texel[3] < 128);
+  let three_colors = transparent.contains(&true);
+
+  let colors: Vec<[f32; 3]> = texels
+    .iter()
+    .zip(transparent)
+    .filter(|(_, transparent)| !transparent)
+    .map(|(texel, _)| [texel[0] as f32, texel[1] as f32, texel[2] as f32])
+    .collect();
+
+  let fit = if colors.is_empty() {
+    ColorFit {
+      color0: 0,
+      color1: 0,
+      indices: u32::MAX,
+      error: 0.0,
+    }
+  } else {
+    let evaluate = |a: [f32; 3], b: [f32; 3]| {
+      fit_indices(
+        texels,
+        &transparent,
+        three_colors,
+        dxt1,
+        quantize_565(a),
+        quantize_565(b),
+        weights,
+      )
+    };
+
+    let (a, b) = match quality {
+      Quality::Fast => bounding_box(&colors),
+      Quality::Normal | Quality::Best => principal_axis(&colors, weights),
+    };
+    let mut best = evaluate(a, b);
+
+    if quality != Quality::Fast {
+      for _ in 0..2 {
+        let Some((a, b)) = least_squares(texels, &transparent, &best, dxt1) else {
+          break;
+        };
+
+        let fit = evaluate(a, b);
+        if fit.error >= best.error {
+          break;
+        }
+        best = fit;
+      }
+    }
+
+    if quality == Quality::Best {
+      best = refine_endpoints(best, |color0, color1| {
+        fit_indices(
+          texels,
+          &transparent,
+          three_colors,
+          dxt1,
+          color0,
+          color1,
+          weights,
+        )
+      });
+    }
+
+    best
+  };
+
+  let mut block = [0; 8];
+  block[..2].copy_from_slice(&fit.color0.to_le_bytes());
+  block[2..4].copy_from_slice(&fit.color1.to_le_bytes());
+  block[4..].copy_from_slice(&fit.indices.to_le_bytes());
+  block
+}
+
+// Orders the endpoints for the mode the block needs and picks the closest palette
+// entry for every texel.
+fn fit_indices(
+  texels: &[[u8; 4]; 16],
+  transparent: &[bool; 16],
+  three_colors: bool,
+  dxt1: bool,
+  color0: u16,
+  color1: u16,
+  weights: Weights,
+) -> ColorFit {
+  let (color0, color1) = if three_colors == (color0 > color1) {
+    (color1, color0)
+  } else {
+    (color0, color1)
+  };
+
+  // equal endpoints select the three color mode of DXT1, which is fine without index 3
+  let four_colors = !dxt1 || color0 > color1;
+  let palette = color_palette(color0, color1, four_colors);
+  let usable = if four_colors { 4 } else { 3 };
+
+  let mut indices = 0;
+  let mut error = 0.0;
+
+  for (i, texel) in texels.iter().enumerate() {
+    if transparent[i] {
+      indices |= 3 << (i * 2);
+      continue;
+    }
+
+    let (index, distance) = palette[..usable]
+      .iter()
+      .map(|color| color_distance(texel, color, weights))
+      .enumerate()
+      .min_by(|a, b| a.1.total_cmp(&b.1))
+      .unwrap();
+
+    indices |= (index as u32) << (i * 2);
+    error += distance;
+  }
+
+  ColorFit {
+    color0,
+    color1,
+    indices,
+    error,
+  }
+}
+
+fn color_distance(a: &[u8; 4], b: &[u8; 4], weights: Weights) -> f32 {
+  (0..3)
+    .map(|i| {
+      let difference = a[i] as f32 - b[i] as f32;
+      difference * difference * weights[i]
+    })
+    .sum()
+}
+
+fn bounding_box(colors: &[[f32; 3]]) -> ([f32; 3], [f32; 3]) {
+  let mut min = [255.0_f32; 3];
+  let mut max = [0.0_f32; 3];
+
+  for color in colors {
+    for i in 0..3 {
+      min[i] = min[i].min(color[i]);
+      max[i] = max[i].max(color[i]);
+    }
+  }
+
+  // the extremes are rarely hit, moving them in by a sixteenth lowers the error
+  let inset = |i: usize| (max[i] - min[i]) / 16.0;
+  (
+    std::array::from_fn(|i| max[i] - inset(i)),
+    std::array::from_fn(|i| min[i] + inset(i)),
+  )
+}
+
+// The colors projected on their principal axis, found by power iteration on the
+// covariance of the weighted colors.
+fn principal_axis(colors: &[[f32; 3]], weights: Weights) -> ([f32; 3], [f32; 3]) {
+  let scale = weights.map(f32::sqrt);
+  let colors: Vec<[f32; 3]> = colors
+    .iter()
+    .map(|color| std::array::from_fn(|i| color[i] * scale[i]))
+    .collect();
+
+  let count = colors.len() as f32;
+  let mean: [f32; 3] =
+    std::array::from_fn(|i| colors.iter().map(|color| color[i]).sum::<f32>() / count);
+
+  let mut covariance = [[0.0_f32; 3]; 3];
+  for color in &colors {
+    for i in 0..3 {
+      for j in 0..3 {
+        covariance[i][j] += (color[i] - mean[i]) * (color[j] - mean[j]);
+      }
+    }
+  }
+
+  let mut axis = [1.0_f32, 1.0, 1.0];
+  for _ in 0..8 {
+    let next: [f32; 3] = std::array::from_fn(|i| (0..3).map(|j| covariance[i][j] * axis[j]).sum());
+    let length = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if length < f32::EPSILON {
+      break;
+    }
+    axis = next.map(|v| v / length);
+  }
+
+  let project = |color: &[f32; 3]| (0..3).map(|i| (color[i] - mean[i]) * axis[i]).sum::<f32>();
+  let (min, max) = colors
+    .iter()
+    .map(project)
+    .fold((f32::MAX, f32::MIN), |(min, max), t| {
+      (min.min(t), max.max(t))
+    });
+
+  let endpoint = |t: f32| -> [f32; 3] {
+    std::array::from_fn(|i| ((mean[i] + axis[i] * t) / scale[i]).clamp(0.0, 255.0))
+  };
+  (endpoint(max), endpoint(min))
+}
+
+// The endpoints minimizing the squared error for the indices of `fit`, `None` if the
+// indices don't determine them.
+fn least_squares(
+  texels: &[[u8; 4]; 16],
+  transparent: &[bool; 16],
+  fit: &ColorFit,
+  dxt1: bool,
+) -> Option<([f32; 3], [f32; 3])> {
+  let four_colors = !dxt1 || fit.color0 > fit.color1;
+  let weights: [(f32, f32); 4] = if four_colors {
+    [
+      (1.0, 0.0),
+      (0.0, 1.0),
+      (2.0 / 3.0, 1.0 / 3.0),
+      (1.0 / 3.0, 2.0 / 3.0),
+    ]
+  } else {
+    [(1.0, 0.0), (0.0, 1.0), (0.5, 0.5), (0.0, 0.0)]
+  };
+
+  let mut aa = 0.0;
+  let mut ab = 0.0;
+  let mut bb = 0.0;
+  let mut ax = [0.0_f32; 3];
+  let mut bx = [0.0_f32; 3];
+
+  for (i, texel) in texels.iter().enumerate() {
+    if transparent[i] {
+      continue;
+    }
+
+    let (alpha, beta) = weights[(fit.indices >> (i * 2)) as usize & 3];
+    aa += alpha * alpha;
+    ab += alpha * beta;
+    bb += beta * beta;
+
+    for c in 0..3 {
+      ax[c] += alpha * texel[c] as f32;
+      bx[c] += beta * texel[c] as f32;
+    }
+  }
+
+  let determinant = aa * bb - ab * ab;
+  if determinant.abs() < f32::EPSILON {
+    return None;
+  }
+
+  Some((
+    std::array::from_fn(|c| ((ax[c] * bb - bx[c] * ab) / determinant).clamp(0.0, 255.0)),
+    std::array::from_fn(|c| ((bx[c] * aa - ax[c] * ab) / determinant).clamp(0.0, 255.0)),
+  ))
+}
+
+// Hill climbing over single steps of one channel of one endpoint.
+fn refine_endpoints(mut best: ColorFit, evaluate: impl Fn(u16, u16) -> ColorFit) -> ColorFit {
+  // red, green and blue as shift and mask of the packed color
+  const CHANNELS: [(u16, u16); 3] = [(11, 0x1F), (5, 0x3F), (0, 0x1F)];
+
+  for _ in 0..16 {
+    let mut improved = false;
+
+    for endpoint in 0..2 {
+      for (shift, mask) in CHANNELS {
+        for step in [-1_i32, 1] {
+          let color = if endpoint == 0 {
+            best.color0
+          } else {
+            best.color1
+          };
+          let value = ((color >> shift) & mask) as i32 + step;
+          if value < 0 || value > mask as i32 {
+            continue;
+          }
+
+          let color = (color & !(mask << shift)) | ((value as u16) << shift);
+          let fit = if endpoint == 0 {
+            evaluate(color, best.color1)
+          } else {
+            evaluate(best.color0, color)
+          };
+
+          if fit.error < best.error {
+            best = fit;
+            improved = true;
+          }
+        }
+      }
+    }
+
+    if !improved {
+      break;
+    }
+  }
+
+  best
+}
+
+fn quantize_565(color: [f32; 3]) -> u16 {
+  let r = (color[0] * 31.0 / 255.0).round() as u16;
+  let g = (color[1] * 63.0 / 255.0).round() as u16;
+  let b = (color[2] * 31.0 / 255.0).round() as u16;
+  (r << 11) | (g << 5) | b
+}
+
+fn encode_alpha(alphas: &[u8; 16], quality: Quality) -> [u8; 8] {
+  let min = *alphas.iter().min().unwrap();
+  let max = *alphas.iter().max().unwrap();
+
+  let mut best = fit_alpha(alphas, max, min);
+
+  // six interpolated values between the inner extremes, with 0 and 255 exact
+  if quality == Quality::Best {
+    let inner = alphas.iter().filter(|&&alpha| alpha != 0 && alpha != 255);
+    if let (Some(&low), Some(&high)) = (inner.clone().min(), inner.max()) {
+      let fit = fit_alpha(alphas, low, high);
+      if fit.1 < best.1 {
+        best = fit;
+      }
+    }
+  }
+
+  best.0
+}
+
+fn fit_alpha(alphas: &[u8; 16], alpha0: u8, alpha1: u8) -> ([u8; 8], u32) {
+  let palette = alpha_palette(alpha0, alpha1);
+
+  let mut indices = 0_u64;
+  let mut error = 0;
+
+  for (i, &alpha) in alphas.iter().enumerate() {
+    let (index, distance) = palette
+      .iter()
+      .map(|&value| (value as i32 - alpha as i32).unsigned_abs())
+      .enumerate()
+      .min_by_key(|&(_, distance)| distance)
+      .unwrap();
+
+    indices |= (index as u64) << (i * 3);
+    error += distance * distance;
+  }
+
+  let mut block = [0; 8];
+  block[0] = alpha0;
+  block[1] = alpha1;
+  block[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+  (block, error)
+}
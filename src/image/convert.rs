@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+
+use crate::Format;
+
+/// Precision a conversion step gives up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Loss {
+  /// Colors are snapped to two 5:6:5 endpoints per block and interpolated.
+  Color,
+  /// Alpha is reduced to fully transparent or opaque.
+  AlphaToOneBit,
+  /// Alpha is quantized to 4 bits.
+  AlphaToFourBits,
+  /// Alpha is snapped to two endpoints per block and interpolated.
+  AlphaInterpolated,
+}
+
+/// A decode or encode on the way from the format of the source to the one of the
+/// texture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionStep {
+  pub from: Format,
+  pub to: Format,
+  /// Empty for lossless steps.
+  pub losses: Vec<Loss>,
+}
+
+// Every block format decodes to RGBA8 and encodes from it, no format is transcoded
+// directly yet.
+fn edges(format: Format) -> Vec<Format> {
+  match format {
+    Format::RGBA8 => vec![Format::Dxt1, Format::Dxt3, Format::Dxt5],
+    _ => vec![Format::RGBA8],
+  }
+}
+
+/// The shortest chain of steps converting `from` into `to`, empty if they're the same.
+pub(crate) fn conversion_steps(from: Format, to: Format) -> Vec<ConversionStep> {
+  let mut previous: Vec<(Format, Format)> = Vec::new();
+  let mut queue = VecDeque::from([from]);
+
+  while let Some(format) = queue.pop_front() {
+    if format == to {
+      break;
+    }
+
+    for next in edges(format) {
+      if next != from && !previous.iter().any(|&(node, _)| node == next) {
+        previous.push((next, format));
+        queue.push_back(next);
+      }
+    }
+  }
+
+  let mut path = vec![to];
+  while let Some(&(_, format)) = previous.iter().find(|&&(node, _)| node == path[0]) {
+    path.insert(0, format);
+  }
+
+  path
+    .windows(2)
+    .map(|step| ConversionStep {
+      from: step[0],
+      to: step[1],
+      losses: losses(from, step[1]),
+    })
+    .collect()
+}
+
+/// The steps of decoding `from` to RGBA8 and encoding the result into `to`, for data
+/// edited in between. Unlike [`conversion_steps`] this isn't empty for the same format.
+pub(crate) fn reencode_steps(from: Format, to: Format) -> Vec<ConversionStep> {
+  let decode = (from != Format::RGBA8).then(|| ConversionStep {
+    from,
+    to: Format::RGBA8,
+    losses: Vec::new(),
+  });
+  let encode = (to != Format::RGBA8).then(|| ConversionStep {
+    from: Format::RGBA8,
+    to,
+    losses: losses(from, to),
+  });
+
+  decode.into_iter().chain(encode).collect()
+}
+
+// What encoding data that started out as `source` into `target` gives up.
+fn losses(source: Format, target: Format) -> Vec<Loss> {
+  let alpha = match (source, target) {
+    (Format::Dxt1, _) | (_, Format::RGBA8) => None,
+    (_, Format::Dxt1) => Some(Loss::AlphaToOneBit),
+    (Format::Dxt3, Format::Dxt3) => None,
+    (_, Format::Dxt3) => Some(Loss::AlphaToFourBits),
+    (Format::Dxt5, Format::Dxt5) => None,
+    (_, Format::Dxt5) => Some(Loss::AlphaInterpolated),
+  };
+
+  match target {
+    Format::RGBA8 => vec![],
+    _ => [Some(Loss::Color), alpha].into_iter().flatten().collect(),
+  }
+}
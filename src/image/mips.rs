@@ -0,0 +1,243 @@
+use std::f32::consts::PI;
+
+use crate::image::RgbaImage;
+
+/// Filter used to downsample each mip from the one before.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum MipmapFilter {
+  /// Averages the texels a mip texel covers, cheap but a little blurry.
+  #[default]
+  Box,
+  /// A windowed sinc, sharper than box with hardly any ringing.
+  Kaiser,
+  /// Three lobes of a sinc, the sharpest but prone to halos around edges.
+  Lanczos,
+}
+
+impl MipmapFilter {
+  // Distance from the center beyond which the weights are zero, in texels of the
+  // smaller image.
+  fn support(&self) -> f32 {
+    match self {
+      MipmapFilter::Box => 0.5,
+      MipmapFilter::Kaiser => 3.0,
+      MipmapFilter::Lanczos => 3.0,
+    }
+  }
+
+  fn weight(&self, x: f32) -> f32 {
+    let x = x.abs();
+    if x > self.support() {
+      return 0.0;
+    }
+
+    match self {
+      MipmapFilter::Box if x == 0.5 => 0.5,
+      MipmapFilter::Box => 1.0,
+      // the alpha of 4 nvtt uses as well
+      MipmapFilter::Kaiser => {
+        let ratio = x / self.support();
+        sinc(x) * bessel_i0(4.0 * (1.0 - ratio * ratio).sqrt()) / bessel_i0(4.0)
+      }
+      MipmapFilter::Lanczos => sinc(x) * sinc(x / self.support()),
+    }
+  }
+}
+
+fn sinc(x: f32) -> f32 {
+  if x == 0.0 {
+    1.0
+  } else {
+    (PI * x).sin() / (PI * x)
+  }
+}
+
+// Modified Bessel function of the first kind, from its power series.
+fn bessel_i0(x: f32) -> f32 {
+  let mut sum = 1.0;
+  let mut term = 1.0;
+  let mut k = 1.0;
+
+  while term > sum * 1e-7 {
+    term *= (x / (2.0 * k)) * (x / (2.0 * k));
+    sum += term;
+    k += 1.0;
+  }
+
+  sum
+}
+
+// An image with linear channels between 0 and 1, so filtering doesn't lose precision
+// between mips.
+struct LinearImage {
+  width: u32,
+  height: u32,
+  texels: Vec<[f32; 4]>,
+}
+
+impl LinearImage {
+  // Decodes the channels marked in `gamma` from sRGB.
+  fn from_rgba(image: &RgbaImage, gamma: [bool; 4]) -> Self {
+    let texels = image
+      .data
+      .chunks_exact(4)
+      .map(|pixel| {
+        std::array::from_fn(|c| {
+          let value = pixel[c] as f32 / 255.0;
+          if gamma[c] {
+            srgb_to_linear(value)
+          } else {
+            value
+          }
+        })
+      })
+      .collect();
+
+    Self {
+      width: image.width,
+      height: image.height,
+      texels,
+    }
+  }
+
+  fn to_rgba(&self, gamma: [bool; 4]) -> RgbaImage {
+    let data = self
+      .texels
+      .iter()
+      .flat_map(|texel| {
+        std::array::from_fn::<u8, 4, _>(|c| {
+          let value = texel[c].clamp(0.0, 1.0);
+          let value = if gamma[c] {
+            linear_to_srgb(value)
+          } else {
+            value
+          };
+          (value * 255.0 + 0.5) as u8
+        })
+      })
+      .collect();
+
+    RgbaImage {
+      width: self.width,
+      height: self.height,
+      data,
+    }
+  }
+
+  // Scales the image to `width` x `height`, one axis after the other.
+  fn resample(&self, width: u32, height: u32, filter: MipmapFilter) -> Self {
+    let horizontal = weights(self.width, width, filter);
+    let vertical = weights(self.height, height, filter);
+
+    let mut rows = Vec::with_capacity((width * self.height) as usize);
+    for row in self.texels.chunks_exact(self.width as usize) {
+      for taps in &horizontal {
+        rows.push(apply(taps, |x| row[x]));
+      }
+    }
+
+    let mut texels = Vec::with_capacity((width * height) as usize);
+    for taps in &vertical {
+      for x in 0..width as usize {
+        texels.push(apply(taps, |y| rows[y * width as usize + x]));
+      }
+    }
+
+    Self {
+      width,
+      height,
+      texels,
+    }
+  }
+}
+
+// Source texels and their normalized weights for every texel of the scaled axis.
+fn weights(source: u32, target: u32, filter: MipmapFilter) -> Vec<Vec<(usize, f32)>> {
+  let scale = source as f32 / target as f32;
+  // magnifying samples the filter in source texels instead
+  let stretch = scale.max(1.0);
+  let support = filter.support() * stretch;
+
+  (0..target)
+    .map(|x| {
+      let center = (x as f32 + 0.5) * scale;
+      let first = (center - support).floor() as i64;
+      let last = (center + support).ceil() as i64;
+
+      let mut taps: Vec<(usize, f32)> = (first..=last)
+        .filter_map(|s| {
+          let weight = filter.weight((s as f32 + 0.5 - center) / stretch);
+          // the edge texels repeat outside of the image
+          let s = s.clamp(0, source as i64 - 1) as usize;
+          (weight != 0.0).then_some((s, weight))
+        })
+        .collect();
+
+      let sum: f32 = taps.iter().map(|(_, weight)| weight).sum();
+      taps.iter_mut().for_each(|(_, weight)| *weight /= sum);
+      taps
+    })
+    .collect()
+}
+
+fn apply(taps: &[(usize, f32)], texel: impl Fn(usize) -> [f32; 4]) -> [f32; 4] {
+  let mut sum = [0.0; 4];
+  for &(index, weight) in taps {
+    for (sum, value) in sum.iter_mut().zip(texel(index)) {
+      *sum += value * weight;
+    }
+  }
+  sum
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+  if value <= 0.04045 {
+    value / 12.92
+  } else {
+    ((value + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+  if value <= 0.0031308 {
+    value * 12.92
+  } else {
+    1.055 * value.powf(1.0 / 2.4) - 0.055
+  }
+}
+
+/// `image` followed by `levels - 1` mips, each half the size of the one before and
+/// rounded down like the mips of the console. The channels marked in `gamma` are
+/// filtered in linear space.
+pub(crate) fn generate_mips(
+  image: &RgbaImage,
+  levels: u32,
+  filter: MipmapFilter,
+  gamma: [bool; 4],
+) -> Vec<RgbaImage> {
+  let mut mips = vec![image.clone()];
+  let mut previous = LinearImage::from_rgba(image, gamma);
+
+  for _ in 1..levels {
+    let width = 1.max(previous.width / 2);
+    let height = 1.max(previous.height / 2);
+    previous = previous.resample(width, height, filter);
+    mips.push(previous.to_rgba(gamma));
+  }
+
+  mips
+}
+
+/// Scales `image` to `width` x `height`, filtering the channels marked in `gamma` in
+/// linear space.
+pub(crate) fn resize(
+  image: &RgbaImage,
+  width: u32,
+  height: u32,
+  filter: MipmapFilter,
+  gamma: [bool; 4],
+) -> RgbaImage {
+  LinearImage::from_rgba(image, gamma)
+    .resample(width, height, filter)
+    .to_rgba(gamma)
+}
@@ -0,0 +1,695 @@
+//! Conversion between untiled textures and common image files.
+//!
+//! Block compressed surfaces are decoded in software, every surface becomes its own
+//! RGBA8 image written as PNG or TGA. The other way around, [`import_texture`]
+//! compresses an image with the built-in encoder and tiles it, mips included, and
+//! [`import_dds`] converts a DDS file to the format of the header and generates the
+//! mips it lacks.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use dds::Dds;
+use miniz_oxide::inflate::TINFLStatus;
+
+pub use crate::image::bc::Quality;
+use crate::image::bc::{Weights, PERCEPTUAL_WEIGHTS, UNIFORM_WEIGHTS};
+use crate::image::convert::{conversion_steps, reencode_steps};
+pub use crate::image::convert::{ConversionStep, Loss};
+use crate::image::mips::generate_mips;
+pub use crate::image::mips::MipmapFilter;
+use crate::{
+  tile_texture, Config, Format, LayoutError, LayoutRequest, Surface, Swizzle, Texture,
+  TextureError, TextureFormat, TextureHeader, TextureMetadata, TextureSign, TextureType,
+};
+
+mod bc;
+mod convert;
+mod mips;
+mod png;
+mod tga;
+
+#[derive(Debug)]
+pub enum ImageError {
+  Io(io::Error),
+  /// A damaged file, or one that is neither PNG nor TGA.
+  Malformed(&'static str),
+  /// A valid file using a feature that isn't supported, like interlacing.
+  Unsupported(&'static str),
+  Zlib(TINFLStatus),
+  /// The header names a format the encoder doesn't support.
+  UnsupportedFormat(TextureFormat),
+  /// A texture that can't be made from a single image, or a DDS file of another type.
+  UnsupportedTextureType(TextureType),
+  SizeMismatch {
+    expected: (u32, u32),
+    found: (u32, u32),
+  },
+  /// The size of the image doesn't fit the texture, when relaying it out.
+  Layout(LayoutError),
+  Texture(TextureError),
+}
+
+impl fmt::Display for ImageError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ImageError::Io(error) => write!(f, "{}", error),
+      ImageError::Malformed(reason) => write!(f, "malformed image: {}", reason),
+      ImageError::Unsupported(feature) => write!(f, "unsupported image: {}", feature),
+      ImageError::Zlib(status) => write!(f, "invalid zlib data: {:?}", status),
+      ImageError::UnsupportedFormat(format) => write!(f, "unsupported texture format {:?}", format),
+      ImageError::UnsupportedTextureType(texture_type) => {
+        write!(f, "can't import an image into a {:?} texture", texture_type)
+      }
+      ImageError::SizeMismatch { expected, found } => write!(
+        f,
+        "image is {}x{}, expected was {}x{}",
+        found.0, found.1, expected.0, expected.1
+      ),
+      ImageError::Layout(error) => write!(f, "{}", error),
+      ImageError::Texture(error) => write!(f, "{}", error),
+    }
+  }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<io::Error> for ImageError {
+  fn from(error: io::Error) -> Self {
+    ImageError::Io(error)
+  }
+}
+
+impl From<TextureError> for ImageError {
+  fn from(error: TextureError) -> Self {
+    ImageError::Texture(error)
+  }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ImageFormat {
+  #[default]
+  Png,
+  Tga,
+}
+
+impl ImageFormat {
+  pub fn extension(&self) -> &'static str {
+    match self {
+      ImageFormat::Png => "png",
+      ImageFormat::Tga => "tga",
+    }
+  }
+}
+
+/// An image with 8 bits per channel, in the order red, green, blue and alpha.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+  pub width: u32,
+  pub height: u32,
+  pub data: Vec<u8>,
+}
+
+impl RgbaImage {
+  /// Reads a PNG or TGA file, told apart by the PNG signature.
+  pub fn read<R: Read>(reader: &mut R) -> Result<Self, ImageError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    if png::is_png(&data) {
+      png::read(&data)
+    } else {
+      tga::read(&data)
+    }
+  }
+
+  /// Decodes a surface of a texture in `format`.
+  pub fn from_surface(format: Format, surface: &Surface) -> Self {
+    Self {
+      width: surface.width,
+      height: surface.height,
+      data: bc::decode(format, surface.width, surface.height, &surface.data),
+    }
+  }
+
+  /// Rearranges the channels like the sampler does with the swizzle of a fetch
+  /// constant, see [`TextureMetadata::swizzle`](crate::TextureMetadata::swizzle).
+  pub fn apply_swizzle(&mut self, swizzle: [Swizzle; 4]) {
+    for pixel in self.data.chunks_exact_mut(4) {
+      let texel = [pixel[0], pixel[1], pixel[2], pixel[3]];
+
+      for (i, swizzle) in swizzle.iter().enumerate() {
+        pixel[i] = match swizzle {
+          Swizzle::X => texel[0],
+          Swizzle::Y => texel[1],
+          Swizzle::Z => texel[2],
+          Swizzle::W => texel[3],
+          Swizzle::Zero => 0,
+          Swizzle::One => 255,
+          Swizzle::Keep => texel[i],
+        };
+      }
+    }
+  }
+
+  /// Divides the color channels by alpha, for DXT2 and DXT4 textures which are stored
+  /// like DXT3 and DXT5 with premultiplied alpha.
+  pub fn unpremultiply(&mut self) {
+    for pixel in self.data.chunks_exact_mut(4) {
+      let alpha = pixel[3] as u32;
+      if alpha == 0 {
+        continue;
+      }
+
+      for channel in &mut pixel[..3] {
+        *channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+      }
+    }
+  }
+
+  pub fn write<W: Write>(&self, format: ImageFormat, writer: &mut W) -> io::Result<()> {
+    match format {
+      ImageFormat::Png => png::write(self, writer),
+      ImageFormat::Tga => tga::write(self, writer),
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ExportOptions {
+  pub format: ImageFormat,
+  /// Exports every mip instead of only the largest.
+  pub all_mips: bool,
+  /// Swizzle of the fetch constant, applied after decoding.
+  pub swizzle: Option<[Swizzle; 4]>,
+  /// Undoes premultiplied alpha, see [`RgbaImage::unpremultiply`].
+  pub unpremultiply: bool,
+}
+
+/// An encoded image file holding one surface of a texture.
+#[derive(Debug, Clone)]
+pub struct ExportedImage {
+  pub mip: u32,
+  pub layer: u32,
+  pub face: u32,
+  pub data: Vec<u8>,
+}
+
+impl ExportedImage {
+  /// A file name telling the surfaces of a texture apart, like `name_face2_mip1.png`.
+  /// Only the parts that vary within the texture are included.
+  pub fn file_name(&self, name: &str, texture: &Texture, format: ImageFormat) -> String {
+    let mut file_name = name.to_string();
+
+    match texture.texture_type {
+      TextureType::Cube => file_name += &format!("_face{}", self.face),
+      _ if texture.depth > 1 => file_name += &format!("_layer{}", self.layer),
+      _ => {}
+    }
+
+    if texture.mipmap_levels > 1 {
+      file_name += &format!("_mip{}", self.mip);
+    }
+
+    format!("{}.{}", file_name, format.extension())
+  }
+}
+
+/// Decodes the surfaces of `texture` and encodes each as an image file.
+pub fn export_texture(
+  texture: &Texture,
+  options: &ExportOptions,
+) -> io::Result<Vec<ExportedImage>> {
+  let mut images = Vec::new();
+
+  for surface in &texture.surfaces {
+    if surface.mip > 0 && !options.all_mips {
+      continue;
+    }
+
+    let mut image = RgbaImage::from_surface(texture.format, surface);
+
+    if options.unpremultiply {
+      image.unpremultiply();
+    }
+
+    if let Some(swizzle) = options.swizzle {
+      image.apply_swizzle(swizzle);
+    }
+
+    let mut data = Vec::new();
+    image.write(options.format, &mut data)?;
+
+    images.push(ExportedImage {
+      mip: surface.mip,
+      layer: surface.layer,
+      face: surface.face,
+      data,
+    });
+  }
+
+  Ok(images)
+}
+
+/// What to do when the size of the imported image differs from the header.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ResizeMode {
+  /// Fails with [`ImageError::SizeMismatch`].
+  #[default]
+  Reject,
+  /// Scales the image to the size of the header.
+  Resample,
+  /// Lays the texture out again at the size of the image and writes the new size,
+  /// pitch and addresses back into the header.
+  Relayout,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ImportOptions {
+  pub quality: Quality,
+  /// Weighs the color error by how sensitive the eye is to each channel.
+  pub perceptual: bool,
+  /// Filter of the generated mips and of resampling. Channels with the gamma sign are
+  /// filtered in linear space.
+  pub mipmap_filter: MipmapFilter,
+  pub resize: ResizeMode,
+}
+
+impl ImportOptions {
+  fn weights(&self) -> Weights {
+    if self.perceptual {
+      PERCEPTUAL_WEIGHTS
+    } else {
+      UNIFORM_WEIGHTS
+    }
+  }
+}
+
+impl Default for ImportOptions {
+  fn default() -> Self {
+    Self {
+      quality: Quality::default(),
+      perceptual: true,
+      mipmap_filter: MipmapFilter::default(),
+      resize: ResizeMode::default(),
+    }
+  }
+}
+
+/// A resize done while importing an image of `image_size` into a texture of
+/// `texture_size`. [`ResizeMode::Resample`] scales the image to the texture,
+/// [`ResizeMode::Relayout`] lays the texture out again for the image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Resize {
+  pub mode: ResizeMode,
+  pub image_size: (u32, u32),
+  /// The size of the texture in the header passed in.
+  pub texture_size: (u32, u32),
+}
+
+/// Tiled texture data along with the header describing it.
+#[derive(Debug, Clone)]
+pub struct ImportedTexture {
+  /// Laid out for a base address of 0, ready for
+  /// [`Xpr2::replace_texture`](crate::Xpr2::replace_texture).
+  pub data: Vec<u8>,
+  /// The header passed in, moved to address 0 and rewritten by
+  /// [`ResizeMode::Relayout`].
+  pub header: TextureHeader,
+  pub resize: Option<Resize>,
+  /// How the source was converted to the format of the header, including a decode and
+  /// encode in the same format for resized or generated mips. See
+  /// [`ConversionStep::losses`] for what got lost on the way.
+  pub conversion: Vec<ConversionStep>,
+}
+
+/// Compresses `image` to the format of `header`, generates the mips it asks for and
+/// tiles everything into the layout it describes. Fails with [`ImageError::Malformed`]
+/// for empty images and data that isn't `width * height` RGBA pixels.
+pub fn import_texture(
+  image: &RgbaImage,
+  header: &TextureHeader,
+  options: &ImportOptions,
+) -> Result<ImportedTexture, ImageError> {
+  if image.width == 0 || image.height == 0 {
+    return Err(ImageError::Malformed("empty image"));
+  }
+
+  let size = (image.width as usize)
+    .checked_mul(image.height as usize)
+    .and_then(|pixels| pixels.checked_mul(4));
+  if size != Some(image.data.len()) {
+    return Err(ImageError::Malformed("image data doesn't match its size"));
+  }
+
+  let metadata = header.metadata();
+  let config = Config::from_metadata(&metadata)
+    .ok_or_else(|| ImageError::UnsupportedFormat(metadata.format()))?;
+
+  if config.texture_type != TextureType::TwoD {
+    return Err(ImageError::UnsupportedTextureType(config.texture_type));
+  }
+
+  let (config, header, resize) = fit_size(header, (image.width, image.height), options.resize)?;
+
+  let resampled;
+  let image = if (image.width, image.height) != (config.width, config.height) {
+    resampled = mips::resize(
+      image,
+      config.width,
+      config.height,
+      options.mipmap_filter,
+      gamma(&metadata),
+    );
+    &resampled
+  } else {
+    image
+  };
+
+  let mipmap_levels = config.mipmap_levels.unwrap_or(1);
+  let surfaces = generate_mips(
+    image,
+    mipmap_levels,
+    options.mipmap_filter,
+    gamma(&metadata),
+  )
+  .into_iter()
+  .enumerate()
+  .map(|(mip, image)| Surface {
+    mip: mip as u32,
+    layer: 0,
+    face: 0,
+    width: image.width,
+    height: image.height,
+    data: bc::encode(
+      config.format,
+      image.width,
+      image.height,
+      &image.data,
+      options.quality,
+      options.weights(),
+    ),
+  })
+  .collect();
+
+  let texture = Texture {
+    format: config.format,
+    texture_type: config.texture_type,
+    width: config.width,
+    height: config.height,
+    depth: 1,
+    mipmap_levels,
+    surfaces,
+  };
+
+  Ok(ImportedTexture {
+    data: tile_texture(&config, &texture)?,
+    header,
+    resize,
+    conversion: conversion_steps(Format::RGBA8, config.format),
+  })
+}
+
+/// Tiles the surfaces of `dds` into the layout `header` describes, like
+/// [`convert_from_dds`](crate::convert_from_dds). A file in another format is
+/// decoded and encoded to the one of the header. Mips the file lacks are generated
+/// from the base level, surplus mips are dropped. Surfaces are decoded once, resized
+/// and generated as RGBA8 and encoded once, mips kept as they are aren't touched.
+pub fn import_dds(
+  dds: &Dds,
+  header: &TextureHeader,
+  options: &ImportOptions,
+) -> Result<ImportedTexture, ImageError> {
+  let metadata = header.metadata();
+  let config = Config::from_metadata(&metadata)
+    .ok_or_else(|| ImageError::UnsupportedFormat(metadata.format()))?;
+
+  let mut texture = Texture::from_dds(dds)?;
+
+  // a DDS file without a DX10 header can't tell arrays of one layer from 2D textures
+  let is_cube = |texture_type| texture_type == TextureType::Cube;
+  if texture.depth != config.depth.unwrap_or(1)
+    || is_cube(texture.texture_type) != is_cube(config.texture_type)
+  {
+    return Err(ImageError::UnsupportedTextureType(texture.texture_type));
+  }
+
+  let (config, header, resize) = fit_size(header, (texture.width, texture.height), options.resize)?;
+
+  let mipmap_levels = config.mipmap_levels.unwrap_or(1);
+  texture.truncate_mips(mipmap_levels);
+
+  // mips that go into the texture as they are, the others are encoded
+  let resized = (texture.width, texture.height) != (config.width, config.height);
+  let kept_levels = match resized || texture.format != config.format {
+    true => 0,
+    false => texture.mipmap_levels,
+  };
+
+  let mut conversion = Vec::new();
+  if kept_levels < mipmap_levels {
+    conversion = reencode_steps(texture.format, config.format);
+
+    // resizing and generating mips only needs the base level
+    let decoded_levels = match resized || kept_levels > 0 {
+      true => 1,
+      false => texture.mipmap_levels,
+    };
+    let mut rgba = decode_texture(&texture, decoded_levels);
+
+    if resized {
+      resample_texture(&mut rgba, config.width, config.height, &metadata, options)?;
+    }
+    if rgba.mipmap_levels < mipmap_levels {
+      generate_missing_mips(&mut rgba, mipmap_levels, &metadata, options)?;
+    }
+
+    texture.surfaces.retain(|surface| surface.mip < kept_levels);
+    for mut surface in rgba.surfaces {
+      if surface.mip >= kept_levels {
+        surface.data = bc::encode(
+          config.format,
+          surface.width,
+          surface.height,
+          &surface.data,
+          options.quality,
+          options.weights(),
+        );
+        texture.surfaces.push(surface);
+      }
+    }
+    texture.surfaces.sort_by_key(|surface| surface.mip);
+
+    texture.format = config.format;
+    texture.width = config.width;
+    texture.height = config.height;
+    texture.mipmap_levels = mipmap_levels;
+  }
+
+  texture.texture_type = config.texture_type;
+  Ok(ImportedTexture {
+    data: tile_texture(&config, &texture)?,
+    header,
+    resize,
+    conversion,
+  })
+}
+
+// The layout to import an image of `size` into, with the header describing it.
+fn fit_size(
+  header: &TextureHeader,
+  size: (u32, u32),
+  mode: ResizeMode,
+) -> Result<(Config, TextureHeader, Option<Resize>), ImageError> {
+  let mut metadata = header.metadata();
+  rebase(&mut metadata);
+  let mut header = header.clone();
+  header.set_metadata(&metadata);
+
+  let config = Config::from_metadata(&metadata)
+    .ok_or_else(|| ImageError::UnsupportedFormat(metadata.format()))?;
+  let expected = (config.width, config.height);
+
+  if size == expected {
+    return Ok((config, header, None));
+  }
+
+  let resize = Resize {
+    mode,
+    image_size: size,
+    texture_size: expected,
+  };
+
+  match mode {
+    ResizeMode::Reject => Err(ImageError::SizeMismatch {
+      expected,
+      found: size,
+    }),
+    ResizeMode::Resample => Ok((config, header, Some(resize))),
+    ResizeMode::Relayout => {
+      // the new size may not fit as many mips
+      let max_levels = 32 - size.0.max(size.1).leading_zeros();
+
+      let layout = LayoutRequest {
+        format: config.format,
+        texture_type: config.texture_type,
+        width: size.0,
+        height: size.1,
+        depth: config.depth.unwrap_or(1),
+        mipmap_levels: Some(config.mipmap_levels.unwrap_or(1).min(max_levels)),
+        tiled: config.tiled,
+        address: 0,
+      }
+      .plan()
+      .map_err(ImageError::Layout)?;
+
+      layout.apply(&mut metadata);
+      header.set_metadata(&metadata);
+      Ok((layout.config, header, Some(resize)))
+    }
+  }
+}
+
+// Moves the data of the texture to address 0, keeping the mips where they are relative
+// to the base level.
+fn rebase(metadata: &mut TextureMetadata) {
+  let base_address = metadata.base_address();
+  let mip_address = metadata.mip_address();
+
+  let start = match mip_address {
+    0 => base_address,
+    _ => base_address.min(mip_address),
+  };
+
+  metadata.set_base_address(base_address - start);
+  if mip_address != 0 {
+    metadata.set_mip_address(mip_address - start);
+  }
+}
+
+// The surfaces of the first `levels` mips decoded to RGBA8, in the channel order of
+// `RgbaImage` rather than the one of DDS files.
+fn decode_texture(texture: &Texture, levels: u32) -> Texture {
+  let surfaces = texture
+    .surfaces
+    .iter()
+    .filter(|surface| surface.mip < levels)
+    .map(|surface| Surface {
+      data: RgbaImage::from_surface(texture.format, surface).data,
+      width: surface.width,
+      height: surface.height,
+      ..*surface
+    })
+    .collect();
+
+  Texture {
+    format: Format::RGBA8,
+    mipmap_levels: texture.mipmap_levels.min(levels),
+    surfaces,
+    ..*texture
+  }
+}
+
+// Scales the base level of every layer and face of a decoded texture, the mips are
+// generated again after.
+fn resample_texture(
+  texture: &mut Texture,
+  width: u32,
+  height: u32,
+  metadata: &TextureMetadata,
+  options: &ImportOptions,
+) -> Result<(), ImageError> {
+  if texture.texture_type == TextureType::Volume {
+    return Err(ImageError::UnsupportedTextureType(texture.texture_type));
+  }
+
+  texture.truncate_mips(1);
+
+  for surface in &mut texture.surfaces {
+    let image = RgbaImage {
+      width: surface.width,
+      height: surface.height,
+      data: std::mem::take(&mut surface.data),
+    };
+    let image = mips::resize(
+      &image,
+      width,
+      height,
+      options.mipmap_filter,
+      gamma(metadata),
+    );
+
+    surface.width = width;
+    surface.height = height;
+    surface.data = image.data;
+  }
+
+  texture.width = width;
+  texture.height = height;
+  Ok(())
+}
+
+// Generates the mips of a decoded texture from `texture.mipmap_levels` on, out of the
+// base level of every layer and face.
+fn generate_missing_mips(
+  texture: &mut Texture,
+  mipmap_levels: u32,
+  metadata: &TextureMetadata,
+  options: &ImportOptions,
+) -> Result<(), ImageError> {
+  // the slices of a volume shrink along with its mips
+  if texture.texture_type == TextureType::Volume {
+    return Err(ImageError::UnsupportedTextureType(texture.texture_type));
+  }
+
+  let bases: Vec<Surface> = texture
+    .surfaces
+    .iter()
+    .filter(|surface| surface.mip == 0)
+    .cloned()
+    .collect();
+
+  for base in bases {
+    let image = RgbaImage {
+      width: base.width,
+      height: base.height,
+      data: base.data,
+    };
+    let mips = generate_mips(
+      &image,
+      mipmap_levels,
+      options.mipmap_filter,
+      gamma(metadata),
+    );
+
+    for (mip, image) in mips
+      .into_iter()
+      .enumerate()
+      .skip(texture.mipmap_levels as usize)
+    {
+      texture.surfaces.push(Surface {
+        mip: mip as u32,
+        layer: base.layer,
+        face: base.face,
+        width: image.width,
+        height: image.height,
+        data: image.data,
+      });
+    }
+  }
+
+  texture.surfaces.sort_by_key(|surface| surface.mip);
+  texture.mipmap_levels = mipmap_levels;
+  Ok(())
+}
+
+// Channels the sampler converts from sRGB.
+fn gamma(metadata: &TextureMetadata) -> [bool; 4] {
+  [
+    metadata.sign_x(),
+    metadata.sign_y(),
+    metadata.sign_z(),
+    metadata.sign_w(),
+  ]
+  .map(|sign| matches!(sign, TextureSign::Gamma))
+}
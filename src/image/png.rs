@@ -0,0 +1,394 @@
+use std::io::{self, Write};
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::inflate::{decompress_to_vec_zlib_with_limit, TINFLStatus};
+
+use crate::image::{ImageError, RgbaImage};
+
+// https://www.w3.org/TR/png/
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+const COLOR_TYPE_RGBA: u8 = 6;
+
+pub(crate) fn write<W: Write>(image: &RgbaImage, writer: &mut W) -> io::Result<()> {
+  writer.write_all(&SIGNATURE)?;
+
+  let mut header = Vec::with_capacity(13);
+  header.extend(image.width.to_be_bytes());
+  header.extend(image.height.to_be_bytes());
+  // bit depth, color type, compression, filter and interlace method
+  header.extend([8, COLOR_TYPE_RGBA, 0, 0, 0]);
+  write_chunk(writer, b"IHDR", &header)?;
+
+  write_chunk(writer, b"IDAT", &compress_to_vec_zlib(&filter(image), 6))?;
+  write_chunk(writer, b"IEND", &[])
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+  writer.write_all(&(data.len() as u32).to_be_bytes())?;
+  writer.write_all(kind)?;
+  writer.write_all(data)?;
+
+  let crc = crc32(crc32(0, kind), data);
+  writer.write_all(&crc.to_be_bytes())
+}
+
+// Every row gets the filter with the smallest sum of absolute differences, the
+// heuristic suggested by the specification.
+fn filter(image: &RgbaImage) -> Vec<u8> {
+  let row_size = image.width as usize * 4;
+  let mut output = Vec::with_capacity((row_size + 1) * image.height as usize);
+  let zero = vec![0; row_size];
+
+  for (y, row) in image.data.chunks_exact(row_size).enumerate() {
+    let above = match y {
+      0 => &zero[..],
+      _ => &image.data[(y - 1) * row_size..y * row_size],
+    };
+
+    let candidates = (0..5).map(|kind| {
+      let filtered: Vec<u8> = (0..row_size)
+        .map(|i| {
+          let left = if i >= 4 { row[i - 4] } else { 0 };
+          let upper_left = if i >= 4 { above[i - 4] } else { 0 };
+          row[i].wrapping_sub(predict(kind, left, above[i], upper_left))
+        })
+        .collect();
+      (kind, filtered)
+    });
+
+    let (kind, filtered) = candidates
+      .min_by_key(|(_, filtered)| {
+        filtered
+          .iter()
+          .map(|&byte| (byte as i8).unsigned_abs() as u32)
+          .sum::<u32>()
+      })
+      .unwrap();
+
+    output.push(kind);
+    output.extend(filtered);
+  }
+
+  output
+}
+
+fn predict(kind: u8, left: u8, above: u8, upper_left: u8) -> u8 {
+  match kind {
+    1 => left,
+    2 => above,
+    3 => ((left as u16 + above as u16) / 2) as u8,
+    4 => paeth(left, above, upper_left),
+    _ => 0,
+  }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+  let p = a as i16 + b as i16 - c as i16;
+  let pa = (p - a as i16).abs();
+  let pb = (p - b as i16).abs();
+  let pc = (p - c as i16).abs();
+
+  if pa <= pb && pa <= pc {
+    a
+  } else if pb <= pc {
+    b
+  } else {
+    c
+  }
+}
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+  const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+      let mut c = i as u32;
+      let mut k = 0;
+      while k < 8 {
+        c = if c & 1 != 0 {
+          0xEDB8_8320 ^ (c >> 1)
+        } else {
+          c >> 1
+        };
+        k += 1;
+      }
+      table[i] = c;
+      i += 1;
+    }
+    table
+  };
+
+  let mut crc = !crc;
+  for &byte in data {
+    crc = TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+  }
+  !crc
+}
+
+const COLOR_TYPE_GRAY: u8 = 0;
+const COLOR_TYPE_RGB: u8 = 2;
+const COLOR_TYPE_PALETTE: u8 = 3;
+const COLOR_TYPE_GRAY_ALPHA: u8 = 4;
+
+struct Header {
+  width: u32,
+  height: u32,
+  bit_depth: u8,
+  color_type: u8,
+}
+
+impl Header {
+  fn channels(&self) -> usize {
+    match self.color_type {
+      COLOR_TYPE_GRAY | COLOR_TYPE_PALETTE => 1,
+      COLOR_TYPE_GRAY_ALPHA => 2,
+      COLOR_TYPE_RGB => 3,
+      _ => 4,
+    }
+  }
+
+  fn bits_per_pixel(&self) -> usize {
+    self.channels() * self.bit_depth as usize
+  }
+
+  // Only valid once `sizes` succeeded.
+  fn row_size(&self) -> usize {
+    (self.width as usize * self.bits_per_pixel()).div_ceil(8)
+  }
+
+  // Bytes of the filtered image data and of the decoded image, `None` if either doesn't
+  // fit in memory.
+  fn sizes(&self) -> Option<(usize, usize)> {
+    let row_size = (self.width as usize)
+      .checked_mul(self.bits_per_pixel())?
+      .div_ceil(8);
+    let filtered = (row_size + 1).checked_mul(self.height as usize)?;
+    let decoded = (self.width as usize)
+      .checked_mul(self.height as usize)?
+      .checked_mul(4)?;
+    Some((filtered, decoded))
+  }
+}
+
+pub(crate) fn is_png(data: &[u8]) -> bool {
+  data.starts_with(&SIGNATURE)
+}
+
+/// Reads a PNG file with any color type and bit depth, without interlacing.
+pub(crate) fn read(data: &[u8]) -> Result<RgbaImage, ImageError> {
+  if !is_png(data) {
+    return Err(ImageError::Malformed("missing PNG signature"));
+  }
+
+  let mut position = SIGNATURE.len();
+  let mut header = None;
+  let mut palette: &[u8] = &[];
+  let mut transparency: &[u8] = &[];
+  let mut compressed = Vec::new();
+
+  loop {
+    let chunk = data
+      .get(position..position + 8)
+      .ok_or(ImageError::Malformed("truncated PNG chunk"))?;
+    let length = u32::from_be_bytes(chunk[..4].try_into().unwrap()) as usize;
+    let kind: [u8; 4] = chunk[4..].try_into().unwrap();
+
+    let body = data
+      .get(position + 8..position + 8 + length)
+      .ok_or(ImageError::Malformed("truncated PNG chunk"))?;
+    let crc = data
+      .get(position + 8 + length..position + 12 + length)
+      .ok_or(ImageError::Malformed("truncated PNG chunk"))?;
+    if u32::from_be_bytes(crc.try_into().unwrap()) != crc32(crc32(0, &kind), body) {
+      return Err(ImageError::Malformed("PNG chunk checksum mismatch"));
+    }
+    position += 12 + length;
+
+    match &kind {
+      b"IHDR" => {
+        if body.len() != 13 {
+          return Err(ImageError::Malformed("invalid PNG header"));
+        }
+        if body[12] != 0 {
+          return Err(ImageError::Unsupported("interlaced PNG"));
+        }
+
+        let parsed = Header {
+          width: u32::from_be_bytes(body[..4].try_into().unwrap()),
+          height: u32::from_be_bytes(body[4..8].try_into().unwrap()),
+          bit_depth: body[8],
+          color_type: body[9],
+        };
+
+        let valid_depth = match parsed.color_type {
+          COLOR_TYPE_GRAY => matches!(parsed.bit_depth, 1 | 2 | 4 | 8 | 16),
+          COLOR_TYPE_PALETTE => matches!(parsed.bit_depth, 1 | 2 | 4 | 8),
+          COLOR_TYPE_RGB | COLOR_TYPE_GRAY_ALPHA | COLOR_TYPE_RGBA => {
+            matches!(parsed.bit_depth, 8 | 16)
+          }
+          _ => false,
+        };
+        if !valid_depth || parsed.width == 0 || parsed.height == 0 {
+          return Err(ImageError::Malformed("invalid PNG header"));
+        }
+
+        header = Some(parsed);
+      }
+      b"PLTE" => palette = body,
+      b"tRNS" => transparency = body,
+      b"IDAT" => compressed.extend_from_slice(body),
+      b"IEND" => break,
+      _ => {}
+    }
+  }
+
+  let header = header.ok_or(ImageError::Malformed("missing PNG header"))?;
+  let (filtered_size, decoded_size) = header
+    .sizes()
+    .ok_or(ImageError::Malformed("PNG image too large"))?;
+
+  // a stream inflating to more than the image holds is cut off rather than buffered
+  let filtered =
+    decompress_to_vec_zlib_with_limit(&compressed, filtered_size).map_err(|error| {
+      match error.status {
+        TINFLStatus::HasMoreOutput => ImageError::Malformed("too much PNG image data"),
+        status => ImageError::Zlib(status),
+      }
+    })?;
+  let raw = unfilter(&header, &filtered, filtered_size)?;
+
+  let mut image = RgbaImage {
+    width: header.width,
+    height: header.height,
+    data: Vec::with_capacity(decoded_size),
+  };
+
+  let row_size = header.row_size();
+  let samples_per_row = header.width as usize * header.channels();
+
+  for row in raw.chunks_exact(row_size) {
+    let samples: Vec<u16> = (0..samples_per_row)
+      .map(|i| sample(row, i, header.bit_depth))
+      .collect();
+
+    for pixel in samples.chunks_exact(header.channels()) {
+      let rgba = match header.color_type {
+        COLOR_TYPE_PALETTE => {
+          let index = pixel[0] as usize;
+          let color = palette
+            .get(index * 3..index * 3 + 3)
+            .ok_or(ImageError::Malformed("PNG palette index out of range"))?;
+          let alpha = transparency.get(index).copied().unwrap_or(255);
+          [color[0], color[1], color[2], alpha]
+        }
+        _ => {
+          let to_u8 = |value: u16| scale(value, header.bit_depth);
+          let keyed = match transparency.len() {
+            2 if header.color_type == COLOR_TYPE_GRAY => {
+              pixel[0] == u16::from_be_bytes([transparency[0], transparency[1]])
+            }
+            6 if header.color_type == COLOR_TYPE_RGB => (0..3).all(|c| {
+              pixel[c] == u16::from_be_bytes([transparency[c * 2], transparency[c * 2 + 1]])
+            }),
+            _ => false,
+          };
+
+          match header.color_type {
+            COLOR_TYPE_GRAY => {
+              let gray = to_u8(pixel[0]);
+              [gray, gray, gray, if keyed { 0 } else { 255 }]
+            }
+            COLOR_TYPE_GRAY_ALPHA => {
+              let gray = to_u8(pixel[0]);
+              [gray, gray, gray, to_u8(pixel[1])]
+            }
+            COLOR_TYPE_RGB => [
+              to_u8(pixel[0]),
+              to_u8(pixel[1]),
+              to_u8(pixel[2]),
+              if keyed { 0 } else { 255 },
+            ],
+            _ => [
+              to_u8(pixel[0]),
+              to_u8(pixel[1]),
+              to_u8(pixel[2]),
+              to_u8(pixel[3]),
+            ],
+          }
+        }
+      };
+
+      image.data.extend(rgba);
+    }
+  }
+
+  Ok(image)
+}
+
+fn unfilter(header: &Header, filtered: &[u8], filtered_size: usize) -> Result<Vec<u8>, ImageError> {
+  let row_size = header.row_size();
+  // filters work on whole bytes, the left neighbour of sub-byte pixels is a byte
+  let bytes_per_pixel = header.bits_per_pixel().div_ceil(8);
+  let height = header.height as usize;
+
+  if filtered.len() < filtered_size {
+    return Err(ImageError::Malformed("truncated PNG image data"));
+  }
+
+  let mut raw = vec![0; row_size * height];
+
+  for y in 0..height {
+    let kind = filtered[y * (row_size + 1)];
+    if kind > 4 {
+      return Err(ImageError::Malformed("invalid PNG filter"));
+    }
+
+    let input = &filtered[y * (row_size + 1) + 1..(y + 1) * (row_size + 1)];
+    let (previous, current) = raw.split_at_mut(y * row_size);
+    let above = match y {
+      0 => None,
+      _ => Some(&previous[(y - 1) * row_size..]),
+    };
+    let current = &mut current[..row_size];
+
+    for i in 0..row_size {
+      let left = if i >= bytes_per_pixel {
+        current[i - bytes_per_pixel]
+      } else {
+        0
+      };
+      let upper = above.map_or(0, |above| above[i]);
+      let upper_left = match above {
+        Some(above) if i >= bytes_per_pixel => above[i - bytes_per_pixel],
+        _ => 0,
+      };
+
+      current[i] = input[i].wrapping_add(predict(kind, left, upper, upper_left));
+    }
+  }
+
+  Ok(raw)
+}
+
+fn sample(row: &[u8], index: usize, bit_depth: u8) -> u16 {
+  match bit_depth {
+    16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+    8 => row[index] as u16,
+    depth => {
+      let depth = depth as usize;
+      let bit = index * depth;
+      let shift = 8 - depth - bit % 8;
+      ((row[bit / 8] >> shift) & ((1 << depth) - 1)) as u16
+    }
+  }
+}
+
+fn scale(value: u16, bit_depth: u8) -> u8 {
+  match bit_depth {
+    16 => (value >> 8) as u8,
+    8 => value as u8,
+    depth => (value as u32 * 255 / ((1 << depth) - 1)) as u8,
+  }
+}
@@ -0,0 +1,141 @@
+use std::io::{self, Write};
+
+use crate::image::{ImageError, RgbaImage};
+
+const IMAGE_TYPE_TRUE_COLOR: u8 = 2;
+const IMAGE_TYPE_GRAY: u8 = 3;
+const IMAGE_TYPE_RLE_TRUE_COLOR: u8 = 10;
+const IMAGE_TYPE_RLE_GRAY: u8 = 11;
+
+// 8 bits of alpha, rows stored from the top
+const DESCRIPTOR_TOP_LEFT: u8 = 0x28;
+
+pub(crate) fn write<W: Write>(image: &RgbaImage, writer: &mut W) -> io::Result<()> {
+  let mut header = [0; 18];
+  header[2] = IMAGE_TYPE_TRUE_COLOR;
+  header[12..14].copy_from_slice(&(image.width as u16).to_le_bytes());
+  header[14..16].copy_from_slice(&(image.height as u16).to_le_bytes());
+  header[16] = 32;
+  header[17] = DESCRIPTOR_TOP_LEFT;
+  writer.write_all(&header)?;
+
+  let pixels: Vec<u8> = image
+    .data
+    .chunks_exact(4)
+    .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+    .collect();
+  writer.write_all(&pixels)
+}
+
+/// Reads an uncompressed or run-length encoded true color or grayscale TGA file.
+pub(crate) fn read(data: &[u8]) -> Result<RgbaImage, ImageError> {
+  let header = data
+    .get(..18)
+    .ok_or(ImageError::Malformed("truncated TGA header"))?;
+
+  let id_length = header[0] as usize;
+  let color_map_type = header[1];
+  let image_type = header[2];
+  let color_map_length = u16::from_le_bytes([header[5], header[6]]) as usize;
+  let color_map_depth = header[7] as usize;
+  let width = u16::from_le_bytes([header[12], header[13]]) as u32;
+  let height = u16::from_le_bytes([header[14], header[15]]) as u32;
+  let depth = header[16];
+  let descriptor = header[17];
+
+  let gray = match image_type {
+    IMAGE_TYPE_TRUE_COLOR | IMAGE_TYPE_RLE_TRUE_COLOR => false,
+    IMAGE_TYPE_GRAY | IMAGE_TYPE_RLE_GRAY => true,
+    _ => return Err(ImageError::Unsupported("TGA image type")),
+  };
+
+  let bytes_per_pixel = match (gray, depth) {
+    (false, 24) => 3,
+    (false, 32) => 4,
+    (true, 8) => 1,
+    _ => return Err(ImageError::Unsupported("TGA pixel depth")),
+  };
+
+  if width == 0 || height == 0 {
+    return Err(ImageError::Malformed("empty TGA image"));
+  }
+
+  // a color map may be present even if the image doesn't use it
+  let mut position = 18 + id_length;
+  if color_map_type != 0 {
+    position += color_map_length * color_map_depth.div_ceil(8);
+  }
+
+  let pixel_count = width as usize * height as usize;
+  let size = pixel_count * bytes_per_pixel;
+
+  let pixels = if image_type == IMAGE_TYPE_RLE_TRUE_COLOR || image_type == IMAGE_TYPE_RLE_GRAY {
+    let mut pixels = Vec::with_capacity(size);
+
+    while pixels.len() < size {
+      let packet = *data
+        .get(position)
+        .ok_or(ImageError::Malformed("truncated TGA image data"))?;
+      let count = (packet & 0x7F) as usize + 1;
+      position += 1;
+
+      if packet & 0x80 != 0 {
+        let pixel = data
+          .get(position..position + bytes_per_pixel)
+          .ok_or(ImageError::Malformed("truncated TGA image data"))?;
+        for _ in 0..count {
+          pixels.extend_from_slice(pixel);
+        }
+        position += bytes_per_pixel;
+      } else {
+        let run = data
+          .get(position..position + count * bytes_per_pixel)
+          .ok_or(ImageError::Malformed("truncated TGA image data"))?;
+        pixels.extend_from_slice(run);
+        position += count * bytes_per_pixel;
+      }
+    }
+
+    pixels.truncate(size);
+    pixels
+  } else {
+    data
+      .get(position..position + size)
+      .ok_or(ImageError::Malformed("truncated TGA image data"))?
+      .to_vec()
+  };
+
+  let rgba: Vec<[u8; 4]> = pixels
+    .chunks_exact(bytes_per_pixel)
+    .map(|pixel| match pixel {
+      [gray] => [*gray, *gray, *gray, 255],
+      [b, g, r] => [*r, *g, *b, 255],
+      [b, g, r, a] => [*r, *g, *b, *a],
+      _ => unreachable!(),
+    })
+    .collect();
+
+  // rows are stored from the bottom unless the descriptor says otherwise
+  let row_size = width as usize;
+  let rows: Vec<&[[u8; 4]]> = if descriptor & 0x20 != 0 {
+    rgba.chunks_exact(row_size).collect()
+  } else {
+    rgba.chunks_exact(row_size).rev().collect()
+  };
+
+  let right_to_left = descriptor & 0x10 != 0;
+  let mut data = Vec::with_capacity(pixel_count * 4);
+  for row in rows {
+    if right_to_left {
+      row.iter().rev().for_each(|pixel| data.extend(pixel));
+    } else {
+      row.iter().for_each(|pixel| data.extend(pixel));
+    }
+  }
+
+  Ok(RgbaImage {
+    width,
+    height,
+    data,
+  })
+}
@@ -0,0 +1,464 @@
+//! KTX2 files holding untiled textures, the counterpart of DDS for toolchains built
+//! around Vulkan formats.
+
+// https://registry.khronos.org/KTX/specs/2.0/ktxspec.v2.html
+// https://registry.khronos.org/DataFormat/specs/1.3/dataformat.1.3.html
+
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use binrw::{BinRead, BinWrite};
+
+use crate::format::{get_format_data, Format, FormatData};
+use crate::layout::TextureType;
+use crate::math::align;
+use crate::texture::{mip_size, mip_slices, new_surface};
+use crate::{Texture, TextureError};
+
+const VK_FORMAT_R8G8B8A8_UNORM: u32 = 37;
+const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+const VK_FORMAT_B8G8R8A8_UNORM: u32 = 44;
+const VK_FORMAT_B8G8R8A8_SRGB: u32 = 50;
+const VK_FORMAT_BC1_RGB_UNORM_BLOCK: u32 = 131;
+const VK_FORMAT_BC1_RGB_SRGB_BLOCK: u32 = 132;
+const VK_FORMAT_BC1_RGBA_UNORM_BLOCK: u32 = 133;
+const VK_FORMAT_BC1_RGBA_SRGB_BLOCK: u32 = 134;
+const VK_FORMAT_BC2_UNORM_BLOCK: u32 = 135;
+const VK_FORMAT_BC2_SRGB_BLOCK: u32 = 136;
+const VK_FORMAT_BC3_UNORM_BLOCK: u32 = 137;
+const VK_FORMAT_BC3_SRGB_BLOCK: u32 = 138;
+
+// mips of a texture at most 2^32 texels wide
+const MAX_LEVELS: u32 = 32;
+
+const KHR_DF_MODEL_RGBSDA: u32 = 1;
+const KHR_DF_MODEL_BC1A: u32 = 128;
+const KHR_DF_MODEL_BC2: u32 = 129;
+const KHR_DF_MODEL_BC3: u32 = 130;
+
+const KHR_DF_PRIMARIES_BT709: u32 = 1;
+const KHR_DF_TRANSFER_LINEAR: u32 = 1;
+const KHR_DF_TRANSFER_SRGB: u32 = 2;
+
+const KHR_DF_CHANNEL_RED: u8 = 0;
+const KHR_DF_CHANNEL_GREEN: u8 = 1;
+const KHR_DF_CHANNEL_BLUE: u8 = 2;
+const KHR_DF_CHANNEL_ALPHA: u8 = 15;
+// color of BC2 and BC3 blocks and the punch-through alpha of BC1
+const KHR_DF_CHANNEL_BC_COLOR: u8 = 0;
+const KHR_DF_CHANNEL_BC1A_ALPHAPRESENT: u8 = 1;
+const KHR_DF_SAMPLE_DATATYPE_LINEAR: u8 = 0x10;
+
+#[derive(BinRead, BinWrite, Debug)]
+#[brw(little, magic = b"\xABKTX 20\xBB\r\n\x1A\n")]
+struct Ktx2Header {
+  vk_format: u32,
+  type_size: u32,
+  pixel_width: u32,
+  pixel_height: u32,
+  /// Zero unless the texture is a volume.
+  pixel_depth: u32,
+  /// Zero unless the texture is an array.
+  layer_count: u32,
+  face_count: u32,
+  /// Zero asks the loader to generate mips from the only level stored.
+  #[br(assert(level_count <= MAX_LEVELS, "{} levels, at most {} fit", level_count, MAX_LEVELS))]
+  level_count: u32,
+  supercompression_scheme: u32,
+  dfd_byte_offset: u32,
+  dfd_byte_length: u32,
+  kvd_byte_offset: u32,
+  kvd_byte_length: u32,
+  sgd_byte_offset: u64,
+  sgd_byte_length: u64,
+  #[br(count = level_count.max(1))]
+  levels: Vec<LevelIndex>,
+}
+
+#[derive(BinRead, BinWrite, Debug)]
+#[brw(little)]
+struct LevelIndex {
+  byte_offset: u64,
+  byte_length: u64,
+  uncompressed_byte_length: u64,
+}
+
+#[derive(Debug)]
+pub enum Ktx2Error {
+  Read(binrw::Error),
+  /// A `vkFormat` without a matching [`Format`].
+  UnsupportedFormat(u32),
+  /// Level data compressed with a supercompression scheme.
+  UnsupportedSupercompression(u32),
+  /// A valid file describing a texture that can't be represented, like a cube map array.
+  UnsupportedTextureType,
+  /// Level data of another size than its format and dimensions ask for.
+  SizeMismatch {
+    level: u32,
+  },
+  /// A file in another format than the texture it's tiled into.
+  FormatMismatch {
+    expected: Format,
+    found: Format,
+  },
+  /// A file of another width, height or depth than the texture it's tiled into.
+  DimensionMismatch {
+    expected: (u32, u32, u32),
+    found: (u32, u32, u32),
+  },
+  /// A file with fewer levels than the texture it's tiled into has mips.
+  MissingMipmapLevels {
+    expected: u32,
+    found: u32,
+  },
+  Texture(TextureError),
+}
+
+impl fmt::Display for Ktx2Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Ktx2Error::Read(error) => write!(f, "{}", error),
+      Ktx2Error::UnsupportedFormat(format) => write!(f, "unsupported vkFormat {}", format),
+      Ktx2Error::UnsupportedSupercompression(scheme) => {
+        write!(f, "unsupported supercompression scheme {}", scheme)
+      }
+      Ktx2Error::UnsupportedTextureType => write!(f, "cube map arrays aren't supported"),
+      Ktx2Error::SizeMismatch { level } => write!(f, "level {} has an invalid size", level),
+      Ktx2Error::FormatMismatch { expected, found } => {
+        write!(
+          f,
+          "file has format {:?}, expected was {:?}",
+          found, expected
+        )
+      }
+      Ktx2Error::DimensionMismatch { expected, found } => write!(
+        f,
+        "file is {}x{}x{}, expected was {}x{}x{}",
+        found.0, found.1, found.2, expected.0, expected.1, expected.2
+      ),
+      Ktx2Error::MissingMipmapLevels { expected, found } => {
+        write!(f, "file has {} levels, expected were {}", found, expected)
+      }
+      Ktx2Error::Texture(error) => write!(f, "{}", error),
+    }
+  }
+}
+
+impl std::error::Error for Ktx2Error {}
+
+impl From<binrw::Error> for Ktx2Error {
+  fn from(error: binrw::Error) -> Self {
+    Ktx2Error::Read(error)
+  }
+}
+
+impl From<TextureError> for Ktx2Error {
+  fn from(error: TextureError) -> Self {
+    Ktx2Error::Texture(error)
+  }
+}
+
+impl From<io::Error> for Ktx2Error {
+  fn from(error: io::Error) -> Self {
+    Ktx2Error::Read(binrw::Error::Io(error))
+  }
+}
+
+/// A texture stored in a KTX2 file.
+#[derive(Debug, Clone)]
+pub struct Ktx2 {
+  pub texture: Texture,
+  /// Whether the format is the sRGB variant, see
+  /// [`TextureMetadata::is_srgb`](crate::TextureMetadata::is_srgb).
+  pub srgb: bool,
+}
+
+impl Ktx2 {
+  /// Reads a KTX2 file in one of the formats of [`Format`], without supercompression.
+  pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, Ktx2Error> {
+    // offsets are relative to the start of the file
+    let start = reader.stream_position()?;
+    let header = Ktx2Header::read(reader)?;
+
+    if header.supercompression_scheme != 0 {
+      return Err(Ktx2Error::UnsupportedSupercompression(
+        header.supercompression_scheme,
+      ));
+    }
+
+    let (format, srgb) = match header.vk_format {
+      VK_FORMAT_BC1_RGB_UNORM_BLOCK | VK_FORMAT_BC1_RGBA_UNORM_BLOCK => (Format::Dxt1, false),
+      VK_FORMAT_BC1_RGB_SRGB_BLOCK | VK_FORMAT_BC1_RGBA_SRGB_BLOCK => (Format::Dxt1, true),
+      VK_FORMAT_BC2_UNORM_BLOCK => (Format::Dxt3, false),
+      VK_FORMAT_BC2_SRGB_BLOCK => (Format::Dxt3, true),
+      VK_FORMAT_BC3_UNORM_BLOCK => (Format::Dxt5, false),
+      VK_FORMAT_BC3_SRGB_BLOCK => (Format::Dxt5, true),
+      VK_FORMAT_B8G8R8A8_UNORM | VK_FORMAT_R8G8B8A8_UNORM => (Format::RGBA8, false),
+      VK_FORMAT_B8G8R8A8_SRGB | VK_FORMAT_R8G8B8A8_SRGB => (Format::RGBA8, true),
+      format => return Err(Ktx2Error::UnsupportedFormat(format)),
+    };
+
+    let (texture_type, depth) = match (header.face_count, header.layer_count) {
+      (6, 0) => (TextureType::Cube, 6),
+      (6, _) => return Err(Ktx2Error::UnsupportedTextureType),
+      _ if header.pixel_depth > 0 => (TextureType::Volume, header.pixel_depth),
+      (_, 0) => (TextureType::TwoD, 1),
+      (_, layers) => (TextureType::Array, layers),
+    };
+
+    let mut texture = Texture {
+      format,
+      texture_type,
+      width: header.pixel_width,
+      // one-dimensional textures have no height
+      height: header.pixel_height.max(1),
+      depth,
+      mipmap_levels: header.levels.len() as u32,
+      surfaces: Vec::new(),
+    };
+
+    let format_data = get_format_data(&format);
+
+    for (mip, level) in header.levels.iter().enumerate() {
+      let mip = mip as u32;
+      let (width, height) = mip_size(texture.width, texture.height, mip);
+      let slices = mip_slices(texture_type, depth, mip);
+      let size_mismatch = Ktx2Error::SizeMismatch { level: mip };
+
+      // the sizes come from the file, so they may overflow or be empty
+      let size = match checked_surface_size(&format_data, width, height) {
+        Some(size) if size > 0 => size,
+        _ => return Err(size_mismatch),
+      };
+      if size.checked_mul(slices as u64) != Some(level.byte_length) {
+        return Err(size_mismatch);
+      }
+
+      let offset = start.checked_add(level.byte_offset).ok_or(size_mismatch)?;
+      reader.seek(SeekFrom::Start(offset))?;
+      let mut data = read_exact(reader, level.byte_length)?;
+
+      // surfaces keep the byte order of A8R8G8B8 DDS files
+      if matches!(
+        header.vk_format,
+        VK_FORMAT_R8G8B8A8_UNORM | VK_FORMAT_R8G8B8A8_SRGB
+      ) {
+        data.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+      }
+
+      for (slice, data) in data.chunks_exact(size as usize).enumerate() {
+        texture.surfaces.push(new_surface(
+          texture_type,
+          mip,
+          slice as u32,
+          width,
+          height,
+          data.to_vec(),
+        ));
+      }
+    }
+
+    Ok(Self { texture, srgb })
+  }
+
+  /// Writes every surface, the levels from the smallest to the largest as the
+  /// specification recommends.
+  pub fn write<W: Write + Seek>(&self, writer: &mut W) -> Result<(), Ktx2Error> {
+    let texture = &self.texture;
+    let format_data = get_format_data(&texture.format);
+
+    let dfd = data_format_descriptor(texture.format, self.srgb);
+    let kvd = key_value_data();
+
+    let levels: Vec<Vec<u8>> = (0..texture.mipmap_levels)
+      .map(|mip| {
+        let mut surfaces: Vec<_> = texture
+          .surfaces
+          .iter()
+          .filter(|surface| surface.mip == mip)
+          .collect();
+        // layers, then faces, then slices of a volume, which are stored as layers
+        surfaces.sort_by_key(|surface| (surface.layer, surface.face));
+        surfaces
+          .iter()
+          .flat_map(|surface| surface.data.clone())
+          .collect()
+      })
+      .collect();
+
+    let header_size = 80 + 24 * texture.mipmap_levels;
+    let dfd_offset = header_size;
+    let kvd_offset = dfd_offset + dfd.len() as u32;
+
+    // levels start on a multiple of the block size and of 4 bytes, the block size
+    // already is for every supported format
+    let level_alignment = format_data.bytes_per_block;
+
+    let mut index = Vec::new();
+    let mut offset = kvd_offset + kvd.len() as u32;
+    for level in levels.iter().rev() {
+      offset = align(offset, level_alignment);
+      index.push(LevelIndex {
+        byte_offset: offset as u64,
+        byte_length: level.len() as u64,
+        uncompressed_byte_length: level.len() as u64,
+      });
+      offset += level.len() as u32;
+    }
+    index.reverse();
+
+    let header = Ktx2Header {
+      vk_format: vk_format(texture.format, self.srgb),
+      type_size: 1,
+      pixel_width: texture.width,
+      pixel_height: texture.height,
+      pixel_depth: match texture.texture_type {
+        TextureType::Volume => texture.depth,
+        _ => 0,
+      },
+      layer_count: match texture.texture_type {
+        TextureType::Array => texture.depth,
+        _ => 0,
+      },
+      face_count: match texture.texture_type {
+        TextureType::Cube => 6,
+        _ => 1,
+      },
+      level_count: texture.mipmap_levels,
+      supercompression_scheme: 0,
+      dfd_byte_offset: dfd_offset,
+      dfd_byte_length: dfd.len() as u32,
+      kvd_byte_offset: kvd_offset,
+      kvd_byte_length: kvd.len() as u32,
+      sgd_byte_offset: 0,
+      sgd_byte_length: 0,
+      levels: index,
+    };
+
+    let start = writer.stream_position()?;
+    header.write(writer)?;
+    writer.write_all(&dfd)?;
+    writer.write_all(&kvd)?;
+
+    for (level, index) in levels.iter().zip(&header.levels).rev() {
+      let position = writer.stream_position()? - start;
+      writer.write_all(&vec![0; (index.byte_offset - position) as usize])?;
+      writer.write_all(level)?;
+    }
+
+    Ok(())
+  }
+}
+
+// Bytes of a surface like `surface_size`, `None` if that doesn't fit a u64.
+fn checked_surface_size(format: &FormatData, width: u32, height: u32) -> Option<u64> {
+  let blocks_x = width.div_ceil(format.block_width) as u64;
+  let blocks_y = height.div_ceil(format.block_height) as u64;
+  blocks_x
+    .checked_mul(blocks_y)?
+    .checked_mul(format.bytes_per_block as u64)
+}
+
+// Reads `size` bytes without allocating them up front, the file may be shorter.
+fn read_exact<R: Read>(reader: &mut R, size: u64) -> io::Result<Vec<u8>> {
+  let mut data = Vec::new();
+  reader.take(size).read_to_end(&mut data)?;
+
+  if data.len() as u64 != size {
+    return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+  }
+
+  Ok(data)
+}
+
+fn vk_format(format: Format, srgb: bool) -> u32 {
+  // DXT1 may use its punch-through alpha, so always the RGBA variant
+  match (format, srgb) {
+    (Format::Dxt1, false) => VK_FORMAT_BC1_RGBA_UNORM_BLOCK,
+    (Format::Dxt1, true) => VK_FORMAT_BC1_RGBA_SRGB_BLOCK,
+    (Format::Dxt3, false) => VK_FORMAT_BC2_UNORM_BLOCK,
+    (Format::Dxt3, true) => VK_FORMAT_BC2_SRGB_BLOCK,
+    (Format::Dxt5, false) => VK_FORMAT_BC3_UNORM_BLOCK,
+    (Format::Dxt5, true) => VK_FORMAT_BC3_SRGB_BLOCK,
+    // surfaces are stored like A8R8G8B8, blue in the first byte
+    (Format::RGBA8, false) => VK_FORMAT_B8G8R8A8_UNORM,
+    (Format::RGBA8, true) => VK_FORMAT_B8G8R8A8_SRGB,
+  }
+}
+
+// A basic data format descriptor block, as `vk2dfd` of the KTX tools builds it.
+fn data_format_descriptor(format: Format, srgb: bool) -> Vec<u8> {
+  // channel, bit offset and bit length of every sample, and its upper value
+  let (model, samples): (u32, &[(u8, u32, u32, u32)]) = match format {
+    Format::Dxt1 => (
+      KHR_DF_MODEL_BC1A,
+      &[(KHR_DF_CHANNEL_BC1A_ALPHAPRESENT, 0, 64, u32::MAX)],
+    ),
+    Format::Dxt3 | Format::Dxt5 => (
+      match format {
+        Format::Dxt3 => KHR_DF_MODEL_BC2,
+        _ => KHR_DF_MODEL_BC3,
+      },
+      &[
+        (KHR_DF_CHANNEL_ALPHA, 0, 64, u32::MAX),
+        (KHR_DF_CHANNEL_BC_COLOR, 64, 64, u32::MAX),
+      ],
+    ),
+    Format::RGBA8 => (
+      KHR_DF_MODEL_RGBSDA,
+      &[
+        (KHR_DF_CHANNEL_BLUE, 0, 8, 255),
+        (KHR_DF_CHANNEL_GREEN, 8, 8, 255),
+        (KHR_DF_CHANNEL_RED, 16, 8, 255),
+        (KHR_DF_CHANNEL_ALPHA, 24, 8, 255),
+      ],
+    ),
+  };
+
+  let format_data = get_format_data(&format);
+  let transfer = if srgb {
+    KHR_DF_TRANSFER_SRGB
+  } else {
+    KHR_DF_TRANSFER_LINEAR
+  };
+  let block_size = 24 + 16 * samples.len() as u32;
+
+  let mut words = vec![
+    4 + block_size,
+    // vendor and descriptor type 0, the Khronos basic descriptor
+    0,
+    2 | block_size << 16,
+    model | KHR_DF_PRIMARIES_BT709 << 8 | transfer << 16,
+    (format_data.block_width - 1) | (format_data.block_height - 1) << 8,
+    format_data.bytes_per_block,
+    0,
+  ];
+
+  for &(channel, offset, length, upper) in samples {
+    // alpha is never stored with the sRGB transfer function
+    let qualifiers = match channel {
+      KHR_DF_CHANNEL_ALPHA if srgb => KHR_DF_SAMPLE_DATATYPE_LINEAR,
+      _ => 0,
+    };
+    let channel = (channel | qualifiers) as u32;
+
+    words.extend([offset | (length - 1) << 16 | channel << 24, 0, 0, upper]);
+  }
+
+  words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+fn key_value_data() -> Vec<u8> {
+  let entry = concat!(
+    "KTXwriter\0",
+    env!("CARGO_PKG_NAME"),
+    " ",
+    env!("CARGO_PKG_VERSION"),
+    "\0"
+  );
+
+  let mut data = (entry.len() as u32).to_le_bytes().to_vec();
+  data.extend(entry.as_bytes());
+  data.resize(align(data.len() as u32, 4) as usize, 0);
+  data
+}
@@ -0,0 +1,222 @@
+use std::fmt;
+
+use crate::format::{get_endian, get_format_data, get_texture_format, FormatData};
+use crate::math::align;
+use crate::mip_map::TextureInfo;
+use crate::texture_header::{Dimension, TextureKind, TextureMetadata};
+use crate::{Config, Format};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureType {
+  TwoD,
+  Cube,
+  Volume,
+  Array,
+}
+
+/// Why a texture can't be laid out, or its size can't be stored in a fetch constant.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+  /// A width, height or depth of zero.
+  EmptyDimension,
+  /// An address that isn't 4 KiB aligned.
+  MisalignedAddress(u32),
+  /// No mip levels, or more than the chain down to 1x1 has.
+  MipmapLevels { levels: u32, max: u32 },
+  /// A size exceeding the bits its dimension has in the fetch constant.
+  SizeOutOfRange { width: u32, height: u32, depth: u32 },
+}
+
+impl fmt::Display for LayoutError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LayoutError::EmptyDimension => write!(f, "textures can't have an empty dimension"),
+      LayoutError::MisalignedAddress(address) => {
+        write!(f, "texture address {:#x} isn't 4 KiB aligned", address)
+      }
+      LayoutError::MipmapLevels { levels, max } => {
+        write!(f, "{} mipmap levels requested, at most {} fit", levels, max)
+      }
+      LayoutError::SizeOutOfRange {
+        width,
+        height,
+        depth,
+      } => write!(
+        f,
+        "a size of {}x{}x{} can't be encoded",
+        width, height, depth
+      ),
+    }
+  }
+}
+
+impl std::error::Error for LayoutError {}
+
+#[derive(Debug, Copy, Clone)]
+pub struct LayoutRequest {
+  pub format: Format,
+  pub texture_type: TextureType,
+  pub width: u32,
+  pub height: u32,
+  /// Slices of a volume or layers of an array. Ignored for 2D textures and cube maps.
+  pub depth: u32,
+  /// `None` allocates the full chain down to 1x1.
+  pub mipmap_levels: Option<u32>,
+  pub tiled: bool,
+  /// Byte offset of the allocation, must be 4 KiB aligned.
+  pub address: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextureLayout {
+  pub config: Config,
+  pub metadata: TextureMetadata,
+  /// Bytes reserved at `base_address`.
+  pub base_size: u32,
+  /// Bytes reserved at `mip_address`, zero without mips.
+  pub mip_size: u32,
+}
+
+impl TextureLayout {
+  /// Total bytes of guest memory used by the texture.
+  pub fn size(&self) -> u32 {
+    self.base_size + self.mip_size
+  }
+
+  /// Writes the size, pitch, mip range and addresses of this layout into `metadata`,
+  /// keeping its format and sampler state.
+  pub fn apply(&self, metadata: &mut TextureMetadata) {
+    metadata.set_dimension(self.metadata.dimension());
+    metadata.set_stacked(self.metadata.stacked());
+    metadata.set_texture_size(self.metadata.texture_size());
+    metadata.set_pitch(self.metadata.pitch());
+    metadata.set_tiled(self.metadata.tiled());
+    metadata.set_packed_mips(self.metadata.packed_mips());
+    metadata.set_base_address(self.metadata.base_address());
+    metadata.set_mip_address(self.metadata.mip_address());
+    metadata.set_min_mip_level(self.metadata.min_mip_level());
+    metadata.set_max_mip_level(self.metadata.max_mip_level());
+  }
+}
+
+impl LayoutRequest {
+  pub fn new(format: Format, width: u32, height: u32) -> Self {
+    Self {
+      format,
+      texture_type: TextureType::TwoD,
+      width,
+      height,
+      depth: 1,
+      mipmap_levels: None,
+      tiled: true,
+      address: 0,
+    }
+  }
+
+  /// Lays the texture out the way `XGSetTextureHeader` does: pitch padded to whole tiles,
+  /// a packed mip tail and the mips placed right behind the base level.
+  pub fn plan(&self) -> Result<TextureLayout, LayoutError> {
+    if self.width == 0 || self.height == 0 {
+      return Err(LayoutError::EmptyDimension);
+    }
+
+    if !self.address.is_multiple_of(4096) {
+      return Err(LayoutError::MisalignedAddress(self.address));
+    }
+
+    let depth = match self.texture_type {
+      TextureType::TwoD => 1,
+      TextureType::Cube => 6,
+      TextureType::Volume | TextureType::Array => self.depth.max(1),
+    };
+
+    let max_levels = 32 - self.width.max(self.height).leading_zeros();
+    let mipmap_levels = self.mipmap_levels.unwrap_or(max_levels);
+
+    if mipmap_levels == 0 || mipmap_levels > max_levels {
+      return Err(LayoutError::MipmapLevels {
+        levels: mipmap_levels,
+        max: max_levels,
+      });
+    }
+
+    let format_data = get_format_data(&self.format);
+    let pitch = padded_pitch(&format_data, self.width, self.tiled);
+
+    let mut info = TextureInfo {
+      width: self.width,
+      height: self.height,
+      depth,
+      pitch,
+      tiled: self.tiled,
+      packed_mips: true,
+      format: &format_data,
+      base_address: self.address,
+      mip_address: 0,
+    };
+
+    let base_size = info.get_base_size();
+    let mip_size = info.get_mips_size(mipmap_levels);
+
+    if mip_size != 0 {
+      info.mip_address = self.address + base_size;
+    }
+
+    let config = Config {
+      texture_type: self.texture_type,
+      width: self.width,
+      height: self.height,
+      depth: (depth > 1).then_some(depth),
+      pitch,
+      tiled: self.tiled,
+      packed_mips: info.packed_mips,
+      format: self.format,
+      mipmap_levels: Some(mipmap_levels),
+      base_address: info.base_address >> 12,
+      mip_address: info.mip_address >> 12,
+    };
+
+    let dimension = match self.texture_type {
+      TextureType::TwoD | TextureType::Array => Dimension::TwoDOrStacked,
+      TextureType::Cube => Dimension::CubeMap,
+      TextureType::Volume => Dimension::ThreeD,
+    };
+
+    let mut metadata = TextureMetadata::new()
+      .with_kind(TextureKind::Texture)
+      .with_dimension(dimension)
+      .with_format(get_texture_format(&self.format))
+      .with_endianness(get_endian(&self.format))
+      .with_tiled(self.tiled)
+      .with_stacked(self.texture_type == TextureType::Array)
+      .with_pitch((pitch >> 5) as u16)
+      .with_packed_mips(info.packed_mips)
+      .with_base_address(config.base_address)
+      .with_mip_address(config.mip_address)
+      .with_min_mip_level(0)
+      .with_max_mip_level((mipmap_levels - 1) as u8);
+
+    metadata.set_size(self.width, self.height, depth)?;
+
+    Ok(TextureLayout {
+      config,
+      metadata,
+      base_size,
+      mip_size,
+    })
+  }
+}
+
+/// Pitch in texels of rows `width` texels wide. Rows are stored in whole tiles of 32x32
+/// blocks, linear rows additionally need to cover a multiple of 256 bytes.
+pub(crate) fn padded_pitch(format_data: &FormatData, width: u32, tiled: bool) -> u32 {
+  let mut pitch_blocks = align(
+    align(width, format_data.block_width) / format_data.block_width,
+    32,
+  );
+  if !tiled {
+    pitch_blocks =
+      align(pitch_blocks * format_data.bytes_per_block, 256) / format_data.bytes_per_block;
+  }
+  pitch_blocks * format_data.block_width
+}
@@ -1,25 +1,66 @@
+#[cfg(feature = "ktx2")]
+use std::io::Seek;
 use std::io::{Read, Write};
 
-use dds::{D3DFormat, Dds};
+use dds::Dds;
 
 pub use crate::format::Format;
-use crate::format::{get_dds_format, get_format_data, FormatData};
+use crate::format::{
+  get_format_data, get_format_from_dds_file, get_format_from_texture, FormatData,
+};
+#[cfg(feature = "ktx2")]
+pub use crate::ktx2::{Ktx2, Ktx2Error};
+use crate::layout::padded_pitch;
+pub use crate::layout::{LayoutError, LayoutRequest, TextureLayout, TextureType};
 use crate::mip_map::TextureInfo;
+pub use crate::resource::ResourceHeader;
+pub use crate::sampler::{
+  D3D11SamplerDesc, Filter, GltfSampler, SamplerDesc, SamplerIssue, WrapMode,
+};
+pub use crate::scan::{scan, ScanMatch, ScanOptions};
+use crate::texture::dds_texture_type;
+pub use crate::texture::{tile_texture, untile_texture, Surface, Texture, TextureError};
 pub use crate::texture_header::*;
-use crate::tile::{tile, untile};
+pub use crate::validate::{Severity, ValidationIssue};
+pub use crate::vertex::{VertexBufferHeader, VertexFetchConstant};
+pub use crate::xpr2::{Xpr2, Xpr2Error, Xpr2Resource, Xpr2Type};
 
 mod format;
+#[cfg(feature = "image")]
+pub mod image;
+#[cfg(feature = "ktx2")]
+mod ktx2;
+mod layout;
+#[cfg(feature = "lzx")]
+pub mod lzx;
 mod math;
 mod mip_map;
+mod report;
+mod resource;
+mod sampler;
+mod scan;
+#[cfg(feature = "serde")]
+mod serialize;
 #[cfg(test)]
 mod tests;
+mod texture;
 mod texture_header;
 mod tile;
-
+#[cfg(feature = "ue3")]
+pub mod ue3;
+mod validate;
+mod vertex;
+mod xpr2;
+
+/// Where and how a texture is stored in guest memory. Prefer [`Config::new`] or
+/// [`Config::from_metadata`] over a struct literal, fields like `texture_type` may be
+/// added over time.
 #[derive(Debug, Copy, Clone)]
 pub struct Config {
+  pub texture_type: TextureType,
   pub width: u32,
   pub height: u32,
+  /// Slices of a volume, layers of an array or the faces of a cube map.
   pub depth: Option<u32>,
   pub pitch: u32,
   pub tiled: bool,
@@ -30,119 +71,154 @@ pub struct Config {
   pub mip_address: u32,
 }
 
-pub fn convert_to_dds<W: Write>(
-  config: &Config,
-  src: &[u8],
-  output: &mut W,
-) -> Result<(), dds::Error> {
-  let mut dds = Dds::new_d3d(
-    get_dds_format(&config.format),
-    config.width,
-    config.height,
-    config.depth,
-    config.mipmap_levels,
-    None,
-  )?;
-
-  let format_data = get_format_data(&config.format);
-  let info = build_texture_info(config, &format_data);
-
-  let mut output_offset = 0;
-
-  for mip in 0..dds.get_num_mipmap_levels() {
-    let mut offset_x = 0;
-    let mut offset_y = 0;
-
-    let input_offset = info.get_mip_location(mip, &mut offset_x, &mut offset_y, true) as usize;
-
-    let (width, height) = info.get_mip_size(mip);
-    let blocks_x = width / format_data.block_width;
-    let blocks_y = height / format_data.block_height;
-
-    let input = &src[input_offset..];
-    let output = &mut dds.data[output_offset..];
-
-    output_offset += untile(
-      output,
-      input,
-      &format_data,
-      blocks_x,
-      blocks_y,
-      offset_x,
-      offset_y,
-    ) as usize;
+impl Config {
+  /// A tiled 2D texture without mips at address 0, its pitch padded to whole tiles.
+  pub fn new(format: Format, width: u32, height: u32) -> Config {
+    Config {
+      texture_type: TextureType::TwoD,
+      width,
+      height,
+      depth: None,
+      pitch: padded_pitch(&get_format_data(&format), width, true),
+      tiled: true,
+      packed_mips: true,
+      format,
+      mipmap_levels: None,
+      base_address: 0,
+      mip_address: 0,
+    }
   }
 
-  // check if the expected length has been written
-  if output_offset != dds.data.len() {
-    panic!(
-      "There were only {} bytes of {} expected written.",
-      output_offset,
-      dds.data.len()
-    );
+  /// The settings described by a fetch constant, `None` if its format isn't supported.
+  pub fn from_metadata(metadata: &TextureMetadata) -> Option<Config> {
+    let format = get_format_from_texture(&metadata.format())?;
+    let (width, height, depth) = metadata.size();
+
+    let texture_type = match metadata.dimension() {
+      Dimension::CubeMap => TextureType::Cube,
+      Dimension::ThreeD => TextureType::Volume,
+      _ if metadata.stacked() => TextureType::Array,
+      _ => TextureType::TwoD,
+    };
+
+    Some(Config {
+      texture_type,
+      width,
+      height,
+      depth: (depth > 1).then_some(depth),
+      pitch: (metadata.pitch() as u32) << 5,
+      tiled: metadata.tiled(),
+      packed_mips: metadata.packed_mips(),
+      format,
+      mipmap_levels: Some(metadata.max_mip_level() as u32 + 1),
+      base_address: metadata.base_address(),
+      mip_address: metadata.mip_address(),
+    })
   }
+}
 
-  dds.write(output)
+/// Untiles the texture described by `config` and writes it to a DDS file, see
+/// [`untile_texture`].
+pub fn convert_to_dds<W: Write>(
+  config: &Config,
+  src: &[u8],
+  output: &mut W,
+) -> Result<(), TextureError> {
+  Ok(untile_texture(config, src)?.to_dds()?.write(output)?)
 }
 
+/// Tiles a DDS file, with or without a DX10 header, into the layout described by
+/// `config`. Mips beyond the ones `config` asks for are dropped, fewer mips, another
+/// size or another format are an error. `image::import_dds` can generate missing mips
+/// and handle a different size or format. [`convert_from_dds_checked`] also checks
+/// sRGB and SNORM variants against the signs of a header.
 pub fn convert_from_dds<R: Read, W: Write>(
   config: &Config,
   src: &mut R,
-) -> Result<Vec<u8>, dds::Error> {
+) -> Result<Vec<u8>, TextureError> {
+  tile_dds(config, &Dds::read(src)?)
+}
+
+/// Tiles a DDS file into the texture `metadata` describes, like [`convert_from_dds`].
+/// Fails with every issue [`TextureMetadata::validate_dds`] reports, including an sRGB
+/// or SNORM variant that the signs of the header disagree with.
+pub fn convert_from_dds_checked<R: Read>(
+  metadata: &TextureMetadata,
+  src: &mut R,
+) -> Result<Vec<u8>, TextureError> {
   let dds = Dds::read(src)?;
+  let issues = metadata.validate_dds(&dds);
 
+  match Config::from_metadata(metadata) {
+    Some(config) if issues.is_empty() => tile_dds(&config, &dds),
+    _ => Err(TextureError::Validation(issues)),
+  }
+}
+
+fn tile_dds(config: &Config, dds: &Dds) -> Result<Vec<u8>, TextureError> {
   let format_data = get_format_data(&config.format);
+  let mipmap_levels = config.mipmap_levels.unwrap_or(1);
 
   let info = build_texture_info(config, &format_data);
-  validate_provided_dds(&dds, &info, config.mipmap_levels.unwrap_or(1));
-
-  let mut expected_size = 0;
+  validate_provided_dds(dds, &info, config.format, mipmap_levels)?;
 
-  for mip in 0..dds.get_num_mipmap_levels() {
-    let (width, height) = info.get_mip_size(mip);
-
-    let blocks_x = width / format_data.block_width;
-    let blocks_y = height / format_data.block_height;
+  let mut texture = Texture::from_dds(dds)?;
+  texture.truncate_mips(mipmap_levels);
+  tile_texture(config, &texture)
+}
 
-    expected_size += (blocks_x * blocks_y * format_data.bytes_per_block) as usize;
+/// Untiles the texture `metadata` describes and writes it to a KTX2 file, with the
+/// sRGB variant of its format if [`TextureMetadata::is_srgb`] is set. Fails with the
+/// issues of `metadata` if its format can't be untiled.
+#[cfg(feature = "ktx2")]
+pub fn convert_to_ktx2<W: Write + Seek>(
+  metadata: &TextureMetadata,
+  src: &[u8],
+  output: &mut W,
+) -> Result<(), Ktx2Error> {
+  let config =
+    Config::from_metadata(metadata).ok_or_else(|| TextureError::Validation(metadata.validate()))?;
+  let texture = untile_texture(&config, src)?;
+
+  Ktx2 {
+    texture,
+    srgb: metadata.is_srgb(),
   }
+  .write(output)
+}
 
-  let mut output = vec![0; expected_size];
-  let mut input_offset = 0;
-
-  for mip in 0..dds.get_num_mipmap_levels() {
-    let mut offset_x = 0;
-    let mut offset_y = 0;
-
-    let output_offset = info.get_mip_location(mip, &mut offset_x, &mut offset_y, true) as usize;
-
-    let (width, height) = info.get_mip_size(mip);
-    let blocks_x = width / format_data.block_width;
-    let blocks_y = height / format_data.block_height;
-
-    let input = &dds.data[input_offset..];
-    let output = &mut output[output_offset..];
+/// Tiles a KTX2 file into the layout described by `config`, like [`convert_from_dds`].
+/// Fails if the format or size differs from `config` or mips are missing.
+#[cfg(feature = "ktx2")]
+pub fn convert_from_ktx2<R: Read + Seek>(
+  config: &Config,
+  src: &mut R,
+) -> Result<Vec<u8>, Ktx2Error> {
+  let mut texture = Ktx2::read(src)?.texture;
+
+  if texture.format != config.format {
+    return Err(Ktx2Error::FormatMismatch {
+      expected: config.format,
+      found: texture.format,
+    });
+  }
 
-    input_offset += tile(
-      output,
-      input,
-      &format_data,
-      blocks_x,
-      blocks_y,
-      offset_x,
-      offset_y,
-    ) as usize;
+  let found = (texture.width, texture.height, texture.depth);
+  let expected = (config.width, config.height, config.depth.unwrap_or(1));
+  if found != expected {
+    return Err(Ktx2Error::DimensionMismatch { expected, found });
   }
 
-  if input_offset != dds.data.len() {
-    panic!(
-      "There were only {} bytes of {} expected read...",
-      input_offset,
-      dds.data.len()
-    );
+  let mipmap_levels = config.mipmap_levels.unwrap_or(1);
+  if texture.mipmap_levels < mipmap_levels {
+    return Err(Ktx2Error::MissingMipmapLevels {
+      expected: mipmap_levels,
+      found: texture.mipmap_levels,
+    });
   }
 
-  Ok(output)
+  texture.truncate_mips(mipmap_levels);
+  Ok(tile_texture(config, &texture)?)
 }
 
 fn build_texture_info<'a>(config: &Config, format_data: &'a FormatData) -> TextureInfo<'a> {
@@ -159,35 +235,37 @@ fn build_texture_info<'a>(config: &Config, format_data: &'a FormatData) -> Textu
   }
 }
 
-fn validate_provided_dds(dds: &Dds, info: &TextureInfo, mipmap_levels: u32) {
-  match dds.get_d3d_format() {
-    Some(D3DFormat::DXT1) => {}
-    Some(D3DFormat::DXT5) => {}
-    _ => panic!("Unsupported image data format."),
+fn validate_provided_dds(
+  dds: &Dds,
+  info: &TextureInfo,
+  format: Format,
+  mipmap_levels: u32,
+) -> Result<(), TextureError> {
+  let provided = get_format_from_dds_file(dds)
+    .ok_or(TextureError::UnsupportedDdsFormat)?
+    .format;
+
+  if provided != format {
+    return Err(TextureError::FormatMismatch {
+      expected: format,
+      found: provided,
+    });
   }
 
-  if dds.get_width() != info.width {
-    panic!(
-      "Provided dds has invalid width, expected was {}.",
-      info.width
-    );
+  // layers of an array or faces of a cube map count as well
+  let found = (dds.get_width(), dds.get_height(), dds_texture_type(dds)?.1);
+  let expected = (info.width, info.height, info.depth);
+  if found != expected {
+    return Err(TextureError::SizeMismatch { expected, found });
   }
 
-  if dds.get_height() != info.height {
-    panic!(
-      "Provided dds has invalid height, expected was {}.",
-      info.height
-    );
+  let found = dds.get_num_mipmap_levels();
+  if found < mipmap_levels {
+    return Err(TextureError::MissingMipmapLevels {
+      expected: mipmap_levels,
+      found,
+    });
   }
 
-  if dds.get_depth() != info.depth {
-    panic!(
-      "Provided dds has invalid depth, expected was {}.",
-      info.depth
-    );
-  }
-
-  if dds.get_num_mipmap_levels() != mipmap_levels {
-    panic!("Provided dds has invalid mipmap level count, expected was {mipmap_levels}.");
-  }
+  Ok(())
 }
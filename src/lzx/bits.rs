@@ -0,0 +1,135 @@
+use crate::lzx::LzxError;
+
+/// Reads LZX bits, which come in 16 bit little-endian words, most significant bit first.
+pub(crate) struct BitReader<'a> {
+  input: &'a [u8],
+  position: usize,
+  // the valid bits are the topmost `count`
+  buffer: u64,
+  count: u32,
+}
+
+impl<'a> BitReader<'a> {
+  pub(crate) fn new(input: &'a [u8]) -> Self {
+    Self {
+      input,
+      position: 0,
+      buffer: 0,
+      count: 0,
+    }
+  }
+
+  pub(crate) fn ensure(&mut self, bits: u32) {
+    while self.count < bits {
+      // past the end the stream reads as zeroes, decoding stops at the frame size
+      let low = self.input.get(self.position).copied().unwrap_or(0);
+      let high = self.input.get(self.position + 1).copied().unwrap_or(0);
+      self.position += 2;
+
+      let word = u16::from_le_bytes([low, high]) as u64;
+      self.buffer |= word << (48 - self.count);
+      self.count += 16;
+    }
+  }
+
+  pub(crate) fn peek(&self, bits: u32) -> u32 {
+    (self.buffer >> (64 - bits)) as u32
+  }
+
+  pub(crate) fn remove(&mut self, bits: u32) {
+    self.buffer <<= bits;
+    self.count -= bits;
+  }
+
+  pub(crate) fn read(&mut self, bits: u32) -> u32 {
+    if bits == 0 {
+      return 0;
+    }
+
+    self.ensure(bits);
+    let value = self.peek(bits);
+    self.remove(bits);
+    value
+  }
+
+  /// Skips to the next 16 bit boundary for raw bytes, a whole word if already there.
+  pub(crate) fn align(&mut self) {
+    if self.count == 0 {
+      self.ensure(16);
+    }
+
+    self.buffer = 0;
+    self.count = 0;
+  }
+
+  pub(crate) fn read_bytes(&mut self, output: &mut [u8]) -> Result<(), LzxError> {
+    let end = self.position + output.len();
+    let input = self
+      .input
+      .get(self.position..end)
+      .ok_or(LzxError::UnexpectedEnd)?;
+
+    output.copy_from_slice(input);
+    self.position = end;
+    Ok(())
+  }
+
+  pub(crate) fn skip_byte(&mut self) {
+    self.position += 1;
+  }
+
+  /// Whether more was consumed than the input holds, bits read ahead don't count.
+  pub(crate) fn is_past_end(&self) -> bool {
+    self.position * 8 - self.count as usize > self.input.len() * 8
+  }
+}
+
+/// Writes bits the way [`BitReader`] expects them.
+pub(crate) struct BitWriter<'a> {
+  output: &'a mut Vec<u8>,
+  // the pending bits are the lowest `count`
+  buffer: u64,
+  count: u32,
+}
+
+impl<'a> BitWriter<'a> {
+  pub(crate) fn new(output: &'a mut Vec<u8>) -> Self {
+    Self {
+      output,
+      buffer: 0,
+      count: 0,
+    }
+  }
+
+  pub(crate) fn write(&mut self, value: u32, bits: u32) {
+    self.buffer = (self.buffer << bits) | (value as u64 & ((1 << bits) - 1));
+    self.count += bits;
+
+    while self.count >= 16 {
+      self.count -= 16;
+      let word = (self.buffer >> self.count) as u16;
+      self.output.extend(word.to_le_bytes());
+    }
+  }
+
+  /// Pads with zeroes to the next 16 bit boundary.
+  pub(crate) fn flush(&mut self) {
+    if self.count > 0 {
+      self.write(0, 16 - self.count);
+    }
+  }
+
+  /// Moves to the start of raw bytes, the counterpart of [`BitReader::align`].
+  pub(crate) fn align(&mut self) {
+    if self.count == 0 {
+      self.write(0, 16);
+    } else {
+      self.flush();
+    }
+  }
+
+  pub(crate) fn write_bytes(&mut self, bytes: &[u8]) {
+    debug_assert_eq!(self.count, 0);
+    self.output.extend(bytes);
+  }
+}
@@ -0,0 +1,331 @@
+use crate::lzx::bits::BitReader;
+use crate::lzx::huffman::HuffmanDecoder;
+use crate::lzx::{
+  position_slots, LzxError, ALIGNED_NUM_ELEMENTS, BLOCKTYPE_ALIGNED, BLOCKTYPE_UNCOMPRESSED,
+  BLOCKTYPE_VERBATIM, EXTRA_BITS, MIN_MATCH, NUM_CHARS, NUM_PRIMARY_LENGTHS, NUM_SECONDARY_LENGTHS,
+  POSITION_BASE, PRETREE_NUM_ELEMENTS,
+};
+
+/// Decodes raw LZX one frame at a time. The window, repeated offsets and code lengths
+/// carry over from frame to frame until [`LzxDecoder::reset`].
+pub struct LzxDecoder {
+  window: Vec<u8>,
+  window_bits: u32,
+  // bytes decoded into the window and handed out so far
+  decoded: u64,
+  emitted: u64,
+  repeated: [u32; 3],
+  main_lengths: Vec<u8>,
+  length_lengths: [u8; NUM_SECONDARY_LENGTHS],
+  main: HuffmanDecoder,
+  length: HuffmanDecoder,
+  aligned: HuffmanDecoder,
+  block_type: u32,
+  block_length: usize,
+  block_remaining: usize,
+  header_read: bool,
+  intel_file_size: i32,
+  intel_started: bool,
+  frames: u32,
+}
+
+impl LzxDecoder {
+  pub fn new(window_bits: u32) -> Result<Self, LzxError> {
+    let slots = position_slots(window_bits).ok_or(LzxError::InvalidWindowSize(window_bits))?;
+
+    Ok(Self {
+      window: vec![0; 1 << window_bits],
+      window_bits,
+      decoded: 0,
+      emitted: 0,
+      repeated: [1; 3],
+      main_lengths: vec![0; NUM_CHARS + slots * 8],
+      length_lengths: [0; NUM_SECONDARY_LENGTHS],
+      main: HuffmanDecoder::default(),
+      length: HuffmanDecoder::default(),
+      aligned: HuffmanDecoder::default(),
+      block_type: 0,
+      block_length: 0,
+      block_remaining: 0,
+      header_read: false,
+      intel_file_size: 0,
+      intel_started: false,
+      frames: 0,
+    })
+  }
+
+  /// Forgets everything decoded so far, as at the start of an independent stream.
+  pub fn reset(&mut self) {
+    *self = Self::new(self.window_bits).unwrap();
+  }
+
+  /// Decodes the chunk `input` into `frame_size` bytes appended to `output`.
+  pub fn decompress_frame(
+    &mut self,
+    input: &[u8],
+    frame_size: usize,
+    output: &mut Vec<u8>,
+  ) -> Result<(), LzxError> {
+    let mut bits = BitReader::new(input);
+
+    if !self.header_read {
+      if bits.read(1) == 1 {
+        let high = bits.read(16);
+        let low = bits.read(16);
+        self.intel_file_size = ((high << 16) | low) as i32;
+      }
+
+      self.header_read = true;
+    }
+
+    let target = self.emitted + frame_size as u64;
+
+    while self.decoded < target {
+      if self.block_remaining == 0 {
+        self.read_block_header(&mut bits)?;
+      }
+
+      let run = self.block_remaining.min((target - self.decoded) as usize);
+
+      match self.block_type {
+        BLOCKTYPE_UNCOMPRESSED => {
+          let mut bytes = vec![0; run];
+          bits.read_bytes(&mut bytes)?;
+
+          for byte in bytes {
+            self.push(byte);
+          }
+
+          self.block_remaining -= run;
+
+          // odd sized blocks are padded to keep the bitstream 16 bit aligned
+          if self.block_remaining == 0 && self.block_length & 1 == 1 {
+            bits.skip_byte();
+          }
+        }
+        _ => self.decode_run(&mut bits, run)?,
+      }
+
+      if bits.is_past_end() {
+        return Err(LzxError::UnexpectedEnd);
+      }
+    }
+
+    let start = output.len();
+    let mask = self.window.len() as u64 - 1;
+    output.extend((self.emitted..target).map(|position| self.window[(position & mask) as usize]));
+    self.emitted = target;
+
+    self.undo_intel_translation(&mut output[start..]);
+    self.frames += 1;
+
+    Ok(())
+  }
+
+  fn read_block_header(&mut self, bits: &mut BitReader) -> Result<(), LzxError> {
+    self.block_type = bits.read(3);
+    let high = bits.read(16);
+    let low = bits.read(8);
+    self.block_remaining = ((high << 8) | low) as usize;
+    self.block_length = self.block_remaining;
+
+    match self.block_type {
+      BLOCKTYPE_ALIGNED | BLOCKTYPE_VERBATIM => {
+        if self.block_type == BLOCKTYPE_ALIGNED {
+          let mut lengths = [0; ALIGNED_NUM_ELEMENTS];
+          for length in &mut lengths {
+            *length = bits.read(3) as u8;
+          }
+          self.aligned = HuffmanDecoder::new(&lengths);
+        }
+
+        read_lengths(bits, &mut self.main_lengths[..NUM_CHARS])?;
+        read_lengths(bits, &mut self.main_lengths[NUM_CHARS..])?;
+        self.main = HuffmanDecoder::new(&self.main_lengths);
+
+        if self.main_lengths[0xE8] != 0 {
+          self.intel_started = true;
+        }
+
+        read_lengths(bits, &mut self.length_lengths)?;
+        self.length = HuffmanDecoder::new(&self.length_lengths);
+      }
+      BLOCKTYPE_UNCOMPRESSED => {
+        self.intel_started = true;
+        bits.align();
+
+        let mut repeated = [0; 12];
+        bits.read_bytes(&mut repeated)?;
+
+        for (offset, bytes) in self.repeated.iter_mut().zip(repeated.chunks_exact(4)) {
+          *offset = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+      }
+      kind => return Err(LzxError::InvalidBlockType(kind)),
+    }
+
+    Ok(())
+  }
+
+  // Decodes at least `run` bytes of the current block, the last match may reach past it.
+  fn decode_run(&mut self, bits: &mut BitReader, run: usize) -> Result<(), LzxError> {
+    let end = self.decoded + run as u64;
+
+    while self.decoded < end {
+      let element = self.main.decode(bits)? as usize;
+
+      if element < NUM_CHARS {
+        self.push(element as u8);
+        self.block_remaining -= 1;
+        continue;
+      }
+
+      let element = element - NUM_CHARS;
+
+      let mut match_length = element & 7;
+      if match_length == NUM_PRIMARY_LENGTHS {
+        match_length += self.length.decode(bits)? as usize;
+      }
+      match_length += MIN_MATCH;
+
+      let slot = element >> 3;
+      let match_offset = match slot {
+        0 => self.repeated[0],
+        1 => {
+          self.repeated.swap(0, 1);
+          self.repeated[0]
+        }
+        2 => {
+          self.repeated.swap(0, 2);
+          self.repeated[0]
+        }
+        _ => {
+          let offset = self.read_offset(bits, slot)?;
+          self.repeated = [offset, self.repeated[0], self.repeated[1]];
+          offset
+        }
+      };
+
+      if match_length > self.block_remaining || match_offset as u64 > self.decoded {
+        return Err(LzxError::InvalidMatch);
+      }
+
+      let mask = self.window.len() - 1;
+      let mut from = (self.decoded as usize).wrapping_sub(match_offset as usize) & mask;
+
+      for _ in 0..match_length {
+        self.push(self.window[from]);
+        from = (from + 1) & mask;
+      }
+
+      self.block_remaining -= match_length;
+    }
+
+    Ok(())
+  }
+
+  fn read_offset(&self, bits: &mut BitReader, slot: usize) -> Result<u32, LzxError> {
+    if slot == 3 {
+      return Ok(1);
+    }
+
+    let extra = EXTRA_BITS[slot] as u32;
+    let base = POSITION_BASE[slot] - 2;
+
+    if self.block_type != BLOCKTYPE_ALIGNED {
+      return Ok(base + bits.read(extra));
+    }
+
+    // aligned blocks code the lowest three bits of long offsets separately
+    let offset = match extra {
+      0 => 1,
+      1 | 2 => base + bits.read(extra),
+      3 => base + self.aligned.decode(bits)? as u32,
+      _ => {
+        let verbatim = bits.read(extra - 3) << 3;
+        base + verbatim + self.aligned.decode(bits)? as u32
+      }
+    };
+
+    Ok(offset)
+  }
+
+  fn push(&mut self, byte: u8) {
+    let mask = self.window.len() as u64 - 1;
+    self.window[(self.decoded & mask) as usize] = byte;
+    self.decoded += 1;
+  }
+
+  // The compressor may have turned the operands of x86 CALL instructions absolute.
+  fn undo_intel_translation(&self, data: &mut [u8]) {
+    if !self.intel_started || self.intel_file_size == 0 || self.frames >= 32768 || data.len() <= 10
+    {
+      return;
+    }
+
+    let file_size = self.intel_file_size;
+    let mut position = (self.emitted - data.len() as u64) as i32;
+    let mut i = 0;
+
+    while i < data.len() - 10 {
+      if data[i] != 0xE8 {
+        i += 1;
+        position += 1;
+        continue;
+      }
+
+      let absolute = i32::from_le_bytes(data[i + 1..i + 5].try_into().unwrap());
+
+      if absolute >= -position && absolute < file_size {
+        let relative = if absolute >= 0 {
+          absolute - position
+        } else {
+          absolute + file_size
+        };
+        data[i + 1..i + 5].copy_from_slice(&relative.to_le_bytes());
+      }
+
+      i += 5;
+      position += 5;
+    }
+  }
+}
+
+// Code lengths are sent as differences to the previous block's, themselves coded
+// with a 20 symbol pretree.
+fn read_lengths(bits: &mut BitReader, lengths: &mut [u8]) -> Result<(), LzxError> {
+  let mut pretree = [0; PRETREE_NUM_ELEMENTS];
+  for length in &mut pretree {
+    *length = bits.read(4) as u8;
+  }
+  let pretree = HuffmanDecoder::new(&pretree);
+
+  let delta = |previous: u8, code: u16| ((previous as i32 - code as i32 + 17) % 17) as u8;
+
+  let mut i = 0;
+
+  while i < lengths.len() {
+    let code = pretree.decode(bits)?;
+
+    let (run, value) = match code {
+      17 => (bits.read(4) as usize + 4, 0),
+      18 => (bits.read(5) as usize + 20, 0),
+      19 => {
+        let run = bits.read(1) as usize + 4;
+        let code = pretree.decode(bits)?;
+        // the whole run takes the delta applied to its first length
+        (run, delta(lengths.get(i).copied().unwrap_or(0), code))
+      }
+      _ => (1, delta(lengths[i], code)),
+    };
+
+    if i + run > lengths.len() {
+      return Err(LzxError::InvalidCode);
+    }
+
+    lengths[i..i + run].fill(value);
+    i += run;
+  }
+
+  Ok(())
+}
@@ -0,0 +1,399 @@
+use crate::lzx::bits::BitWriter;
+use crate::lzx::huffman::{build_codes, build_lengths};
+use crate::lzx::{
+  position_slots, LzxError, BLOCKTYPE_UNCOMPRESSED, BLOCKTYPE_VERBATIM, EXTRA_BITS, MAX_MATCH,
+  MIN_MATCH, NUM_CHARS, NUM_PRIMARY_LENGTHS, NUM_SECONDARY_LENGTHS, POSITION_BASE,
+  PRETREE_NUM_ELEMENTS,
+};
+
+/// How hard the encoder looks for matches.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CompressionLevel {
+  /// Uncompressed blocks only.
+  Store,
+  /// Greedy matching over short hash chains.
+  Fast,
+  #[default]
+  Normal,
+  /// Lazy matching over long hash chains, several times slower than `Normal`.
+  Best,
+}
+
+impl CompressionLevel {
+  // candidates checked per position and whether to try a match one byte later
+  fn search(&self) -> (usize, bool) {
+    match self {
+      CompressionLevel::Store => (0, false),
+      CompressionLevel::Fast => (8, false),
+      CompressionLevel::Normal => (64, true),
+      CompressionLevel::Best => (1024, true),
+    }
+  }
+}
+
+const HASH_BITS: u32 = 16;
+const NONE: u64 = u64::MAX;
+
+// Short matches far away cost more than the literals they replace.
+const MAX_SHORT_MATCH_OFFSET: u32 = 0x4000;
+
+#[derive(Debug, Copy, Clone)]
+enum Token {
+  Literal(u8),
+  Match { length: usize, offset: u32 },
+}
+
+/// Encodes frames as one verbatim block each, or as an uncompressed block when that
+/// comes out smaller. Output is read by [`LzxDecoder`](crate::lzx::LzxDecoder) with
+/// the same window size.
+pub struct LzxEncoder {
+  level: CompressionLevel,
+  window_bits: u32,
+  // the last window of input followed by the current frame
+  history: Vec<u8>,
+  history_start: u64,
+  inserted: u64,
+  head: Vec<u64>,
+  previous: Vec<u64>,
+  repeated: [u32; 3],
+  main_lengths: Vec<u8>,
+  length_lengths: Vec<u8>,
+  header_written: bool,
+  // state after the last verbatim block, until it's known to be used
+  pending: Option<BlockState>,
+}
+
+type BlockState = ([u32; 3], Vec<u8>, Vec<u8>);
+
+impl LzxEncoder {
+  pub fn new(window_bits: u32, level: CompressionLevel) -> Result<Self, LzxError> {
+    let slots = position_slots(window_bits).ok_or(LzxError::InvalidWindowSize(window_bits))?;
+
+    Ok(Self {
+      level,
+      window_bits,
+      history: Vec::new(),
+      history_start: 0,
+      inserted: 0,
+      head: vec![NONE; 1 << HASH_BITS],
+      previous: vec![NONE; 1 << window_bits],
+      repeated: [1; 3],
+      main_lengths: vec![0; NUM_CHARS + slots * 8],
+      length_lengths: vec![0; NUM_SECONDARY_LENGTHS],
+      header_written: false,
+      pending: None,
+    })
+  }
+
+  /// Encodes up to 32 KiB into one chunk appended to `output`.
+  pub fn compress_frame(&mut self, frame: &[u8], output: &mut Vec<u8>) {
+    let frame_start = self.history_start + self.history.len() as u64;
+    self.history.extend(frame);
+
+    let mut chunk = Vec::new();
+
+    if self.level != CompressionLevel::Store {
+      let tokens = self.find_tokens(frame_start);
+      self.write_verbatim(&tokens, frame.len(), &mut chunk);
+    }
+
+    // incompressible data is stored instead, chunk sizes have to fit 16 bits
+    if chunk.is_empty() || chunk.len() > frame.len() + 16 {
+      chunk.clear();
+      self.write_uncompressed(frame, &mut chunk);
+    } else {
+      self.commit_verbatim();
+    }
+
+    self.header_written = true;
+    output.extend(chunk);
+
+    let window = 1 << self.window_bits;
+    if self.history.len() > window {
+      let excess = self.history.len() - window;
+      self.history.drain(..excess);
+      self.history_start += excess as u64;
+    }
+  }
+
+  fn byte(&self, position: u64) -> u8 {
+    self.history[(position - self.history_start) as usize]
+  }
+
+  fn hash(&self, position: u64) -> usize {
+    let index = (position - self.history_start) as usize;
+    let bytes = &self.history[index..index + 3];
+    let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    (value.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+  }
+
+  // Adds every position in front of `end` that has the three bytes hashing needs.
+  fn insert_until(&mut self, end: u64) {
+    let available = self.history_start + self.history.len() as u64;
+    let end = end.min(available.saturating_sub(2));
+    let mask = (1 << self.window_bits) - 1;
+
+    while self.inserted < end {
+      let hash = self.hash(self.inserted);
+      self.previous[(self.inserted & mask) as usize] = self.head[hash];
+      self.head[hash] = self.inserted;
+      self.inserted += 1;
+    }
+  }
+
+  fn find_match(&self, position: u64, end: u64) -> Option<(usize, u32)> {
+    let max_length = MAX_MATCH.min((end - position) as usize);
+    if max_length < 3 {
+      return None;
+    }
+
+    let (chain, _) = self.level.search();
+    let max_offset = (1 << self.window_bits) - 3;
+    let mask = (1 << self.window_bits) - 1;
+
+    let mut best: Option<(usize, u32)> = None;
+    let mut candidate = self.head[self.hash(position)];
+
+    for _ in 0..chain {
+      if candidate == NONE || candidate >= position || position - candidate > max_offset {
+        break;
+      }
+
+      let length = (0..max_length)
+        .take_while(|&i| self.byte(candidate + i as u64) == self.byte(position + i as u64))
+        .count();
+
+      if length > best.map_or(0, |(length, _)| length) {
+        best = Some((length, (position - candidate) as u32));
+
+        if length == max_length {
+          break;
+        }
+      }
+
+      let next = self.previous[(candidate & mask) as usize];
+      if next >= candidate {
+        break;
+      }
+      candidate = next;
+    }
+
+    best.filter(|&(length, offset)| length > 3 || (length == 3 && offset <= MAX_SHORT_MATCH_OFFSET))
+  }
+
+  fn find_tokens(&mut self, start: u64) -> Vec<Token> {
+    let end = self.history_start + self.history.len() as u64;
+    let (_, lazy) = self.level.search();
+
+    let mut tokens = Vec::new();
+    let mut position = start;
+
+    while position < end {
+      self.insert_until(position);
+      let found = self.find_match(position, end);
+
+      let found = match found {
+        Some((length, _)) if lazy && length < 32 && position + 1 < end => {
+          self.insert_until(position + 1);
+          let next = self.find_match(position + 1, end);
+
+          if next.is_some_and(|(next, _)| next > length) {
+            None
+          } else {
+            found
+          }
+        }
+        _ => found,
+      };
+
+      match found {
+        Some((length, offset)) => {
+          tokens.push(Token::Match { length, offset });
+          position += length as u64;
+        }
+        None => {
+          tokens.push(Token::Literal(self.byte(position)));
+          position += 1;
+        }
+      }
+    }
+
+    self.insert_until(end);
+    tokens
+  }
+
+  fn write_header_bit(&self, writer: &mut BitWriter) {
+    if !self.header_written {
+      // no E8 call translation
+      writer.write(0, 1);
+    }
+  }
+
+  fn write_uncompressed(&mut self, frame: &[u8], output: &mut Vec<u8>) {
+    let mut writer = BitWriter::new(output);
+    self.write_header_bit(&mut writer);
+
+    writer.write(BLOCKTYPE_UNCOMPRESSED, 3);
+    writer.write(frame.len() as u32 >> 8, 16);
+    writer.write(frame.len() as u32 & 0xFF, 8);
+    writer.align();
+
+    for offset in self.repeated {
+      writer.write_bytes(&offset.to_le_bytes());
+    }
+
+    writer.write_bytes(frame);
+
+    if frame.len() % 2 == 1 {
+      writer.write_bytes(&[0]);
+    }
+
+    self.pending = None;
+  }
+
+  fn write_verbatim(&mut self, tokens: &[Token], size: usize, output: &mut Vec<u8>) {
+    let mut repeated = self.repeated;
+    let mut main_frequencies = vec![0; self.main_lengths.len()];
+    let mut length_frequencies = vec![0; NUM_SECONDARY_LENGTHS];
+
+    // main symbol, length symbol and the verbatim offset bits of every token
+    let symbols: Vec<(usize, Option<usize>, u32, u32)> = tokens
+      .iter()
+      .map(|token| match *token {
+        Token::Literal(byte) => (byte as usize, None, 0, 0),
+        Token::Match { length, offset } => {
+          let (slot, verbatim, extra) = encode_offset(&mut repeated, offset);
+
+          let length = length - MIN_MATCH;
+          let header = length.min(NUM_PRIMARY_LENGTHS);
+          let footer = (length >= NUM_PRIMARY_LENGTHS).then(|| length - NUM_PRIMARY_LENGTHS);
+
+          (NUM_CHARS + slot * 8 + header, footer, verbatim, extra)
+        }
+      })
+      .collect();
+
+    for &(main, length, _, _) in &symbols {
+      main_frequencies[main] += 1;
+      if let Some(length) = length {
+        length_frequencies[length] += 1;
+      }
+    }
+
+    let main_lengths = build_lengths(&main_frequencies, 16);
+    let length_lengths = build_lengths(&length_frequencies, 16);
+    let main_codes = build_codes(&main_lengths);
+    let length_codes = build_codes(&length_lengths);
+
+    let mut writer = BitWriter::new(output);
+    self.write_header_bit(&mut writer);
+
+    writer.write(BLOCKTYPE_VERBATIM, 3);
+    writer.write(size as u32 >> 8, 16);
+    writer.write(size as u32 & 0xFF, 8);
+
+    write_lengths(
+      &mut writer,
+      &self.main_lengths[..NUM_CHARS],
+      &main_lengths[..NUM_CHARS],
+    );
+    write_lengths(
+      &mut writer,
+      &self.main_lengths[NUM_CHARS..],
+      &main_lengths[NUM_CHARS..],
+    );
+    write_lengths(&mut writer, &self.length_lengths, &length_lengths);
+
+    for (main, length, verbatim, extra) in symbols {
+      writer.write(main_codes[main] as u32, main_lengths[main] as u32);
+
+      if let Some(length) = length {
+        writer.write(length_codes[length] as u32, length_lengths[length] as u32);
+      }
+
+      writer.write(verbatim, extra);
+    }
+
+    writer.flush();
+
+    self.pending = Some((repeated, main_lengths, length_lengths));
+  }
+
+  // The block state only carries over once the verbatim block is actually used.
+  fn commit_verbatim(&mut self) {
+    if let Some((repeated, main_lengths, length_lengths)) = self.pending.take() {
+      self.repeated = repeated;
+      self.main_lengths = main_lengths;
+      self.length_lengths = length_lengths;
+    }
+  }
+}
+
+// Returns the position slot, verbatim bits and their count for a match, updating the
+// repeated offsets the way the decoder does.
+fn encode_offset(repeated: &mut [u32; 3], offset: u32) -> (usize, u32, u32) {
+  if offset == repeated[0] {
+    (0, 0, 0)
+  } else if offset == repeated[1] {
+    repeated.swap(0, 1);
+    (1, 0, 0)
+  } else if offset == repeated[2] {
+    repeated.swap(0, 2);
+    (2, 0, 0)
+  } else {
+    *repeated = [offset, repeated[0], repeated[1]];
+
+    let formatted = offset + 2;
+    let slot = POSITION_BASE.partition_point(|&base| base <= formatted) - 1;
+    let extra = EXTRA_BITS[slot] as u32;
+
+    (slot, formatted - POSITION_BASE[slot], extra)
+  }
+}
+
+// Sends `lengths` as differences to `previous` through a pretree, runs of zeroes
+// take a single code.
+fn write_lengths(writer: &mut BitWriter, previous: &[u8], lengths: &[u8]) {
+  // pretree symbol with its extra bits and their count
+  let mut codes: Vec<(usize, u32, u32)> = Vec::new();
+  let mut i = 0;
+
+  while i < lengths.len() {
+    let zeroes = lengths[i..]
+      .iter()
+      .take_while(|&&length| length == 0)
+      .count();
+
+    if zeroes >= 20 {
+      let run = zeroes.min(51);
+      codes.push((18, (run - 20) as u32, 5));
+      i += run;
+    } else if zeroes >= 4 {
+      let run = zeroes.min(19);
+      codes.push((17, (run - 4) as u32, 4));
+      i += run;
+    } else {
+      let delta = (previous[i] as usize + 17 - lengths[i] as usize) % 17;
+      codes.push((delta, 0, 0));
+      i += 1;
+    }
+  }
+
+  let mut frequencies = [0; PRETREE_NUM_ELEMENTS];
+  for &(symbol, _, _) in &codes {
+    frequencies[symbol] += 1;
+  }
+
+  // pretree lengths are sent in 4 bits
+  let pretree_lengths = build_lengths(&frequencies, 15);
+  let pretree_codes = build_codes(&pretree_lengths);
+
+  for &length in &pretree_lengths {
+    writer.write(length as u32, 4);
+  }
+
+  for (symbol, extra, bits) in codes {
+    writer.write(pretree_codes[symbol] as u32, pretree_lengths[symbol] as u32);
+    writer.write(extra, bits);
+  }
+}
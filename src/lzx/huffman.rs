@@ -0,0 +1,154 @@
+use crate::lzx::bits::BitReader;
+use crate::lzx::LzxError;
+
+pub(crate) const MAX_CODE_LENGTH: usize = 16;
+
+/// Canonical Huffman code: shorter codes first, equal lengths ordered by symbol.
+#[derive(Debug, Default)]
+pub(crate) struct HuffmanDecoder {
+  counts: [u16; MAX_CODE_LENGTH + 1],
+  symbols: Vec<u16>,
+}
+
+impl HuffmanDecoder {
+  pub(crate) fn new(lengths: &[u8]) -> Self {
+    let mut counts = [0; MAX_CODE_LENGTH + 1];
+    for &length in lengths {
+      counts[length as usize] += 1;
+    }
+    counts[0] = 0;
+
+    let mut symbols = Vec::with_capacity(lengths.len());
+    for length in 1..=MAX_CODE_LENGTH as u8 {
+      for (symbol, _) in lengths.iter().enumerate().filter(|(_, &l)| l == length) {
+        symbols.push(symbol as u16);
+      }
+    }
+
+    Self { counts, symbols }
+  }
+
+  pub(crate) fn decode(&self, bits: &mut BitReader) -> Result<u16, LzxError> {
+    bits.ensure(MAX_CODE_LENGTH as u32);
+    let peek = bits.peek(MAX_CODE_LENGTH as u32);
+
+    let mut first = 0;
+    let mut index = 0;
+
+    for length in 1..=MAX_CODE_LENGTH {
+      let code = peek >> (MAX_CODE_LENGTH - length);
+      let count = self.counts[length] as u32;
+
+      if code < first + count {
+        bits.remove(length as u32);
+        return Ok(self.symbols[(index + code - first) as usize]);
+      }
+
+      index += count;
+      first = (first + count) << 1;
+    }
+
+    Err(LzxError::InvalidCode)
+  }
+}
+
+/// Code lengths no longer than `limit` for symbols occurring `frequencies` times.
+/// At least two symbols get a code, so the result is always a complete code.
+pub(crate) fn build_lengths(frequencies: &[u32], limit: u8) -> Vec<u8> {
+  let mut frequencies = frequencies.to_vec();
+
+  // unused symbols fill up the code, their lengths are sent anyway
+  let used = frequencies.iter().filter(|&&f| f > 0).count();
+  for frequency in frequencies
+    .iter_mut()
+    .filter(|f| **f == 0)
+    .take(2 - used.min(2))
+  {
+    *frequency = 1;
+  }
+
+  loop {
+    let lengths = huffman_lengths(&frequencies);
+
+    if lengths.iter().all(|&length| length <= limit) {
+      return lengths;
+    }
+
+    // flatten the distribution until the deepest code fits
+    for frequency in frequencies.iter_mut().filter(|f| **f > 0) {
+      *frequency = (*frequency >> 1).max(1);
+    }
+  }
+}
+
+fn huffman_lengths(frequencies: &[u32]) -> Vec<u8> {
+  use std::cmp::Reverse;
+  use std::collections::BinaryHeap;
+
+  // nodes past the symbols are internal, `parents` links every node to its parent
+  let mut parents = vec![usize::MAX; frequencies.len()];
+  let mut heap = BinaryHeap::new();
+
+  for (symbol, &frequency) in frequencies.iter().enumerate() {
+    if frequency > 0 {
+      heap.push(Reverse((frequency as u64, symbol)));
+    }
+  }
+
+  while heap.len() > 1 {
+    let Reverse((a, left)) = heap.pop().unwrap();
+    let Reverse((b, right)) = heap.pop().unwrap();
+
+    let node = parents.len();
+    parents.push(usize::MAX);
+    parents[left] = node;
+    parents[right] = node;
+
+    heap.push(Reverse((a + b, node)));
+  }
+
+  (0..frequencies.len())
+    .map(|symbol| {
+      if frequencies[symbol] == 0 {
+        return 0;
+      }
+
+      let mut depth = 0;
+      let mut node = symbol;
+      while parents[node] != usize::MAX {
+        node = parents[node];
+        depth += 1;
+      }
+      depth
+    })
+    .collect()
+}
+
+/// The canonical codes matching [`HuffmanDecoder`] for `lengths`.
+pub(crate) fn build_codes(lengths: &[u8]) -> Vec<u16> {
+  let mut counts = [0_u32; MAX_CODE_LENGTH + 1];
+  for &length in lengths {
+    counts[length as usize] += 1;
+  }
+  counts[0] = 0;
+
+  let mut next = [0_u32; MAX_CODE_LENGTH + 1];
+  let mut code = 0;
+  for length in 1..=MAX_CODE_LENGTH {
+    code = (code + counts[length - 1]) << 1;
+    next[length] = code;
+  }
+
+  lengths
+    .iter()
+    .map(|&length| {
+      if length == 0 {
+        return 0;
+      }
+
+      let code = next[length as usize];
+      next[length as usize] += 1;
+      code as u16
+    })
+    .collect()
+}
@@ -0,0 +1,125 @@
+//! XMemCompress, the LZX variant used by the 360 XDK.
+//!
+//! LZX output is split in frames of 32 KiB, each stored as a chunk with a big-endian
+//! size prefix. A chunk producing less than a full frame is prefixed by `0xFF` and
+//! the frame size. The native format of `xbcompress` adds a file header and groups
+//! the chunks in blocks that are decompressed independently.
+//!
+//! Compressed texture data goes through [`decompress`] before being handed to
+//! [`convert_to_dds`](crate::convert_to_dds) like any other tiled data.
+
+// the bitstream follows https://github.com/kyz/libmspack/blob/master/libmspack/mspack/lzxd.c
+
+use std::fmt;
+
+pub use crate::lzx::decoder::LzxDecoder;
+pub use crate::lzx::encoder::{CompressionLevel, LzxEncoder};
+pub use crate::lzx::xmem::{
+  compress_native, compress_raw, decompress, decompress_native, decompress_raw,
+};
+
+mod bits;
+mod decoder;
+mod encoder;
+mod huffman;
+mod xmem;
+
+pub(crate) const MIN_MATCH: usize = 2;
+pub(crate) const MAX_MATCH: usize = 257;
+pub(crate) const NUM_CHARS: usize = 256;
+pub(crate) const NUM_PRIMARY_LENGTHS: usize = 7;
+pub(crate) const NUM_SECONDARY_LENGTHS: usize = 249;
+pub(crate) const PRETREE_NUM_ELEMENTS: usize = 20;
+pub(crate) const ALIGNED_NUM_ELEMENTS: usize = 8;
+
+pub(crate) const BLOCKTYPE_VERBATIM: u32 = 1;
+pub(crate) const BLOCKTYPE_ALIGNED: u32 = 2;
+pub(crate) const BLOCKTYPE_UNCOMPRESSED: u32 = 3;
+
+pub(crate) const FRAME_SIZE: usize = 0x8000;
+
+/// The window XMemCompress uses unless told otherwise, 128 KiB.
+pub const DEFAULT_WINDOW_BITS: u32 = 17;
+
+const MAX_POSITION_SLOTS: usize = 51;
+
+pub(crate) const EXTRA_BITS: [u8; MAX_POSITION_SLOTS] = extra_bits();
+pub(crate) const POSITION_BASE: [u32; MAX_POSITION_SLOTS] = position_base();
+
+const fn extra_bits() -> [u8; MAX_POSITION_SLOTS] {
+  let mut bits = [0; MAX_POSITION_SLOTS];
+  let mut i = 0;
+  let mut j = 0;
+
+  while i < MAX_POSITION_SLOTS {
+    bits[i] = j;
+    if i + 1 < MAX_POSITION_SLOTS {
+      bits[i + 1] = j;
+    }
+    if i != 0 && j < 17 {
+      j += 1;
+    }
+    i += 2;
+  }
+
+  bits
+}
+
+const fn position_base() -> [u32; MAX_POSITION_SLOTS] {
+  let bits = extra_bits();
+  let mut base = [0; MAX_POSITION_SLOTS];
+  let mut i = 0;
+  let mut j = 0;
+
+  while i < MAX_POSITION_SLOTS {
+    base[i] = j;
+    j += 1 << bits[i];
+    i += 1;
+  }
+
+  base
+}
+
+/// Number of position slots for a window of `1 << window_bits` bytes, `None` for
+/// sizes LZX doesn't support.
+pub(crate) fn position_slots(window_bits: u32) -> Option<usize> {
+  match window_bits {
+    15 => Some(30),
+    16 => Some(32),
+    17 => Some(34),
+    18 => Some(36),
+    19 => Some(38),
+    20 => Some(42),
+    21 => Some(50),
+    _ => None,
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LzxError {
+  /// The input ended in the middle of a chunk or header.
+  UnexpectedEnd,
+  /// The native file header is missing or describes unsupported settings.
+  InvalidHeader,
+  InvalidWindowSize(u32),
+  InvalidBlockType(u32),
+  /// A bit sequence that doesn't belong to any Huffman code.
+  InvalidCode,
+  /// A match reaching in front of the decoded data or past the end of its block.
+  InvalidMatch,
+}
+
+impl fmt::Display for LzxError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LzxError::UnexpectedEnd => write!(f, "unexpected end of compressed data"),
+      LzxError::InvalidHeader => write!(f, "invalid XMemCompress header"),
+      LzxError::InvalidWindowSize(bits) => write!(f, "unsupported window size 2^{}", bits),
+      LzxError::InvalidBlockType(kind) => write!(f, "invalid LZX block type {}", kind),
+      LzxError::InvalidCode => write!(f, "invalid Huffman code"),
+      LzxError::InvalidMatch => write!(f, "invalid match"),
+    }
+  }
+}
+
+impl std::error::Error for LzxError {}
@@ -0,0 +1,270 @@
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+
+use binrw::{BinRead, BinWrite};
+
+use crate::lzx::decoder::LzxDecoder;
+use crate::lzx::encoder::{CompressionLevel, LzxEncoder};
+use crate::lzx::{LzxError, DEFAULT_WINDOW_BITS, FRAME_SIZE};
+
+// Uncompressed bytes per block of the native format, the XDK default.
+const PARTITION_SIZE: usize = 0x80000;
+
+pub(crate) const NATIVE_IDENTIFIER: u32 = 0x0FF512EE;
+
+/// `XCOMPRESS_FILE_HEADER_LZXNATIVE`, written by `xbcompress` and `XMemCompress`
+/// with `XMEMCOMPRESS_STREAM`.
+#[derive(BinRead, BinWrite, Debug)]
+#[brw(big, magic = 0x0FF512EE_u32)]
+pub(crate) struct NativeHeader {
+  pub(crate) version: u16,
+  pub(crate) reserved: u16,
+  pub(crate) context_flags: u32,
+  pub(crate) flags: u32,
+  pub(crate) window_size: u32,
+  pub(crate) partition_size: u32,
+  pub(crate) uncompressed_size: u64,
+  pub(crate) compressed_size: u64,
+  pub(crate) uncompressed_block_size: u32,
+  pub(crate) compressed_block_size_max: u32,
+}
+
+/// Decompresses either format, told apart by the native file identifier. Raw data is
+/// expected to use the default window size.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, LzxError> {
+  if data.starts_with(&NATIVE_IDENTIFIER.to_be_bytes()) {
+    decompress_native(data)
+  } else {
+    decompress_raw(data, DEFAULT_WINDOW_BITS)
+  }
+}
+
+/// Decompresses chunks without any file header, as `XMemDecompress` does by default.
+pub fn decompress_raw(data: &[u8], window_bits: u32) -> Result<Vec<u8>, LzxError> {
+  let mut decoder = LzxDecoder::new(window_bits)?;
+  let mut output = Vec::new();
+
+  decompress_chunks(&mut decoder, data, &mut output)?;
+
+  Ok(output)
+}
+
+/// Decompresses a file with the native header, every block with a fresh decoder.
+pub fn decompress_native(data: &[u8]) -> Result<Vec<u8>, LzxError> {
+  let mut reader = Cursor::new(data);
+  let header = NativeHeader::read(&mut reader).map_err(|_| LzxError::InvalidHeader)?;
+
+  if !header.window_size.is_power_of_two() {
+    return Err(LzxError::InvalidHeader);
+  }
+
+  let mut decoder = LzxDecoder::new(header.window_size.trailing_zeros())?;
+  let mut output = Vec::new();
+  let mut position = reader.position() as usize;
+
+  while (output.len() as u64) < header.uncompressed_size {
+    let size = read_u32(data, position)? as usize;
+    position += 4;
+
+    let block = data
+      .get(position..position + size)
+      .ok_or(LzxError::UnexpectedEnd)?;
+    position += size;
+
+    decoder.reset();
+    decompress_chunks(&mut decoder, block, &mut output)?;
+  }
+
+  if output.len() as u64 != header.uncompressed_size {
+    return Err(LzxError::UnexpectedEnd);
+  }
+
+  Ok(output)
+}
+
+fn decompress_chunks(
+  decoder: &mut LzxDecoder,
+  data: &[u8],
+  output: &mut Vec<u8>,
+) -> Result<(), LzxError> {
+  let mut position = 0;
+
+  while position < data.len() {
+    // 0xFF marks a frame smaller than 32 KiB, only the last one should be
+    let frame_size = if data[position] == 0xFF {
+      position += 1;
+      let size = read_u16(data, position)? as usize;
+      position += 2;
+      size
+    } else {
+      FRAME_SIZE
+    };
+
+    let size = read_u16(data, position)? as usize;
+    position += 2;
+
+    // blocks may be padded with zeroes
+    if size == 0 {
+      break;
+    }
+
+    let chunk = data
+      .get(position..position + size)
+      .ok_or(LzxError::UnexpectedEnd)?;
+    position += size;
+
+    decoder.decompress_frame(chunk, frame_size, output)?;
+  }
+
+  Ok(())
+}
+
+/// Compresses everything `reader` yields into chunks without a file header, the
+/// counterpart of [`decompress_raw`]. Returns the number of bytes written.
+pub fn compress_raw<R: Read, W: Write>(
+  reader: &mut R,
+  writer: &mut W,
+  window_bits: u32,
+  level: CompressionLevel,
+) -> io::Result<u64> {
+  let mut encoder = LzxEncoder::new(window_bits, level).map_err(invalid_input)?;
+  let mut frame = vec![0; FRAME_SIZE];
+  let mut written = 0;
+
+  loop {
+    let size = read_frame(reader, &mut frame)?;
+    if size == 0 {
+      break;
+    }
+
+    let chunk = compress_chunk(&mut encoder, &frame[..size]);
+    writer.write_all(&chunk)?;
+    written += chunk.len() as u64;
+
+    if size < FRAME_SIZE {
+      break;
+    }
+  }
+
+  Ok(written)
+}
+
+/// Compresses everything `reader` yields into the native format with the default
+/// window size, in blocks of 512 KiB. The header is filled in once the sizes are
+/// known, `writer` ends up behind the data.
+pub fn compress_native<R: Read, W: Write + Seek>(
+  reader: &mut R,
+  writer: &mut W,
+  level: CompressionLevel,
+) -> io::Result<()> {
+  let mut header = NativeHeader {
+    version: 0x0103,
+    reserved: 0,
+    context_flags: 0,
+    flags: 0,
+    window_size: 1 << DEFAULT_WINDOW_BITS,
+    partition_size: PARTITION_SIZE as u32,
+    uncompressed_size: 0,
+    compressed_size: 0,
+    uncompressed_block_size: PARTITION_SIZE as u32,
+    compressed_block_size_max: 0,
+  };
+
+  let start = writer.stream_position()?;
+  header.write(writer).map_err(binrw_to_io)?;
+
+  let mut frame = vec![0; FRAME_SIZE];
+  let mut end_of_input = false;
+
+  while !end_of_input {
+    let mut encoder = LzxEncoder::new(DEFAULT_WINDOW_BITS, level).map_err(invalid_input)?;
+    let mut block = Vec::new();
+    let mut block_size = 0;
+
+    while block_size < PARTITION_SIZE {
+      let size = read_frame(reader, &mut frame)?;
+      if size > 0 {
+        block.extend(compress_chunk(&mut encoder, &frame[..size]));
+        block_size += size;
+      }
+
+      if size < FRAME_SIZE {
+        end_of_input = true;
+        break;
+      }
+    }
+
+    if block.is_empty() {
+      break;
+    }
+
+    writer.write_all(&(block.len() as u32).to_be_bytes())?;
+    writer.write_all(&block)?;
+
+    header.uncompressed_size += block_size as u64;
+    header.compressed_size += 4 + block.len() as u64;
+    header.compressed_block_size_max = header.compressed_block_size_max.max(block.len() as u32);
+  }
+
+  let end = writer.stream_position()?;
+  writer.seek(SeekFrom::Start(start))?;
+  header.write(writer).map_err(binrw_to_io)?;
+  writer.seek(SeekFrom::Start(end))?;
+
+  Ok(())
+}
+
+// Compresses one frame and prefixes it the way `decompress_chunks` expects.
+fn compress_chunk(encoder: &mut LzxEncoder, frame: &[u8]) -> Vec<u8> {
+  let mut compressed = Vec::new();
+  encoder.compress_frame(frame, &mut compressed);
+
+  let mut chunk = Vec::with_capacity(compressed.len() + 5);
+  if frame.len() != FRAME_SIZE {
+    chunk.push(0xFF);
+    chunk.extend((frame.len() as u16).to_be_bytes());
+  }
+  chunk.extend((compressed.len() as u16).to_be_bytes());
+  chunk.extend(compressed);
+  chunk
+}
+
+// Fills `frame` as far as the reader allows, less only at the end of the input.
+fn read_frame<R: Read>(reader: &mut R, frame: &mut [u8]) -> io::Result<usize> {
+  let mut size = 0;
+
+  while size < frame.len() {
+    match reader.read(&mut frame[size..]) {
+      Ok(0) => break,
+      Ok(read) => size += read,
+      Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+      Err(error) => return Err(error),
+    }
+  }
+
+  Ok(size)
+}
+
+fn invalid_input(error: LzxError) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidInput, error)
+}
+
+fn binrw_to_io(error: binrw::Error) -> io::Error {
+  match error {
+    binrw::Error::Io(error) => error,
+    error => io::Error::other(error.to_string()),
+  }
+}
+
+fn read_u16(data: &[u8], position: usize) -> Result<u16, LzxError> {
+  let bytes = data
+    .get(position..position + 2)
+    .ok_or(LzxError::UnexpectedEnd)?;
+  Ok(u16::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], position: usize) -> Result<u32, LzxError> {
+  let bytes = data
+    .get(position..position + 4)
+    .ok_or(LzxError::UnexpectedEnd)?;
+  Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
@@ -36,3 +36,20 @@ pub(crate) const fn next_pow2(y: u32) -> u32 {
   x += 1;
   x as u32
 }
+
+/// Interprets the lowest `bits` of `x` as a two's complement number.
+pub(crate) const fn sign_extend(x: u32, bits: u32) -> i32 {
+  ((x << (32 - bits)) as i32) >> (32 - bits)
+}
+
+/// Stores `x` as a two's complement number of `bits` width, `None` if it doesn't fit.
+pub(crate) const fn truncate_signed(x: i32, bits: u32) -> Option<u32> {
+  let min = -(1 << (bits - 1));
+  let max = (1 << (bits - 1)) - 1;
+
+  if x < min || x > max {
+    return None;
+  }
+
+  Some(x as u32 & ((1 << bits) - 1))
+}
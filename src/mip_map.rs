@@ -78,16 +78,17 @@ impl<'a> TextureInfo<'a> {
     // Walk forward to find the address of the mip.
     let mut packed_mip_base = 1;
 
-    for i in packed_mip_base..mip {
-      let mip_width = 1.max(width_pow2 >> i);
-      let mip_height = 1.max(height_pow2 >> i);
+    while packed_mip_base < mip {
+      let mip_width = 1.max(width_pow2 >> packed_mip_base);
+      let mip_height = 1.max(height_pow2 >> packed_mip_base);
 
       if mip_width.min(mip_height) <= 16 {
         // We've reached the point where the mips are packed into a single tile.
-        packed_mip_base = i;
         break;
       }
-      address_offset += self.get_mip_extent(i, is_guest).all_blocks() * bytes_per_block;
+      address_offset +=
+        self.get_mip_extent(packed_mip_base, is_guest).all_blocks() * bytes_per_block;
+      packed_mip_base += 1;
     }
 
     // Now, check if the mip is packed at an offset.
@@ -101,6 +102,42 @@ impl<'a> TextureInfo<'a> {
     address_base + address_offset
   }
 
+  /// Blocks per row and rows of blocks of a single slice of `mip` in guest memory.
+  pub(crate) fn get_mip_pitch(&self, mip: u32) -> (u32, u32) {
+    let extent = self.get_mip_extent(mip, true);
+
+    // mips in the packed tail share a whole tile
+    (extent.block_pitch_h.max(32), extent.block_pitch_v.max(32))
+  }
+
+  /// Bytes reserved at `base_address`, covering every slice of mip 0.
+  pub(crate) fn get_base_size(&self) -> u32 {
+    let extent = self.get_mip_extent(0, true);
+    align(extent.all_blocks() * self.format.bytes_per_block, 4096)
+  }
+
+  /// Bytes reserved at `mip_address` for the mips `1..levels`.
+  pub(crate) fn get_mips_size(&self, levels: u32) -> u32 {
+    let width_pow2 = next_pow2(self.width);
+    let height_pow2 = next_pow2(self.height);
+
+    let mut size = 0;
+
+    for i in 1..levels {
+      size += self.get_mip_extent(i, true).all_blocks() * self.format.bytes_per_block;
+
+      let mip_width = 1.max(width_pow2 >> i);
+      let mip_height = 1.max(height_pow2 >> i);
+
+      if self.packed_mips && mip_width.min(mip_height) <= 16 {
+        // All remaining mips share this tile.
+        break;
+      }
+    }
+
+    align(size, 4096)
+  }
+
   fn get_packed_tile_offset(
     &self,
     packed_tile: u32,
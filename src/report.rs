@@ -0,0 +1,107 @@
+use std::fmt::{Debug, Display, Formatter, Result};
+
+use crate::format::{get_format_data, get_format_from_texture};
+use crate::texture_header::{TextureHeader, TextureMetadata};
+
+// Headers found in the wild may hold bit patterns without a matching variant,
+// those are printed instead of panicking.
+fn field<T: Debug, E>(value: std::result::Result<T, E>) -> String {
+  match value {
+    Ok(value) => format!("{value:?}"),
+    Err(_) => "invalid".to_string(),
+  }
+}
+
+impl Display for TextureMetadata {
+  fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+    let (width, height, depth) = self.size();
+
+    write!(f, "{:?} texture {width}x{height}", self.dimension())?;
+    if depth > 1 {
+      write!(f, "x{depth}")?;
+    }
+    writeln!(
+      f,
+      ", mips {}..={}{}",
+      self.min_mip_level(),
+      self.max_mip_level(),
+      if self.packed_mips() { " (packed)" } else { "" }
+    )?;
+
+    let format = self.format();
+    write!(f, "  format:   {format:?}")?;
+    match get_format_from_texture(&format) {
+      Some(known) => {
+        let data = get_format_data(&known);
+        writeln!(
+          f,
+          " ({}x{} blocks, {} bytes each)",
+          data.block_width, data.block_height, data.bytes_per_block
+        )?;
+      }
+      None => writeln!(f, " (unsupported)")?,
+    }
+
+    writeln!(
+      f,
+      "  layout:   {}, pitch {}, endian {:?}, kind {:?}",
+      if self.tiled() { "tiled" } else { "linear" },
+      (self.pitch() as u32) << 5,
+      self.endianness(),
+      self.kind()
+    )?;
+    writeln!(
+      f,
+      "  address:  base {:#010x}, mips {:#010x}",
+      self.base_address() << 12,
+      self.mip_address() << 12
+    )?;
+    writeln!(
+      f,
+      "  channels: x {:?}/{}, y {:?}/{}, z {:?}/{}, w {:?}/{}, {:?}",
+      self.sign_x(),
+      field(self.swizzle_x_or_err()),
+      self.sign_y(),
+      field(self.swizzle_y_or_err()),
+      self.sign_z(),
+      field(self.swizzle_z_or_err()),
+      self.sign_w(),
+      field(self.swizzle_w_or_err()),
+      self.num_format()
+    )?;
+    writeln!(
+      f,
+      "  clamp:    {:?}/{:?}/{:?}, border {:?}, policy {:?}",
+      self.clamp_x(),
+      self.clamp_y(),
+      self.clamp_z(),
+      self.border_color(),
+      self.clamp_policy()
+    )?;
+    write!(
+      f,
+      "  filter:   mag {:?}, min {:?}, mip {:?}, aniso {}, lod bias {}",
+      self.mag_filter(),
+      self.min_filter(),
+      self.mip_filter(),
+      field(self.aniso_filter_or_err()),
+      self.lod_bias()
+    )
+  }
+}
+
+impl Display for TextureHeader {
+  fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+    let common = self.resource_common();
+
+    writeln!(
+      f,
+      "{} resource, {} references, fence {}/{}",
+      field(common.resource_type_or_err()),
+      self.reference_count,
+      self.fence,
+      self.read_fence
+    )?;
+    write!(f, "{}", self.metadata())
+  }
+}
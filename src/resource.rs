@@ -0,0 +1,91 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use binrw::{BinRead, BinResult, BinWrite, Endian};
+
+use crate::texture_header::{
+  ResourceCommon, ResourceType, TextureHeader, TextureKind, DEFAULT_ENDIAN,
+};
+use crate::vertex::VertexBufferHeader;
+
+/// A D3D resource header of either kind. Both share the leading fields and the
+/// fetch constant type bits, which are used to tell them apart.
+#[derive(Debug)]
+pub enum ResourceHeader {
+  Texture(TextureHeader),
+  VertexBuffer(VertexBufferHeader),
+}
+
+// the type bits lead the first dword of every fetch constant
+fn fetch_kind(dword: u32) -> TextureKind {
+  match dword & 0b11 {
+    0 => TextureKind::InvalidTexture,
+    1 => TextureKind::InvalidVertex,
+    2 => TextureKind::Texture,
+    _ => TextureKind::Vertex,
+  }
+}
+
+impl ResourceHeader {
+  /// Reads a header in [`DEFAULT_ENDIAN`], the byte order of 360 data.
+  pub fn read_360<R: Read + Seek>(reader: &mut R) -> BinResult<Self> {
+    Self::read_options(reader, DEFAULT_ENDIAN, ())
+  }
+
+  /// Writes the header in [`DEFAULT_ENDIAN`], the byte order of 360 data.
+  pub fn write_360<W: Write + Seek>(&self, writer: &mut W) -> BinResult<()> {
+    self.write_options(writer, DEFAULT_ENDIAN, ())
+  }
+}
+
+impl BinRead for ResourceHeader {
+  type Args<'a> = ();
+
+  fn read_options<R: Read + Seek>(
+    reader: &mut R,
+    endian: Endian,
+    _args: Self::Args<'_>,
+  ) -> BinResult<Self> {
+    let start = reader.stream_position()?;
+    let dwords = <[u32; 8]>::read_options(reader, endian, ())?;
+    reader.seek(SeekFrom::Start(start))?;
+
+    // The vertex fetch constant follows `base_flush`, the texture one `mip_flush`.
+    let vertex = matches!(fetch_kind(dwords[6]), TextureKind::Vertex);
+    let texture = matches!(fetch_kind(dwords[7]), TextureKind::Texture);
+
+    let is_vertex = match (vertex, texture) {
+      (true, false) => true,
+      (false, true) => false,
+      (true, true) => {
+        let common = ResourceCommon::from_bytes(dwords[0].to_le_bytes());
+        matches!(
+          common.resource_type_or_err(),
+          Ok(ResourceType::VertexBuffer)
+        )
+      }
+      (false, false) => return Err(binrw::Error::NoVariantMatch { pos: start }),
+    };
+
+    if is_vertex {
+      VertexBufferHeader::read_options(reader, endian, ()).map(ResourceHeader::VertexBuffer)
+    } else {
+      TextureHeader::read_options(reader, endian, ()).map(ResourceHeader::Texture)
+    }
+  }
+}
+
+impl BinWrite for ResourceHeader {
+  type Args<'a> = ();
+
+  fn write_options<W: Write + Seek>(
+    &self,
+    writer: &mut W,
+    endian: Endian,
+    _args: Self::Args<'_>,
+  ) -> BinResult<()> {
+    match self {
+      ResourceHeader::Texture(header) => header.write_options(writer, endian, ()),
+      ResourceHeader::VertexBuffer(header) => header.write_options(writer, endian, ()),
+    }
+  }
+}
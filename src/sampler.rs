@@ -0,0 +1,318 @@
+use std::fmt::{Display, Formatter};
+
+use crate::texture_header::{
+  AnisoFilter, BorderColor, ClampMode, MipFilter, TextureMetadata, TriClamp,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WrapMode {
+  Repeat,
+  MirroredRepeat,
+  ClampToEdge,
+  ClampToBorder,
+  MirrorOnce,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Filter {
+  Nearest,
+  Linear,
+}
+
+/// Sampler state of a fetch constant without any GPU specifics.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SamplerDesc {
+  pub wrap_u: WrapMode,
+  pub wrap_v: WrapMode,
+  pub wrap_w: WrapMode,
+  pub mag_filter: Filter,
+  pub min_filter: Filter,
+  /// `None` samples the base level only.
+  pub mip_filter: Option<Filter>,
+  /// 1 disables anisotropic filtering.
+  pub max_anisotropy: u32,
+  /// RGBA in 0.0..=1.0.
+  pub border_color: [f32; 4],
+  pub min_lod: f32,
+  pub max_lod: f32,
+  pub lod_bias: f32,
+}
+
+/// A setting that couldn't be carried over exactly and was approximated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamplerIssue {
+  pub field: &'static str,
+  pub description: String,
+}
+
+impl Display for SamplerIssue {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}: {}", self.field, self.description)
+  }
+}
+
+fn issue(issues: &mut Vec<SamplerIssue>, field: &'static str, description: String) {
+  issues.push(SamplerIssue { field, description });
+}
+
+fn convert_clamp(mode: ClampMode, field: &'static str, issues: &mut Vec<SamplerIssue>) -> WrapMode {
+  let (wrap, exact) = match mode {
+    ClampMode::Repeat => (WrapMode::Repeat, true),
+    ClampMode::MirroredRepeat => (WrapMode::MirroredRepeat, true),
+    ClampMode::ClampToEdge => (WrapMode::ClampToEdge, true),
+    ClampMode::MirrorClampToEdge => (WrapMode::MirrorOnce, true),
+    ClampMode::ClampToHalfway => (WrapMode::ClampToEdge, false),
+    ClampMode::MirrorClampToHalfway => (WrapMode::MirrorOnce, false),
+    ClampMode::ClampToBorder => (WrapMode::ClampToBorder, true),
+    ClampMode::MirrorClampToBorder => (WrapMode::MirrorOnce, false),
+  };
+
+  if !exact {
+    issue(issues, field, format!("{mode:?} is sampled as {wrap:?}"));
+  }
+
+  wrap
+}
+
+fn convert_filter(mode: MipFilter, field: &'static str, issues: &mut Vec<SamplerIssue>) -> Filter {
+  match mode {
+    MipFilter::Point => Filter::Nearest,
+    MipFilter::Linear => Filter::Linear,
+    MipFilter::Basemap | MipFilter::Keep => {
+      issue(issues, field, format!("{mode:?} is sampled as Linear"));
+      Filter::Linear
+    }
+  }
+}
+
+impl TextureMetadata {
+  /// Converts the sampler state, listing every setting without an exact equivalent.
+  pub fn sampler_desc(&self) -> (SamplerDesc, Vec<SamplerIssue>) {
+    let mut issues = Vec::new();
+
+    let wrap_u = convert_clamp(self.clamp_x(), "clamp_x", &mut issues);
+    let wrap_v = convert_clamp(self.clamp_y(), "clamp_y", &mut issues);
+    let wrap_w = convert_clamp(self.clamp_z(), "clamp_z", &mut issues);
+
+    let mag_filter = convert_filter(self.mag_filter(), "mag_filter", &mut issues);
+    let min_filter = convert_filter(self.min_filter(), "min_filter", &mut issues);
+
+    let min_lod = self.min_mip_level() as f32;
+    let mut max_lod = self.max_mip_level() as f32;
+
+    let mip_filter = match self.mip_filter() {
+      MipFilter::Point => Some(Filter::Nearest),
+      MipFilter::Linear => Some(Filter::Linear),
+      MipFilter::Basemap => {
+        max_lod = min_lod;
+        None
+      }
+      MipFilter::Keep => {
+        issue(
+          &mut issues,
+          "mip_filter",
+          "Keep is sampled as Linear".to_string(),
+        );
+        Some(Filter::Linear)
+      }
+    };
+
+    let max_anisotropy = match self.aniso_filter_or_err() {
+      Ok(AnisoFilter::Disabled) | Ok(AnisoFilter::Max1To1) => 1,
+      Ok(AnisoFilter::Max2To1) => 2,
+      Ok(AnisoFilter::Max4To1) => 4,
+      Ok(AnisoFilter::Max8To1) => 8,
+      Ok(AnisoFilter::Max16To1) => 16,
+      Ok(AnisoFilter::UseFetchConst) | Err(_) => {
+        issue(
+          &mut issues,
+          "aniso_filter",
+          "anisotropy isn't set by the fetch constant, disabled it".to_string(),
+        );
+        1
+      }
+    };
+
+    let border_color = match self.border_color() {
+      BorderColor::AgbrBlack => [0.0, 0.0, 0.0, 0.0],
+      BorderColor::AgbrWhite => [1.0, 1.0, 1.0, 1.0],
+      mode @ (BorderColor::AcbycrBlack | BorderColor::AcbcryBlack) => {
+        issue(
+          &mut issues,
+          "border_color",
+          format!("{mode:?} is a YCbCr colour, used transparent black"),
+        );
+        [0.0, 0.0, 0.0, 0.0]
+      }
+    };
+
+    let tri_clamp = self.tri_clamp();
+    if !matches!(tri_clamp, TriClamp::Normal) {
+      issue(
+        &mut issues,
+        "tri_clamp",
+        format!("{tri_clamp:?} trilinear clamping is sampled as Normal"),
+      );
+    }
+
+    let sampler = SamplerDesc {
+      wrap_u,
+      wrap_v,
+      wrap_w,
+      mag_filter,
+      min_filter,
+      mip_filter,
+      max_anisotropy,
+      border_color,
+      min_lod,
+      max_lod,
+      lod_bias: self.lod_bias(),
+    };
+
+    (sampler, issues)
+  }
+}
+
+// https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#reference-sampler
+const GL_NEAREST: u32 = 9728;
+const GL_LINEAR: u32 = 9729;
+const GL_NEAREST_MIPMAP_NEAREST: u32 = 9984;
+const GL_LINEAR_MIPMAP_NEAREST: u32 = 9985;
+const GL_NEAREST_MIPMAP_LINEAR: u32 = 9986;
+const GL_LINEAR_MIPMAP_LINEAR: u32 = 9987;
+const GL_CLAMP_TO_EDGE: u32 = 33071;
+const GL_MIRRORED_REPEAT: u32 = 33648;
+const GL_REPEAT: u32 = 10497;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GltfSampler {
+  pub mag_filter: u32,
+  pub min_filter: u32,
+  pub wrap_s: u32,
+  pub wrap_t: u32,
+}
+
+impl GltfSampler {
+  pub fn to_json(&self) -> String {
+    format!(
+      r#"{{"magFilter":{},"minFilter":{},"wrapS":{},"wrapT":{}}}"#,
+      self.mag_filter, self.min_filter, self.wrap_s, self.wrap_t
+    )
+  }
+}
+
+// https://learn.microsoft.com/en-us/windows/win32/api/d3d11/ns-d3d11-d3d11_sampler_desc
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct D3D11SamplerDesc {
+  /// `D3D11_FILTER`
+  pub filter: u32,
+  /// `D3D11_TEXTURE_ADDRESS_MODE`
+  pub address_u: u32,
+  pub address_v: u32,
+  pub address_w: u32,
+  pub mip_lod_bias: f32,
+  pub max_anisotropy: u32,
+  /// `D3D11_COMPARISON_FUNC`
+  pub comparison_func: u32,
+  pub border_color: [f32; 4],
+  pub min_lod: f32,
+  pub max_lod: f32,
+}
+
+const D3D11_FILTER_ANISOTROPIC: u32 = 0x55;
+const D3D11_COMPARISON_NEVER: u32 = 1;
+
+impl SamplerDesc {
+  /// glTF has neither border colours nor mirror-once, those are approximated.
+  pub fn to_gltf(&self) -> (GltfSampler, Vec<SamplerIssue>) {
+    let mut issues = Vec::new();
+
+    let wrap = |mode: WrapMode, field: &'static str, issues: &mut Vec<SamplerIssue>| match mode {
+      WrapMode::Repeat => GL_REPEAT,
+      WrapMode::MirroredRepeat => GL_MIRRORED_REPEAT,
+      WrapMode::ClampToEdge => GL_CLAMP_TO_EDGE,
+      WrapMode::ClampToBorder => {
+        issue(
+          issues,
+          field,
+          "ClampToBorder is sampled as ClampToEdge".to_string(),
+        );
+        GL_CLAMP_TO_EDGE
+      }
+      WrapMode::MirrorOnce => {
+        issue(
+          issues,
+          field,
+          "MirrorOnce is sampled as MirroredRepeat".to_string(),
+        );
+        GL_MIRRORED_REPEAT
+      }
+    };
+
+    let mag_filter = match self.mag_filter {
+      Filter::Nearest => GL_NEAREST,
+      Filter::Linear => GL_LINEAR,
+    };
+
+    let min_filter = match (self.min_filter, self.mip_filter) {
+      (Filter::Nearest, None) => GL_NEAREST,
+      (Filter::Linear, None) => GL_LINEAR,
+      (Filter::Nearest, Some(Filter::Nearest)) => GL_NEAREST_MIPMAP_NEAREST,
+      (Filter::Linear, Some(Filter::Nearest)) => GL_LINEAR_MIPMAP_NEAREST,
+      (Filter::Nearest, Some(Filter::Linear)) => GL_NEAREST_MIPMAP_LINEAR,
+      (Filter::Linear, Some(Filter::Linear)) => GL_LINEAR_MIPMAP_LINEAR,
+    };
+
+    let sampler = GltfSampler {
+      mag_filter,
+      min_filter,
+      wrap_s: wrap(self.wrap_u, "wrap_u", &mut issues),
+      wrap_t: wrap(self.wrap_v, "wrap_v", &mut issues),
+    };
+
+    (sampler, issues)
+  }
+
+  pub fn to_d3d11(&self) -> D3D11SamplerDesc {
+    let address = |mode: WrapMode| match mode {
+      WrapMode::Repeat => 1,
+      WrapMode::MirroredRepeat => 2,
+      WrapMode::ClampToEdge => 3,
+      WrapMode::ClampToBorder => 4,
+      WrapMode::MirrorOnce => 5,
+    };
+
+    // D3D11_FILTER packs mip, mag and min as one bit each, linear being set.
+    let bit = |filter: Filter, shift: u32| match filter {
+      Filter::Nearest => 0,
+      Filter::Linear => 1 << shift,
+    };
+
+    let filter = if self.max_anisotropy > 1 {
+      D3D11_FILTER_ANISOTROPIC
+    } else {
+      bit(self.mip_filter.unwrap_or(Filter::Nearest), 0)
+        | bit(self.mag_filter, 2)
+        | bit(self.min_filter, 4)
+    };
+
+    // D3D11 can't disable mipmapping in the sampler, clamping the LOD does the same.
+    let max_lod = match self.mip_filter {
+      Some(_) => self.max_lod,
+      None => self.min_lod,
+    };
+
+    D3D11SamplerDesc {
+      filter,
+      address_u: address(self.wrap_u),
+      address_v: address(self.wrap_v),
+      address_w: address(self.wrap_w),
+      mip_lod_bias: self.lod_bias,
+      max_anisotropy: self.max_anisotropy,
+      comparison_func: D3D11_COMPARISON_NEVER,
+      border_color: self.border_color,
+      min_lod: self.min_lod,
+      max_lod,
+    }
+  }
+}
@@ -0,0 +1,118 @@
+use std::io::Cursor;
+
+use binrw::{BinRead, Endian};
+
+use crate::texture_header::{TextureHeader, D3DFLUSH_INITIAL_VALUE};
+use crate::Config;
+
+const HEADER_SIZE: usize = 52;
+
+// The fetch constant starts after the seven `D3DResource` dwords.
+const FETCH_CONSTANT_OFFSET: usize = 28;
+
+#[derive(Debug, Copy, Clone)]
+pub struct ScanOptions {
+  pub endian: Endian,
+  /// Headers are only looked for at multiples of this, 4 for dword aligned data.
+  pub alignment: usize,
+  /// Candidates scoring below this are dropped, see [`ScanMatch::confidence`].
+  pub min_confidence: f32,
+}
+
+impl Default for ScanOptions {
+  fn default() -> Self {
+    Self {
+      endian: Endian::Big,
+      alignment: 4,
+      min_confidence: 0.8,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct ScanMatch {
+  pub offset: usize,
+  pub header: TextureHeader,
+  /// The decoded settings, `None` if the format isn't supported by the converter.
+  pub config: Option<Config>,
+  /// 0.0 to 1.0, headers failing validation never score above 0.4.
+  pub confidence: f32,
+}
+
+/// Looks for texture headers in `data`, in the retail layout. Every position is
+/// first checked for the texture fetch constant type bits, so only a fraction of
+/// them is parsed and validated. Matches don't overlap.
+pub fn scan(data: &[u8], options: &ScanOptions) -> Vec<ScanMatch> {
+  let alignment = options.alignment.max(1);
+  let mut matches = Vec::new();
+  let mut offset = 0;
+
+  while offset + HEADER_SIZE <= data.len() {
+    if let Some(found) = check_candidate(data, offset, options) {
+      matches.push(found);
+      offset += HEADER_SIZE.next_multiple_of(alignment);
+    } else {
+      offset += alignment;
+    }
+  }
+
+  matches
+}
+
+fn check_candidate(data: &[u8], offset: usize, options: &ScanOptions) -> Option<ScanMatch> {
+  // the type bits are the lowest of the first fetch constant dword
+  let kind_byte = match options.endian {
+    Endian::Big => data[offset + FETCH_CONSTANT_OFFSET + 3],
+    Endian::Little => data[offset + FETCH_CONSTANT_OFFSET],
+  };
+
+  if kind_byte & 0b11 != 2 {
+    return None;
+  }
+
+  let mut reader = Cursor::new(&data[offset..offset + HEADER_SIZE]);
+  let header = TextureHeader::read_options(&mut reader, options.endian, ()).ok()?;
+
+  let confidence = score_header(&header);
+  if confidence < options.min_confidence {
+    return None;
+  }
+
+  Some(ScanMatch {
+    offset,
+    config: Config::from_metadata(&header.metadata()),
+    header,
+    confidence,
+  })
+}
+
+/// Rates how much `header` looks like one written by the XDK, from 0.0 to 1.0. Most
+/// of it is the fetch constant passing validation, the rest the `D3DResource` fields.
+pub(crate) fn score_header(header: &TextureHeader) -> f32 {
+  let (errors, warnings) = header.metadata().count_issues();
+
+  let mut score = 0.0;
+
+  if errors == 0 {
+    score += 0.6 - 0.05 * warnings.min(4) as f32;
+  }
+
+  if header.resource_common().texture_type().is_some() {
+    score += 0.2;
+  }
+
+  if (1..=16).contains(&header.reference_count) {
+    score += 0.1;
+  }
+
+  let flush = |value: u32| value == D3DFLUSH_INITIAL_VALUE || value == 0;
+  if flush(header.base_flush) && flush(header.mip_flush) {
+    score += 0.05;
+  }
+
+  if header.fence == 0 && header.read_fence == 0 {
+    score += 0.05;
+  }
+
+  score
+}
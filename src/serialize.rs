@@ -0,0 +1,320 @@
+use modular_bitfield::error::OutOfBounds;
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::texture_header::*;
+
+// The bitfield structs only expose accessors, so (de)serialization goes through
+// plain mirrors of them. Values are kept exactly as encoded in the fetch constant.
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "GpuTextureSize1D")]
+struct Size1D {
+  width: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "GpuTextureSize2D")]
+struct Size2D {
+  width: u16,
+  height: u16,
+  stack_depth: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "GpuTextureSize3D")]
+struct Size3D {
+  width: u16,
+  height: u16,
+  depth: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "GpuTextureSizeStack")]
+struct SizeStack {
+  width: u16,
+  height: u16,
+  depth: u8,
+}
+
+impl Serialize for TextureSize1D {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    Size1D {
+      width: self.width(),
+    }
+    .serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for TextureSize1D {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let size = Size1D::deserialize(deserializer)?;
+
+    TextureSize1D::new()
+      .with_width_checked(size.width)
+      .map_err(D::Error::custom)
+  }
+}
+
+impl Serialize for TextureSize2D {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    Size2D {
+      width: self.width(),
+      height: self.height(),
+      stack_depth: self.stack_depth(),
+    }
+    .serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for TextureSize2D {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let size = Size2D::deserialize(deserializer)?;
+
+    TextureSize2D::new()
+      .with_width_checked(size.width)
+      .and_then(|s| s.with_height_checked(size.height))
+      .and_then(|s| s.with_stack_depth_checked(size.stack_depth))
+      .map_err(D::Error::custom)
+  }
+}
+
+impl Serialize for TextureSize3D {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    Size3D {
+      width: self.width(),
+      height: self.height(),
+      depth: self.depth(),
+    }
+    .serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for TextureSize3D {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let size = Size3D::deserialize(deserializer)?;
+
+    TextureSize3D::new()
+      .with_width_checked(size.width)
+      .and_then(|s| s.with_height_checked(size.height))
+      .and_then(|s| s.with_depth_checked(size.depth))
+      .map_err(D::Error::custom)
+  }
+}
+
+impl Serialize for TextureSizeStack {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    SizeStack {
+      width: self.width(),
+      height: self.height(),
+      depth: self.depth(),
+    }
+    .serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for TextureSizeStack {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let size = SizeStack::deserialize(deserializer)?;
+
+    TextureSizeStack::new()
+      .with_width_checked(size.width)
+      .and_then(|s| s.with_height_checked(size.height))
+      .and_then(|s| s.with_depth_checked(size.depth))
+      .map_err(D::Error::custom)
+  }
+}
+
+/// `texture_size` is a union, `dimension` decides which of the size structs it holds.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "dimension", content = "texture_size")]
+enum TextureSize {
+  OneD(TextureSize1D),
+  TwoDOrStacked(TextureSize2D),
+  ThreeD(TextureSize3D),
+  CubeMap(TextureSizeStack),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "GpuTextureFetchConstant")]
+struct FetchConstant {
+  kind: TextureKind,
+  sign_x: TextureSign,
+  sign_y: TextureSign,
+  sign_z: TextureSign,
+  sign_w: TextureSign,
+  clamp_x: ClampMode,
+  clamp_y: ClampMode,
+  clamp_z: ClampMode,
+  signed_repeating_fraction: SignedRepeatingFractionMode,
+  dim_tbd: u8,
+  pitch: u16,
+  tiled: bool,
+  format: TextureFormat,
+  endianness: Endian,
+  request_size: RequestSize,
+  stacked: bool,
+  clamp_policy: ClampPolicy,
+  base_address: u32,
+  #[serde(flatten)]
+  texture_size: TextureSize,
+  num_format: NumFormat,
+  swizzle_x: Swizzle,
+  swizzle_y: Swizzle,
+  swizzle_z: Swizzle,
+  swizzle_w: Swizzle,
+  exp_adjust: u8,
+  mag_filter: MipFilter,
+  min_filter: MipFilter,
+  mip_filter: MipFilter,
+  aniso_filter: AnisoFilter,
+  arbitrary_filter: ArbitraryFilter,
+  border_size: u8,
+  vol_mag_filter: MinMagFilter,
+  vol_min_filter: MinMagFilter,
+  min_mip_level: u8,
+  max_mip_level: u8,
+  mag_aniso_walk: u8,
+  min_aniso_walk: u8,
+  lodbias: u16,
+  grad_exp_adjust_h: u8,
+  grad_exp_adjust_v: u8,
+  border_color: BorderColor,
+  force_bc_w_to_max: bool,
+  tri_clamp: TriClamp,
+  aniso_bias: u8,
+  packed_mips: bool,
+  mip_address: u32,
+}
+
+impl Serialize for TextureMetadata {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let texture_size = self.texture_size().to_le_bytes();
+
+    let texture_size = match self.dimension() {
+      Dimension::OneD => TextureSize::OneD(TextureSize1D::from_bytes(texture_size)),
+      Dimension::TwoDOrStacked => {
+        TextureSize::TwoDOrStacked(TextureSize2D::from_bytes(texture_size))
+      }
+      Dimension::ThreeD => TextureSize::ThreeD(TextureSize3D::from_bytes(texture_size)),
+      Dimension::CubeMap => TextureSize::CubeMap(TextureSizeStack::from_bytes(texture_size)),
+    };
+
+    FetchConstant {
+      kind: self.kind(),
+      sign_x: self.sign_x(),
+      sign_y: self.sign_y(),
+      sign_z: self.sign_z(),
+      sign_w: self.sign_w(),
+      clamp_x: self.clamp_x(),
+      clamp_y: self.clamp_y(),
+      clamp_z: self.clamp_z(),
+      signed_repeating_fraction: self.signed_repeating_fraction(),
+      dim_tbd: self.dim_tbd(),
+      pitch: self.pitch(),
+      tiled: self.tiled(),
+      format: self.format(),
+      endianness: self.endianness(),
+      request_size: self.request_size_or_err().map_err(S::Error::custom)?,
+      stacked: self.stacked(),
+      clamp_policy: self.clamp_policy(),
+      base_address: self.base_address(),
+      texture_size,
+      num_format: self.num_format(),
+      swizzle_x: self.swizzle_x_or_err().map_err(S::Error::custom)?,
+      swizzle_y: self.swizzle_y_or_err().map_err(S::Error::custom)?,
+      swizzle_z: self.swizzle_z_or_err().map_err(S::Error::custom)?,
+      swizzle_w: self.swizzle_w_or_err().map_err(S::Error::custom)?,
+      exp_adjust: self.exp_adjust(),
+      mag_filter: self.mag_filter(),
+      min_filter: self.min_filter(),
+      mip_filter: self.mip_filter(),
+      aniso_filter: self.aniso_filter_or_err().map_err(S::Error::custom)?,
+      arbitrary_filter: self.arbitrary_filter_or_err().map_err(S::Error::custom)?,
+      border_size: self.border_size(),
+      vol_mag_filter: self.vol_mag_filter(),
+      vol_min_filter: self.vol_min_filter(),
+      min_mip_level: self.min_mip_level(),
+      max_mip_level: self.max_mip_level(),
+      mag_aniso_walk: self.mag_aniso_walk(),
+      min_aniso_walk: self.min_aniso_walk(),
+      lodbias: self.lodbias(),
+      grad_exp_adjust_h: self.grad_exp_adjust_h(),
+      grad_exp_adjust_v: self.grad_exp_adjust_v(),
+      border_color: self.border_color(),
+      force_bc_w_to_max: self.force_bc_w_to_max(),
+      tri_clamp: self.tri_clamp(),
+      aniso_bias: self.aniso_bias(),
+      packed_mips: self.packed_mips(),
+      mip_address: self.mip_address(),
+    }
+    .serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for TextureMetadata {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let fetch = FetchConstant::deserialize(deserializer)?;
+    build_metadata(fetch).map_err(D::Error::custom)
+  }
+}
+
+// enum fields can't be out of range, only the plain integers need checking
+fn build_metadata(fetch: FetchConstant) -> Result<TextureMetadata, OutOfBounds> {
+  let (dimension, texture_size) = match fetch.texture_size {
+    TextureSize::OneD(size) => (Dimension::OneD, size.into_bytes()),
+    TextureSize::TwoDOrStacked(size) => (Dimension::TwoDOrStacked, size.into_bytes()),
+    TextureSize::ThreeD(size) => (Dimension::ThreeD, size.into_bytes()),
+    TextureSize::CubeMap(size) => (Dimension::CubeMap, size.into_bytes()),
+  };
+
+  TextureMetadata::new()
+    .with_kind(fetch.kind)
+    .with_sign_x(fetch.sign_x)
+    .with_sign_y(fetch.sign_y)
+    .with_sign_z(fetch.sign_z)
+    .with_sign_w(fetch.sign_w)
+    .with_clamp_x(fetch.clamp_x)
+    .with_clamp_y(fetch.clamp_y)
+    .with_clamp_z(fetch.clamp_z)
+    .with_signed_repeating_fraction(fetch.signed_repeating_fraction)
+    .with_dim_tbd_checked(fetch.dim_tbd)?
+    .with_pitch_checked(fetch.pitch)?
+    .with_tiled(fetch.tiled)
+    .with_format(fetch.format)
+    .with_endianness(fetch.endianness)
+    .with_request_size(fetch.request_size)
+    .with_stacked(fetch.stacked)
+    .with_clamp_policy(fetch.clamp_policy)
+    .with_base_address_checked(fetch.base_address)?
+    .with_texture_size(u32::from_le_bytes(texture_size))
+    .with_num_format(fetch.num_format)
+    .with_swizzle_x(fetch.swizzle_x)
+    .with_swizzle_y(fetch.swizzle_y)
+    .with_swizzle_z(fetch.swizzle_z)
+    .with_swizzle_w(fetch.swizzle_w)
+    .with_exp_adjust_checked(fetch.exp_adjust)?
+    .with_mag_filter(fetch.mag_filter)
+    .with_min_filter(fetch.min_filter)
+    .with_mip_filter(fetch.mip_filter)
+    .with_aniso_filter(fetch.aniso_filter)
+    .with_arbitrary_filter(fetch.arbitrary_filter)
+    .with_border_size_checked(fetch.border_size)?
+    .with_vol_mag_filter(fetch.vol_mag_filter)
+    .with_vol_min_filter(fetch.vol_min_filter)
+    .with_min_mip_level_checked(fetch.min_mip_level)?
+    .with_max_mip_level_checked(fetch.max_mip_level)?
+    .with_mag_aniso_walk_checked(fetch.mag_aniso_walk)?
+    .with_min_aniso_walk_checked(fetch.min_aniso_walk)?
+    .with_lodbias_checked(fetch.lodbias)?
+    .with_grad_exp_adjust_h_checked(fetch.grad_exp_adjust_h)?
+    .with_grad_exp_adjust_v_checked(fetch.grad_exp_adjust_v)?
+    .with_border_color(fetch.border_color)
+    .with_force_bc_w_to_max(fetch.force_bc_w_to_max)
+    .with_tri_clamp(fetch.tri_clamp)
+    .with_aniso_bias_checked(fetch.aniso_bias)?
+    .with_dimension(dimension)
+    .with_packed_mips(fetch.packed_mips)
+    .with_mip_address_checked(fetch.mip_address)
+}
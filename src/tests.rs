@@ -1,4 +1,29 @@
+use std::io::Cursor;
+
+use binrw::{BinRead, BinWrite};
+use dds::{D3DFormat, Dds};
+
 use crate::math::{align, log2_ceil, next_pow2};
+use crate::scan::score_header;
+use crate::{
+  convert_from_dds, convert_from_dds_checked, convert_to_dds, scan, tile_texture, untile_texture,
+  ClampMode, Config, Dimension, Format, HeaderOptions, LayoutError, LayoutRequest, ResourceCommon,
+  ResourceHeader, ResourceType, ScanOptions, Severity, Texture, TextureError, TextureFormat,
+  TextureHeader, TextureKind, TextureType, WrapMode, Xpr2, Xpr2Error, Xpr2Resource, Xpr2Type,
+};
+
+// Xorshift noise, the same on every run.
+fn noise(len: usize) -> Vec<u8> {
+  let mut seed = 0x1234_5678_u32;
+  (0..len)
+    .map(|_| {
+      seed ^= seed << 13;
+      seed ^= seed >> 17;
+      seed ^= seed << 5;
+      seed as u8
+    })
+    .collect()
+}
 
 #[test]
 fn test_align() {
@@ -8,6 +33,49 @@ fn test_align() {
   assert_eq!(align(45, 32), 64);
 }
 
+#[test]
+fn test_packed_mip_locations() {
+  use crate::format::get_format_data;
+  use crate::mip_map::TextureInfo;
+
+  let format_data = get_format_data(&Format::Dxt1);
+  let info = TextureInfo {
+    width: 256,
+    height: 256,
+    depth: 1,
+    pitch: 256,
+    tiled: true,
+    packed_mips: true,
+    format: &format_data,
+    base_address: 0,
+    mip_address: 0x8000,
+  };
+
+  let locations: Vec<(u32, u32, u32)> = (1..9)
+    .map(|mip| {
+      let (mut x, mut y) = (0, 0);
+      let address = info.get_mip_location(mip, &mut x, &mut y, true);
+      (address, x, y)
+    })
+    .collect();
+
+  // mips 1 to 3 take a tile of their own, 16x16 and below share the one behind them:
+  // the 16x16 mip on the right, 8x8 and 4x4 left of it, 2x2 and 1x1 below
+  assert_eq!(
+    locations,
+    [
+      (0x8000, 0, 0),
+      (0xA000, 0, 0),
+      (0xC000, 0, 0),
+      (0xE000, 4, 0),
+      (0xE000, 2, 0),
+      (0xE000, 1, 0),
+      (0xE000, 0, 2),
+      (0xE000, 0, 1),
+    ]
+  );
+}
+
 #[test]
 fn test_log2_ceil() {
   assert_eq!(log2_ceil(1), 0);
@@ -32,3 +100,1848 @@ fn test_next_pow2() {
   assert_eq!(next_pow2(6), 8);
   assert_eq!(next_pow2(7), 8);
 }
+
+#[test]
+fn test_layout_plan() {
+  let layout = LayoutRequest::new(Format::Dxt1, 256, 256).plan().unwrap();
+
+  assert_eq!(layout.config.pitch, 256);
+  assert_eq!(layout.config.mipmap_levels, Some(9));
+  assert_eq!(layout.base_size, 0x8000);
+  assert_eq!(layout.mip_size, 0x8000);
+  assert_eq!(layout.config.mip_address, 8);
+
+  assert_eq!(layout.metadata.pitch(), 8);
+  assert_eq!(layout.metadata.max_mip_level(), 8);
+  assert_eq!(layout.metadata.size(), (256, 256, 1));
+}
+
+#[test]
+fn test_layout_errors() {
+  let request = LayoutRequest::new(Format::Dxt1, 0, 256);
+  assert_eq!(request.plan().unwrap_err(), LayoutError::EmptyDimension);
+
+  let request = LayoutRequest {
+    address: 0x800,
+    ..LayoutRequest::new(Format::Dxt1, 256, 256)
+  };
+  assert_eq!(
+    request.plan().unwrap_err(),
+    LayoutError::MisalignedAddress(0x800)
+  );
+
+  let request = LayoutRequest {
+    mipmap_levels: Some(10),
+    ..LayoutRequest::new(Format::Dxt1, 256, 256)
+  };
+  assert_eq!(
+    request.plan().unwrap_err(),
+    LayoutError::MipmapLevels { levels: 10, max: 9 }
+  );
+
+  // 2D textures have 13 bits per side, volumes 11
+  assert!(LayoutRequest::new(Format::Dxt1, 8192, 64).plan().is_ok());
+  assert_eq!(
+    LayoutRequest::new(Format::Dxt1, 8193, 64)
+      .plan()
+      .unwrap_err(),
+    LayoutError::SizeOutOfRange {
+      width: 8193,
+      height: 64,
+      depth: 1
+    }
+  );
+
+  let mut metadata = LayoutRequest::new(Format::Dxt1, 64, 64)
+    .plan()
+    .unwrap()
+    .metadata;
+  metadata.set_dimension(Dimension::ThreeD);
+  assert!(metadata.set_size(2048, 64, 4).is_ok());
+  assert!(metadata.set_size(0x10000 + 64, 64, 4).is_err());
+  assert_eq!(metadata.size(), (2048, 64, 4));
+}
+
+#[test]
+fn test_header_from_dds() {
+  let dds = Dds::new_d3d(D3DFormat::DXT5, 128, 64, None, Some(8), None).unwrap();
+  let header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+  let metadata = header.metadata();
+
+  assert_eq!(header.common, 3);
+  assert_eq!(header.reference_count, 1);
+  assert!(matches!(metadata.kind(), TextureKind::Texture));
+  assert!(matches!(metadata.format(), TextureFormat::Dxt4_5));
+  assert!(matches!(metadata.dimension(), Dimension::TwoDOrStacked));
+  assert_eq!(metadata.size(), (128, 64, 1));
+  assert_eq!(metadata.max_mip_level(), 7);
+  assert_eq!(metadata.base_address(), 0);
+  assert_eq!(metadata.mip_address(), 4);
+}
+
+#[test]
+fn test_resource_common() {
+  let common = ResourceCommon::from_bytes(0x0020_0012_u32.to_le_bytes());
+  assert_eq!(common.resource_type(), ResourceType::CubeTexture);
+  assert_eq!(common.texture_type(), Some(TextureType::Cube));
+  assert!(common.cpu_cached());
+  assert!(!common.not_in_memory());
+
+  let common = ResourceCommon::from_bytes(1_u32.to_le_bytes());
+  assert_eq!(common.texture_type(), None);
+
+  let common = ResourceCommon::from_bytes(0x3F_u32.to_le_bytes());
+  assert_eq!(common.texture_type(), None);
+}
+
+#[test]
+fn test_header_byte_order() {
+  let dds = Dds::new_d3d(D3DFormat::DXT1, 64, 64, None, Some(7), None).unwrap();
+  let header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+
+  let mut big = Cursor::new(Vec::new());
+  header.write_360(&mut big).unwrap();
+  let mut little = Cursor::new(Vec::new());
+  header.write_le(&mut little).unwrap();
+
+  let big = big.into_inner();
+  let little = little.into_inner();
+
+  assert_eq!(big.len(), 52);
+  assert_eq!(&big[..8], &[0, 0, 0, 3, 0, 0, 0, 1]);
+
+  for (b, l) in big.chunks_exact(4).zip(little.chunks_exact(4)) {
+    assert_eq!(b, [l[3], l[2], l[1], l[0]]);
+  }
+
+  let from_big = TextureHeader::read_360(&mut Cursor::new(&big)).unwrap();
+  let from_little = TextureHeader::read_le(&mut Cursor::new(&little)).unwrap();
+
+  assert_eq!(from_big.metadata().size(), (64, 64, 1));
+  assert_eq!(
+    from_big.metadata().into_bytes(),
+    from_little.metadata().into_bytes()
+  );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_metadata_serde() {
+  let metadata = LayoutRequest {
+    texture_type: TextureType::Cube,
+    ..LayoutRequest::new(Format::Dxt1, 64, 64)
+  }
+  .plan()
+  .unwrap()
+  .metadata;
+
+  let json = serde_json::to_value(&metadata).unwrap();
+  assert_eq!(json["dimension"], "CubeMap");
+  assert_eq!(json["format"], "Dxt1");
+  assert_eq!(json["texture_size"]["depth"], 5);
+
+  let parsed: crate::TextureMetadata = serde_json::from_value(json).unwrap();
+  assert_eq!(parsed.into_bytes(), metadata.into_bytes());
+}
+
+#[test]
+fn test_metadata_display() {
+  let metadata = LayoutRequest::new(Format::Dxt1, 256, 128)
+    .plan()
+    .unwrap()
+    .metadata;
+  let report = metadata.to_string();
+
+  assert!(report.starts_with("TwoDOrStacked texture 256x128, mips 0..=8 (packed)"));
+  assert!(report.contains("Dxt1 (4x4 blocks, 8 bytes each)"));
+  assert!(report.contains("base 0x00000000, mips 0x00004000"));
+}
+
+#[test]
+fn test_signed_fields() {
+  let mut metadata = LayoutRequest::new(Format::Dxt5, 64, 64)
+    .plan()
+    .unwrap()
+    .metadata;
+
+  metadata.set_lod_bias(-1.5).unwrap();
+  assert_eq!(metadata.lodbias(), 0x3D0);
+  assert_eq!(metadata.lod_bias(), -1.5);
+  assert!(metadata.set_lod_bias(16.0).is_err());
+  assert!(metadata.set_lod_bias(f32::NAN).is_err());
+
+  metadata.set_exp_adjust_signed(-32).unwrap();
+  assert_eq!(metadata.exp_adjust(), 0x20);
+  assert_eq!(metadata.exp_adjust_signed(), -32);
+  assert!(metadata.set_exp_adjust_signed(32).is_err());
+
+  metadata.set_aniso_bias_signed(7).unwrap();
+  assert_eq!(metadata.aniso_bias_signed(), 7);
+  assert!(metadata.set_aniso_bias_signed(-9).is_err());
+}
+
+#[test]
+fn test_sampler_desc() {
+  let dds = Dds::new_d3d(D3DFormat::DXT1, 64, 64, None, Some(7), None).unwrap();
+  let mut metadata = TextureHeader::from_dds(&dds, &HeaderOptions::default())
+    .unwrap()
+    .metadata();
+  metadata.set_clamp_y(ClampMode::ClampToBorder);
+  metadata.set_clamp_z(ClampMode::ClampToHalfway);
+
+  let (sampler, issues) = metadata.sampler_desc();
+  assert_eq!(sampler.wrap_u, WrapMode::Repeat);
+  assert_eq!(sampler.wrap_v, WrapMode::ClampToBorder);
+  assert_eq!(sampler.wrap_w, WrapMode::ClampToEdge);
+  assert_eq!(sampler.max_lod, 6.0);
+  assert_eq!(issues.len(), 1);
+  assert_eq!(issues[0].field, "clamp_z");
+
+  let (gltf, issues) = sampler.to_gltf();
+  assert_eq!(
+    gltf.to_json(),
+    r#"{"magFilter":9729,"minFilter":9987,"wrapS":10497,"wrapT":33071}"#
+  );
+  assert_eq!(issues.len(), 1);
+
+  let d3d11 = sampler.to_d3d11();
+  assert_eq!(d3d11.filter, 0x15);
+  assert_eq!((d3d11.address_u, d3d11.address_v), (1, 4));
+}
+
+#[test]
+fn test_validate_metadata() {
+  let metadata = LayoutRequest::new(Format::Dxt1, 128, 128)
+    .plan()
+    .unwrap()
+    .metadata;
+  assert_eq!(metadata.validate(), vec![]);
+
+  let metadata = metadata
+    .with_kind(TextureKind::Vertex)
+    .with_min_mip_level(9)
+    .with_pitch(1);
+
+  let issues = metadata.validate();
+  let fields: Vec<_> = issues.iter().map(|issue| issue.field).collect();
+
+  assert_eq!(fields, ["kind", "max_mip_level", "pitch"]);
+  assert!(issues.iter().all(|issue| issue.severity == Severity::Error));
+
+  // scanning counts the same issues without formatting them
+  assert_eq!(metadata.count_issues(), (3, 0));
+}
+
+#[test]
+fn test_resource_header_kind() {
+  let vertex: [u32; 8] = [1, 1, 0, 0, 0, 0xFFFF0000, 0x1000_0003, 0x0000_0102];
+  let vertex: Vec<u8> = vertex
+    .iter()
+    .flat_map(|dword| dword.to_be_bytes())
+    .collect();
+
+  let header = ResourceHeader::read_360(&mut Cursor::new(&vertex)).unwrap();
+  let ResourceHeader::VertexBuffer(header) = header else {
+    panic!("expected a vertex buffer");
+  };
+  let fetch_constant = header.fetch_constant();
+  assert_eq!(fetch_constant.address_bytes(), 0x1000_0000);
+  assert_eq!(fetch_constant.size_bytes(), 0x100);
+  assert!(matches!(fetch_constant.endianness(), crate::Endian::_8in32));
+
+  let dds = Dds::new_d3d(D3DFormat::DXT1, 64, 64, None, None, None).unwrap();
+  let mut texture = Cursor::new(Vec::new());
+  TextureHeader::from_dds(&dds, &HeaderOptions::default())
+    .unwrap()
+    .write_360(&mut texture)
+    .unwrap();
+  texture.set_position(0);
+
+  let header = ResourceHeader::read_360(&mut texture).unwrap();
+  assert!(matches!(header, ResourceHeader::Texture(_)));
+  assert_eq!(texture.position(), 52);
+}
+
+#[test]
+fn test_score_header() {
+  let dds = Dds::new_d3d(D3DFormat::DXT1, 64, 64, None, Some(7), None).unwrap();
+  let header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+  assert!(score_header(&header) > 0.9);
+
+  let mut retail = Cursor::new(Vec::new());
+  header.write_360(&mut retail).unwrap();
+
+  // the same bytes read in the wrong byte order don't validate
+  let swapped = TextureHeader::read_le(&mut Cursor::new(retail.into_inner())).unwrap();
+  assert!(score_header(&swapped) < 0.5);
+}
+
+#[test]
+fn test_read_xpr2() {
+  let dds = Dds::new_d3d(D3DFormat::DXT1, 64, 64, None, Some(5), None).unwrap();
+  let header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+  let config = Config::from_metadata(&header.metadata()).unwrap();
+
+  let size = LayoutRequest {
+    mipmap_levels: Some(5),
+    ..LayoutRequest::new(Format::Dxt1, 64, 64)
+  }
+  .plan()
+  .unwrap()
+  .size();
+  let data: Vec<u8> = (0..size).map(|i| i as u8).collect();
+
+  let mut header_bytes = Cursor::new(Vec::new());
+  header.write_360(&mut header_bytes).unwrap();
+
+  // one entry, its header at 0x14 and the name behind it, data at 0x800
+  let mut file = Vec::new();
+  file.extend(b"XPR2");
+  for dword in [0x800 - 12, data.len() as u32, 1] {
+    file.extend(dword.to_be_bytes());
+  }
+  file.extend(b"TX2D");
+  for dword in [0x14_u32, 52, 0x48] {
+    file.extend(dword.to_be_bytes());
+  }
+  file.extend(header_bytes.into_inner());
+  file.extend(b"font\0");
+  file.resize(0x800, 0);
+  file.extend(&data);
+
+  let xpr2 = Xpr2::read(&mut Cursor::new(&file)).unwrap();
+  assert_eq!(xpr2.resources.len(), 1);
+
+  // sizes past the end of the file fail instead of allocating them
+  let mut truncated = file.clone();
+  truncated[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+  assert!(Xpr2::read(&mut Cursor::new(&truncated)).is_err());
+  let mut truncated = file.clone();
+  truncated[24..28].copy_from_slice(&u32::MAX.to_be_bytes());
+  assert!(Xpr2::read(&mut Cursor::new(&truncated)).is_err());
+
+  let resource = &xpr2.resources[0];
+  assert_eq!(resource.name, "font");
+  assert_eq!(resource.kind, Xpr2Type::Texture2D);
+  assert_eq!(resource.kind.fourcc(), *b"TX2D");
+  let metadata = resource.texture_header().unwrap().unwrap().metadata();
+  assert_eq!(metadata.size(), (64, 64, 1));
+
+  let mut expected = Vec::new();
+  convert_to_dds(&config, &data, &mut expected).unwrap();
+  let mut output = Vec::new();
+  xpr2.convert_to_dds(resource, &mut output).unwrap();
+  assert_eq!(output, expected);
+
+  let user = Xpr2Resource {
+    name: "glyphs".to_string(),
+    kind: Xpr2Type::User,
+    header: Vec::new(),
+  };
+  assert!(matches!(
+    xpr2.convert_to_dds(&user, &mut output),
+    Err(Xpr2Error::NotATexture(_))
+  ));
+
+  // a fetch constant with a format that can't be untiled
+  let mut unsupported = header;
+  let metadata = unsupported.metadata().with_format(TextureFormat::_8);
+  unsupported.set_metadata(&metadata);
+  let mut header_bytes = Cursor::new(Vec::new());
+  unsupported.write_360(&mut header_bytes).unwrap();
+  let resource = Xpr2Resource {
+    name: "font".to_string(),
+    kind: Xpr2Type::Texture2D,
+    header: header_bytes.into_inner(),
+  };
+  assert!(matches!(
+    xpr2.convert_to_dds(&resource, &mut output),
+    Err(Xpr2Error::UnsupportedFormat(TextureFormat::_8))
+  ));
+}
+
+#[test]
+fn test_write_xpr2() {
+  let small = LayoutRequest::new(Format::Dxt1, 64, 64).plan().unwrap();
+  let dds = Dds::new_d3d(D3DFormat::DXT1, 64, 64, None, Some(7), None).unwrap();
+  let header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+
+  let mut header_bytes = Cursor::new(Vec::new());
+  header.write_360(&mut header_bytes).unwrap();
+  let header_bytes = header_bytes.into_inner();
+
+  let mut xpr2 = Xpr2 {
+    resources: vec![
+      Xpr2Resource {
+        name: "font".to_string(),
+        kind: Xpr2Type::Texture2D,
+        header: header_bytes.clone(),
+      },
+      Xpr2Resource {
+        name: "glyphs".to_string(),
+        kind: Xpr2Type::User,
+        header: vec![1, 2, 3, 4, 5, 6],
+      },
+    ],
+    data: vec![0xAB; small.size() as usize],
+  };
+
+  let mut written = Cursor::new(Vec::new());
+  xpr2.write(&mut written).unwrap();
+  let written = written.into_inner();
+  assert_eq!(written.len(), 0x1000 + small.size() as usize);
+
+  let read = Xpr2::read(&mut Cursor::new(&written)).unwrap();
+  assert_eq!(read.resources[0].header, header_bytes);
+  assert_eq!(read.resources[1].name, "glyphs");
+  assert_eq!(read.data, xpr2.data);
+
+  // a larger texture no longer fits and moves behind the old data
+  let large = LayoutRequest::new(Format::Dxt1, 256, 256).plan().unwrap();
+  let dds = Dds::new_d3d(D3DFormat::DXT1, 256, 256, None, Some(9), None).unwrap();
+  let header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+  assert!(matches!(
+    xpr2.replace_texture(1, header.clone(), &[]),
+    Err(Xpr2Error::NotATexture(_))
+  ));
+  assert!(matches!(
+    xpr2.replace_texture(2, header.clone(), &[]),
+    Err(Xpr2Error::MissingResource(2))
+  ));
+  xpr2
+    .replace_texture(0, header, &vec![0xCD; large.size() as usize])
+    .unwrap();
+
+  let metadata = xpr2.resources[0]
+    .texture_header()
+    .unwrap()
+    .unwrap()
+    .metadata();
+  assert_eq!(metadata.base_address(), small.size() >> 12);
+  assert_eq!(
+    metadata.mip_address(),
+    (small.size() + large.base_size) >> 12
+  );
+  assert_eq!(xpr2.data.len(), (small.size() + large.size()) as usize);
+  assert!(xpr2.data[..small.size() as usize].iter().all(|&b| b == 0));
+  assert_eq!(xpr2.resources[1].header, [1, 2, 3, 4, 5, 6]);
+
+  let mut written = Cursor::new(Vec::new());
+  xpr2.write(&mut written).unwrap();
+  let read = Xpr2::read(&mut Cursor::new(written.into_inner())).unwrap();
+  let metadata = read.resources[0]
+    .texture_header()
+    .unwrap()
+    .unwrap()
+    .metadata();
+  assert_eq!(metadata.size(), (256, 256, 1));
+  assert_eq!(metadata.base_address(), small.size() >> 12);
+}
+
+#[test]
+fn test_scan_headers() {
+  let dds = Dds::new_d3d(D3DFormat::DXT5, 128, 128, None, Some(8), None).unwrap();
+  let header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+  let mut header_bytes = Cursor::new(Vec::new());
+  header.write_360(&mut header_bytes).unwrap();
+  let header_bytes = header_bytes.into_inner();
+
+  // noise with the type bits of a texture fetch constant everywhere
+  let mut data: Vec<u8> = (0..4096_u32)
+    .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8 & !1 | 2)
+    .collect();
+  data[100..152].copy_from_slice(&header_bytes);
+  data[3000..3052].copy_from_slice(&header_bytes);
+
+  let matches = scan(&data, &ScanOptions::default());
+  let offsets: Vec<_> = matches.iter().map(|found| found.offset).collect();
+  assert_eq!(offsets, [100, 3000]);
+
+  let config = matches[0].config.unwrap();
+  assert!(matches!(config.format, Format::Dxt5));
+  assert_eq!((config.width, config.height), (128, 128));
+  assert_eq!(config.mipmap_levels, Some(8));
+  assert!(matches[0].confidence > 0.9);
+}
+
+#[cfg(feature = "lzx")]
+#[test]
+fn test_lzx_uncompressed_block() {
+  use crate::lzx;
+
+  // no E8 translation, an uncompressed block of 5 bytes, padding to 16 bits
+  let mut chunk = vec![0x00, 0x30, 0x50, 0x00];
+  // repeated offsets
+  chunk.extend([1, 0, 0, 0].repeat(3));
+  chunk.extend(b"hello\0");
+
+  let mut raw = vec![0xFF, 0x00, 0x05, 0x00, chunk.len() as u8];
+  raw.extend(&chunk);
+  assert_eq!(lzx::decompress_raw(&raw, 17).unwrap(), b"hello");
+
+  let mut native = Vec::new();
+  for dword in [0x0FF5_12EE, 0x0103_0000, 0, 0, 0x2_0000, 0x8_0000, 0, 5, 0] {
+    native.extend(u32::to_be_bytes(dword));
+  }
+  for dword in [
+    raw.len() as u32 + 4,
+    0x8000,
+    raw.len() as u32 + 4,
+    raw.len() as u32,
+  ] {
+    native.extend(dword.to_be_bytes());
+  }
+  native.extend(&raw);
+  assert_eq!(lzx::decompress(&native).unwrap(), b"hello");
+
+  assert_eq!(
+    lzx::decompress_raw(&raw[..raw.len() - 3], 17),
+    Err(lzx::LzxError::UnexpectedEnd)
+  );
+}
+
+#[cfg(feature = "lzx")]
+#[test]
+fn test_lzx_round_trip() {
+  use crate::lzx::{self, CompressionLevel};
+
+  // tiled texture data is mostly repetitive blocks with some noise in between
+  let data: Vec<u8> = noise(300_000)
+    .into_iter()
+    .enumerate()
+    .map(|(i, noise)| {
+      if i % 1024 < 700 {
+        (i % 24) as u8
+      } else {
+        noise
+      }
+    })
+    .collect();
+
+  let mut sizes = Vec::new();
+
+  for level in [
+    CompressionLevel::Store,
+    CompressionLevel::Fast,
+    CompressionLevel::Normal,
+    CompressionLevel::Best,
+  ] {
+    let mut raw = Vec::new();
+    lzx::compress_raw(&mut data.as_slice(), &mut raw, 17, level).unwrap();
+    assert_eq!(lzx::decompress_raw(&raw, 17).unwrap(), data);
+    sizes.push(raw.len());
+
+    let mut native = Cursor::new(Vec::new());
+    lzx::compress_native(&mut Cursor::new(&data[..100_000]), &mut native, level).unwrap();
+    assert_eq!(
+      lzx::decompress(&native.into_inner()).unwrap(),
+      &data[..100_000]
+    );
+  }
+
+  assert!(sizes[0] > data.len());
+  assert!(sizes[1] < data.len() / 2);
+  assert!(sizes[3] <= sizes[1]);
+}
+
+#[cfg(feature = "ue3")]
+#[test]
+fn test_read_ue3_texture() {
+  use miniz_oxide::deflate::compress_to_vec_zlib;
+
+  use crate::format::get_format_data;
+  use crate::lzx::{self, CompressionLevel};
+  use crate::ue3::{MipData, PackageInfo, Texture2D, Ue3Error};
+
+  let names = [
+    "None",
+    "SizeX",
+    "SizeY",
+    "IntProperty",
+    "Format",
+    "ByteProperty",
+    "EPixelFormat",
+    "PF_DXT1",
+    "TextureFileCacheName",
+    "NameProperty",
+    "Textures",
+  ];
+  let package = PackageInfo {
+    names: names.iter().map(|name| name.to_string()).collect(),
+    version: 845,
+    endian: binrw::Endian::Big,
+  };
+
+  let format_data = get_format_data(&Format::Dxt1);
+  let tiled = |size: u32| {
+    let blocks = size / 4;
+    let linear: Vec<u8> = (0..blocks * blocks * 8).map(|i| (i / 8) as u8).collect();
+    let mut tiled = vec![0; linear.len()];
+    crate::tile::tile(&mut tiled, &linear, &format_data, blocks, blocks, 0, 0);
+    (linear, tiled)
+  };
+
+  // bulk data compressed in a single block
+  let compressed = |block: Vec<u8>, uncompressed: usize| {
+    let mut data = Vec::new();
+    for value in [
+      0x9E2A83C1,
+      0x20000,
+      block.len() as u32,
+      uncompressed as u32,
+      block.len() as u32,
+      uncompressed as u32,
+    ] {
+      data.extend(value.to_be_bytes());
+    }
+    data.extend(block);
+    data
+  };
+
+  let (linear_0, tiled_0) = tiled(256);
+  let mut lzx_block = Vec::new();
+  lzx::compress_raw(
+    &mut tiled_0.as_slice(),
+    &mut lzx_block,
+    17,
+    CompressionLevel::Fast,
+  )
+  .unwrap();
+  let mip_0 = compressed(lzx_block, tiled_0.len());
+
+  // a stored deflate block
+  let (linear_1, tiled_1) = tiled(128);
+  let zlib_block = compress_to_vec_zlib(&tiled_1, 0);
+  let mip_1 = compressed(zlib_block, tiled_1.len());
+
+  let mut tfc = vec![0xCD; 100];
+  tfc.extend(&mip_1);
+
+  let mut export = Vec::new();
+  let int = |export: &mut Vec<u8>, value: i32| export.extend(value.to_be_bytes());
+  let name =
+    |export: &mut Vec<u8>, index: i32| export.extend([index, 0].map(i32::to_be_bytes).concat());
+
+  int(&mut export, 7);
+  for (property, value) in [(1, 256), (2, 256)] {
+    name(&mut export, property);
+    name(&mut export, 3);
+    int(&mut export, 4);
+    int(&mut export, 0);
+    int(&mut export, value);
+  }
+  name(&mut export, 4);
+  name(&mut export, 5);
+  int(&mut export, 8);
+  int(&mut export, 0);
+  name(&mut export, 6);
+  name(&mut export, 7);
+  name(&mut export, 8);
+  name(&mut export, 9);
+  int(&mut export, 8);
+  int(&mut export, 0);
+  name(&mut export, 10);
+  name(&mut export, 0);
+
+  // source art
+  for value in [0x20, 0, 0, 0] {
+    int(&mut export, value);
+  }
+
+  int(&mut export, 3);
+  for value in [0x80, 0, mip_0.len() as i32, 0] {
+    int(&mut export, value);
+  }
+  export.extend(&mip_0);
+  int(&mut export, 256);
+  int(&mut export, 256);
+  for value in [0x03, 0, mip_1.len() as i32, 100] {
+    int(&mut export, value);
+  }
+  int(&mut export, 128);
+  int(&mut export, 128);
+  for value in [0x21, 0, 0, 0] {
+    int(&mut export, value);
+  }
+  int(&mut export, 64);
+  int(&mut export, 64);
+
+  let mut texture = Texture2D::read(&export, &package).unwrap();
+  assert_eq!((texture.size_x, texture.size_y), (256, 256));
+  assert!(matches!(texture.format, Format::Dxt1));
+  assert_eq!(texture.texture_file_cache.as_deref(), Some("Textures"));
+  assert_eq!(texture.mips.len(), 3);
+  assert!(matches!(&texture.mips[0].data, MipData::Loaded(data) if *data == tiled_0));
+  assert!(matches!(
+    texture.mips[1].data,
+    MipData::External { offset: 100, .. }
+  ));
+  assert!(matches!(texture.mips[2].data, MipData::Empty));
+  assert_eq!(texture.mips[0].untile(Format::Dxt1).unwrap(), linear_0);
+
+  let mut output = Vec::new();
+  assert!(texture.convert_to_dds(&mut output).is_err());
+
+  texture.load_external(&mut Cursor::new(tfc)).unwrap();
+  assert_eq!(texture.mips[1].untile(Format::Dxt1).unwrap(), linear_1);
+
+  texture.convert_to_dds(&mut output).unwrap();
+  let dds = Dds::read(&mut Cursor::new(output)).unwrap();
+  assert_eq!(dds.get_num_mipmap_levels(), 2);
+  assert_eq!(dds.data, [linear_0, linear_1].concat());
+
+  // data shorter than the padded mip
+  texture.mips[1].data = MipData::Loaded(tiled_1[..100].to_vec());
+  assert!(matches!(
+    texture.mips[1].untile(Format::Dxt1),
+    Err(Ue3Error::SizeMismatch)
+  ));
+
+  // a summary announcing more blocks than the data holds
+  let mut corrupt = mip_1.clone();
+  corrupt[12..16].copy_from_slice(&u32::MAX.to_be_bytes());
+  texture.mips[1].data = MipData::External {
+    flags: 0x03,
+    offset: 0,
+    size: corrupt.len() as u32,
+  };
+  assert!(matches!(
+    texture.load_external(&mut Cursor::new(&corrupt)),
+    Err(Ue3Error::InvalidBulkData)
+  ));
+
+  // a size beyond the end of the file fails to read instead of being allocated
+  texture.mips[1].data = MipData::External {
+    flags: 0x03,
+    offset: 0,
+    size: u32::MAX,
+  };
+  assert!(matches!(
+    texture.load_external(&mut Cursor::new(&corrupt)),
+    Err(Ue3Error::Read(_))
+  ));
+}
+
+#[test]
+fn test_tile_texture_round_trip() {
+  let requests = [
+    // wider than one tile, with a pitch beyond the width
+    LayoutRequest {
+      texture_type: TextureType::Cube,
+      ..LayoutRequest::new(Format::Dxt1, 200, 300)
+    },
+    LayoutRequest {
+      texture_type: TextureType::Array,
+      depth: 3,
+      tiled: false,
+      ..LayoutRequest::new(Format::RGBA8, 64, 48)
+    },
+    LayoutRequest::new(Format::Dxt5, 128, 128),
+  ];
+
+  for request in requests {
+    let config = request.plan().unwrap().config;
+
+    let src = noise(request.plan().unwrap().size() as usize);
+
+    let texture = untile_texture(&config, &src).unwrap();
+    let depth = config.depth.unwrap_or(1) as usize;
+    assert_eq!(texture.texture_type, request.texture_type);
+    assert_eq!(
+      texture.surfaces.len(),
+      depth * config.mipmap_levels.unwrap() as usize
+    );
+
+    let last = texture.surfaces.last().unwrap();
+    assert_eq!(last.mip, config.mipmap_levels.unwrap() - 1);
+    assert_eq!((last.width, last.height), (1, 1));
+    assert_eq!(texture.surface(1, 0, 0).unwrap().width, request.width / 2);
+
+    // only the bytes of the surfaces survive, padding comes back as zeroes
+    let tiled = tile_texture(&config, &texture).unwrap();
+    assert_eq!(tiled.len(), src.len());
+    let retiled = untile_texture(&config, &tiled).unwrap();
+    for (a, b) in texture.surfaces.iter().zip(&retiled.surfaces) {
+      assert_eq!(a.data, b.data);
+    }
+    assert_eq!(tile_texture(&config, &retiled).unwrap(), tiled);
+  }
+}
+
+#[test]
+fn test_texture_to_dds() {
+  let layout = LayoutRequest {
+    texture_type: TextureType::Cube,
+    mipmap_levels: Some(3),
+    ..LayoutRequest::new(Format::Dxt1, 64, 64)
+  }
+  .plan()
+  .unwrap();
+  let config = layout.config;
+
+  let src: Vec<u8> = (0..layout.size()).map(|i| (i / 8) as u8).collect();
+  let texture = untile_texture(&config, &src).unwrap();
+
+  let dds = texture.to_dds().unwrap();
+  assert_eq!(dds.get_num_array_layers(), 6);
+  assert_eq!(dds.get_num_mipmap_levels(), 3);
+  assert_eq!(dds.data.len(), 6 * (2048 + 512 + 128));
+  assert_eq!(
+    &dds.data[2688..4736],
+    texture.surface(0, 0, 1).unwrap().data
+  );
+
+  let parsed = Texture::from_dds(&dds).unwrap();
+  assert_eq!(parsed.texture_type, TextureType::Cube);
+  for (a, b) in texture.surfaces.iter().zip(&parsed.surfaces) {
+    assert_eq!((a.mip, a.layer, a.face), (b.mip, b.layer, b.face));
+    assert_eq!(a.data, b.data);
+  }
+
+  let mut output = Vec::new();
+  convert_to_dds(&config, &src, &mut output).unwrap();
+  let mut expected = Vec::new();
+  dds.write(&mut expected).unwrap();
+  assert_eq!(output, expected);
+}
+
+#[test]
+fn test_texture_errors() {
+  let config = Config::new(Format::Dxt1, 200, 300);
+  let layout = LayoutRequest {
+    mipmap_levels: Some(1),
+    ..LayoutRequest::new(Format::Dxt1, 200, 300)
+  }
+  .plan()
+  .unwrap();
+  assert_eq!(config.pitch, layout.config.pitch);
+  assert_eq!(config.mipmap_levels, None);
+
+  // the last tile row of the base level is cut off
+  let src = vec![0; layout.size() as usize - 4096];
+  assert!(matches!(
+    untile_texture(&config, &src),
+    Err(TextureError::DataTooShort {
+      expected: 49152,
+      found: 45056,
+    })
+  ));
+  assert!(matches!(
+    convert_to_dds(&config, &src, &mut Vec::new()),
+    Err(TextureError::DataTooShort { .. })
+  ));
+
+  let src = vec![0; layout.size() as usize];
+  let mut texture = untile_texture(&config, &src).unwrap();
+  texture.format = Format::Dxt5;
+  assert!(matches!(
+    tile_texture(&config, &texture),
+    Err(TextureError::FormatMismatch {
+      expected: Format::Dxt1,
+      found: Format::Dxt5,
+    })
+  ));
+
+  texture.format = Format::Dxt1;
+  texture.surfaces[0].data.truncate(4096);
+  assert!(matches!(
+    tile_texture(&config, &texture),
+    Err(TextureError::DataTooShort {
+      expected: 30000,
+      found: 4096,
+    })
+  ));
+
+  texture.surfaces[0].data.resize(30008, 0);
+  assert!(matches!(
+    tile_texture(&config, &texture),
+    Err(TextureError::MissingSurface { mip: 0, .. })
+  ));
+
+  texture.surfaces.clear();
+  assert!(matches!(
+    tile_texture(&config, &texture),
+    Err(TextureError::MissingSurface {
+      mip: 0,
+      layer: 0,
+      face: 0
+    })
+  ));
+
+  // arrays need a DX10 header
+  let layout = LayoutRequest {
+    texture_type: TextureType::Array,
+    depth: 2,
+    ..LayoutRequest::new(Format::Dxt1, 64, 64)
+  }
+  .plan()
+  .unwrap();
+  let texture = untile_texture(&layout.config, &vec![0; layout.size() as usize]).unwrap();
+  assert!(matches!(
+    texture.to_dds(),
+    Err(TextureError::UnsupportedTextureType(TextureType::Array))
+  ));
+
+  let mut dds = Dds::new_d3d(D3DFormat::DXT1, 64, 64, None, Some(1), None).unwrap();
+  let mut file = Vec::new();
+  dds.write(&mut file).unwrap();
+  let config = Config {
+    mipmap_levels: Some(3),
+    ..Config::new(Format::Dxt1, 64, 64)
+  };
+  assert!(matches!(
+    convert_from_dds::<_, Vec<u8>>(&config, &mut file.as_slice()),
+    Err(TextureError::MissingMipmapLevels {
+      expected: 3,
+      found: 1,
+    })
+  ));
+  let config = Config::new(Format::Dxt1, 32, 64);
+  assert!(matches!(
+    convert_from_dds::<_, Vec<u8>>(&config, &mut file.as_slice()),
+    Err(TextureError::SizeMismatch {
+      expected: (32, 64, 1),
+      found: (64, 64, 1),
+    })
+  ));
+  let config = Config::new(Format::Dxt5, 64, 64);
+  assert!(matches!(
+    convert_from_dds::<_, Vec<u8>>(&config, &mut file.as_slice()),
+    Err(TextureError::FormatMismatch {
+      expected: Format::Dxt5,
+      found: Format::Dxt1,
+    })
+  ));
+
+  dds.data.truncate(1000);
+  assert!(matches!(
+    Texture::from_dds(&dds),
+    Err(TextureError::DataTooShort {
+      expected: 2048,
+      found: 1000,
+    })
+  ));
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_png_compression() {
+  use crate::image::{ImageFormat, RgbaImage};
+
+  let data = noise(100_000)
+    .into_iter()
+    .enumerate()
+    .map(|(i, noise)| if i % 512 < 400 { (i % 7) as u8 } else { noise })
+    .collect();
+
+  let image = RgbaImage {
+    width: 250,
+    height: 100,
+    data,
+  };
+  let mut png = Vec::new();
+  image.write(ImageFormat::Png, &mut png).unwrap();
+  assert!(png.len() < image.data.len() / 3);
+  assert_eq!(RgbaImage::read(&mut Cursor::new(&png)).unwrap(), image);
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_export_texture() {
+  use crate::image::{export_texture, ExportOptions, ImageFormat, RgbaImage};
+  use crate::{Surface, Swizzle};
+  use miniz_oxide::inflate::decompress_to_vec_zlib as zlib_decompress;
+
+  // red and blue endpoints, the rows use the indices 0, 1, 2 and 3
+  let dxt1 = [0x00, 0xF8, 0x1F, 0x00, 0x00, 0x55, 0xAA, 0xFF];
+  // alpha 255 and 0, interpolated in sevenths, every texel using index 2
+  let dxt5 = [
+    0xFF, 0x00, 0x92, 0x24, 0x49, 0x92, 0x24, 0x49, 0x00, 0xF8, 0x1F, 0x00, 0, 0, 0, 0,
+  ];
+
+  let surface = |width, height, data: &[u8]| Surface {
+    mip: 0,
+    layer: 0,
+    face: 0,
+    width,
+    height,
+    data: data.to_vec(),
+  };
+
+  let image = RgbaImage::from_surface(Format::Dxt1, &surface(4, 4, &dxt1));
+  assert_eq!(&image.data[..4], [255, 0, 0, 255]);
+  assert_eq!(&image.data[16..20], [0, 0, 255, 255]);
+  assert_eq!(&image.data[32..36], [170, 0, 85, 255]);
+  assert_eq!(&image.data[48..52], [85, 0, 170, 255]);
+
+  // only the visible part of the block is kept
+  let mut image = RgbaImage::from_surface(Format::Dxt5, &surface(2, 1, &dxt5));
+  assert_eq!(image.data, [255, 0, 0, 219, 255, 0, 0, 219]);
+
+  image.unpremultiply();
+  assert_eq!(&image.data[..4], [255, 0, 0, 219]);
+  image.apply_swizzle([Swizzle::W, Swizzle::Zero, Swizzle::X, Swizzle::One]);
+  assert_eq!(&image.data[..4], [219, 0, 255, 255]);
+
+  let mut dxt3 = [0x11; 16];
+  dxt3[8..].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]);
+  let mut image = RgbaImage::from_surface(Format::Dxt3, &surface(1, 1, &dxt3));
+  assert_eq!(image.data, [255, 255, 255, 17]);
+  image.data = vec![16, 8, 0, 32];
+  image.unpremultiply();
+  assert_eq!(image.data, [128, 64, 0, 32]);
+
+  let config = LayoutRequest::new(Format::Dxt1, 64, 64)
+    .plan()
+    .unwrap()
+    .config;
+  let texture = untile_texture(&config, &vec![0; 0x8000]).unwrap();
+
+  let images = export_texture(&texture, &ExportOptions::default()).unwrap();
+  assert_eq!(images.len(), 1);
+  assert_eq!(
+    images[0].file_name("texture", &texture, ImageFormat::Png),
+    "texture_mip0.png"
+  );
+
+  let png = &images[0].data;
+  assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+  assert_eq!(&png[12..16], b"IHDR");
+  assert_eq!(&png[16..29], [0, 0, 0, 64, 0, 0, 0, 64, 8, 6, 0, 0, 0]);
+  assert_eq!(&png[29..33], 0xAA69_71DE_u32.to_be_bytes());
+
+  let length = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+  assert_eq!(&png[37..41], b"IDAT");
+  let pixels = zlib_decompress(&png[41..41 + length]).unwrap();
+  assert_eq!(pixels.len(), 64 * (1 + 64 * 4));
+  assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xAE\x42\x60\x82");
+
+  let options = ExportOptions {
+    format: ImageFormat::Tga,
+    all_mips: true,
+    ..Default::default()
+  };
+  let images = export_texture(&texture, &options).unwrap();
+  assert_eq!(images.len(), 7);
+  assert_eq!(images[6].mip, 6);
+  assert_eq!(images[6].data.len(), 18 + 4);
+  assert_eq!(&images[6].data[12..18], [1, 0, 1, 0, 32, 0x28]);
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_read_images() {
+  use crate::image::{ImageError, ImageFormat, RgbaImage};
+
+  let image = RgbaImage {
+    width: 13,
+    height: 7,
+    data: noise(13 * 7 * 4)
+      .into_iter()
+      .enumerate()
+      .map(|(i, noise)| if i % 3 == 0 { noise } else { i as u8 })
+      .collect(),
+  };
+
+  for format in [ImageFormat::Png, ImageFormat::Tga] {
+    let mut file = Vec::new();
+    image.write(format, &mut file).unwrap();
+    assert_eq!(RgbaImage::read(&mut file.as_slice()).unwrap(), image);
+  }
+
+  // 3x2, two bits per palette index, the first entry half transparent
+  let palette: &[u8] = &[
+    137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 3, 0, 0, 0, 2, 2, 3, 0,
+    0, 0, 224, 26, 142, 137, 0, 0, 0, 12, 80, 76, 84, 69, 255, 0, 0, 0, 255, 0, 0, 0, 255, 10, 20,
+    30, 34, 136, 41, 4, 0, 0, 0, 2, 116, 82, 78, 83, 128, 255, 96, 18, 186, 254, 0, 0, 0, 12, 73,
+    68, 65, 84, 120, 156, 99, 144, 96, 120, 2, 0, 1, 48, 0, 253, 86, 205, 28, 115, 0, 0, 0, 0, 73,
+    69, 78, 68, 174, 66, 96, 130,
+  ];
+  let image = RgbaImage::read(&mut &palette[..]).unwrap();
+  assert_eq!((image.width, image.height), (3, 2));
+  assert_eq!(
+    image.data,
+    [
+      255, 0, 0, 128, 0, 255, 0, 255, 0, 0, 255, 255, 10, 20, 30, 255, 0, 0, 255, 255, 0, 255, 0,
+      255
+    ]
+  );
+
+  // 1x2 gray and alpha with 16 bits each, the second row filtered by the one above
+  let gray: &[u8] = &[
+    137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 1, 0, 0, 0, 2, 16, 4, 0,
+    0, 0, 99, 24, 162, 239, 0, 0, 0, 18, 73, 68, 65, 84, 120, 156, 99, 16, 50, 249, 207, 192, 196,
+    200, 192, 192, 0, 0, 9, 83, 1, 73, 234, 141, 6, 213, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96,
+    130,
+  ];
+  let image = RgbaImage::read(&mut &gray[..]).unwrap();
+  assert_eq!(image.data, [18, 18, 18, 255, 19, 19, 19, 255]);
+
+  let mut damaged = gray.to_vec();
+  damaged[20] ^= 1;
+  assert!(RgbaImage::read(&mut damaged.as_slice()).is_err());
+
+  // a header with a valid checksum, but a size the data doesn't match
+  let with_size = |width: u32, height: u32| {
+    let mut file = gray.to_vec();
+    file[16..20].copy_from_slice(&width.to_be_bytes());
+    file[20..24].copy_from_slice(&height.to_be_bytes());
+    let mut crc = !0_u32;
+    for &byte in &file[12..29] {
+      crc ^= byte as u32;
+      for _ in 0..8 {
+        crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+      }
+    }
+    file[29..33].copy_from_slice(&(!crc).to_be_bytes());
+    RgbaImage::read(&mut file.as_slice())
+  };
+  assert_eq!(with_size(1, 2).unwrap().data, image.data);
+  assert!(matches!(
+    with_size(u32::MAX, u32::MAX),
+    Err(ImageError::Malformed("PNG image too large"))
+  ));
+  assert!(matches!(
+    with_size(1, 1),
+    Err(ImageError::Malformed("too much PNG image data"))
+  ));
+
+  // run-length encoded, rows from the bottom
+  let mut tga = vec![0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0, 2, 0, 24, 0];
+  tga.extend([0x82, 1, 2, 3, 0x00, 4, 5, 6, 0x01, 7, 8, 9, 10, 11, 12]);
+  let image = RgbaImage::read(&mut tga.as_slice()).unwrap();
+  assert_eq!(
+    image.data,
+    [6, 5, 4, 255, 9, 8, 7, 255, 12, 11, 10, 255, 3, 2, 1, 255, 3, 2, 1, 255, 3, 2, 1, 255]
+  );
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_import_texture() {
+  use crate::image::{import_texture, ImageError, ImportOptions, Quality, RgbaImage};
+
+  // smooth gradients with some noise, the kind of content block compression expects
+  let noise = noise(64 * 64);
+  let mut data = Vec::new();
+  for y in 0..64_u32 {
+    for x in 0..64_u32 {
+      let noise = noise[(y * 64 + x) as usize] % 24;
+      data.extend([
+        (x * 4) as u8 ^ noise,
+        (y * 4) as u8,
+        ((x + y) * 2) as u8 / 2 + noise,
+        (255 - x * 4) as u8,
+      ]);
+    }
+  }
+  let image = RgbaImage {
+    width: 64,
+    height: 64,
+    data,
+  };
+
+  for (format, d3d_format) in [
+    (Format::Dxt1, D3DFormat::DXT1),
+    (Format::Dxt3, D3DFormat::DXT3),
+    (Format::Dxt5, D3DFormat::DXT5),
+  ] {
+    let dds = Dds::new_d3d(d3d_format, 64, 64, None, Some(7), None).unwrap();
+    let header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+    let config = Config::from_metadata(&header.metadata()).unwrap();
+
+    // low alpha would make DXT1 punch pixels through
+    let mut image = image.clone();
+    if format == Format::Dxt1 {
+      image
+        .data
+        .chunks_exact_mut(4)
+        .for_each(|pixel| pixel[3] = 255);
+    }
+
+    let mut errors = Vec::new();
+
+    for quality in [Quality::Fast, Quality::Normal, Quality::Best] {
+      let options = ImportOptions {
+        quality,
+        perceptual: false,
+        ..Default::default()
+      };
+      let tiled = import_texture(&image, &header, &options).unwrap().data;
+
+      let texture = untile_texture(&config, &tiled).unwrap();
+      assert_eq!(texture.surfaces.len(), 7);
+      assert_eq!(texture.format, format);
+
+      let decoded = RgbaImage::from_surface(format, &texture.surfaces[0]);
+      let channels = if format == Format::Dxt1 { 3 } else { 4 };
+      let error: u64 = decoded
+        .data
+        .chunks_exact(4)
+        .zip(image.data.chunks_exact(4))
+        .map(|(a, b)| {
+          (0..channels)
+            .map(|c| (a[c] as i64 - b[c] as i64).pow(2) as u64)
+            .sum::<u64>()
+        })
+        .sum();
+      errors.push(error as f64 / (64.0 * 64.0 * channels as f64));
+
+      // the smallest mip is the average of the image
+      let last = &texture.surfaces[6];
+      let decoded = RgbaImage::from_surface(format, last);
+      assert!((decoded.data[1] as i32 - 126).abs() <= 4);
+    }
+
+    assert!(errors[0] < 40.0, "{:?} {:?}", format, errors);
+    assert!(errors[1] <= errors[0], "{:?} {:?}", format, errors);
+    assert!(errors[2] <= errors[1], "{:?} {:?}", format, errors);
+  }
+
+  // DXT1 keeps punch-through alpha
+  let mut image = RgbaImage {
+    width: 4,
+    height: 4,
+    data: [200, 100, 50, 255].repeat(16),
+  };
+  image.data[3] = 0;
+  let dds = Dds::new_d3d(D3DFormat::DXT1, 4, 4, None, Some(1), None).unwrap();
+  let header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+  let config = Config::from_metadata(&header.metadata()).unwrap();
+  let tiled = import_texture(&image, &header, &ImportOptions::default())
+    .unwrap()
+    .data;
+  let texture = untile_texture(&config, &tiled).unwrap();
+  let decoded = RgbaImage::from_surface(Format::Dxt1, &texture.surfaces[0]);
+  assert_eq!(&decoded.data[..4], [0, 0, 0, 0]);
+  assert_eq!(decoded.data[7], 255);
+
+  let image = RgbaImage {
+    width: 2,
+    height: 2,
+    data: vec![0; 16],
+  };
+  assert!(matches!(
+    import_texture(&image, &header, &ImportOptions::default()),
+    Err(ImageError::SizeMismatch { .. })
+  ));
+
+  for (width, height, len) in [(0, 4, 0), (4, 0, 0), (4, 4, 60), (4, 4, 68)] {
+    let image = RgbaImage {
+      width,
+      height,
+      data: vec![0; len],
+    };
+    assert!(matches!(
+      import_texture(&image, &header, &ImportOptions::default()),
+      Err(ImageError::Malformed(_))
+    ));
+  }
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_mipmap_filters() {
+  use crate::image::{import_texture, ImportOptions, MipmapFilter, RgbaImage};
+  use crate::TextureSign;
+
+  let checkerboard = RgbaImage {
+    width: 16,
+    height: 16,
+    data: (0..16 * 16)
+      .flat_map(|i| match (i % 16 + i / 16) % 2 {
+        0 => [0, 0, 0, 255],
+        _ => [255, 255, 255, 255],
+      })
+      .collect(),
+  };
+
+  let dds = Dds::new_d3d(D3DFormat::A8R8G8B8, 16, 16, None, Some(5), None).unwrap();
+  let linear = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+  let mut srgb = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+  srgb.set_metadata(
+    &linear
+      .metadata()
+      .with_sign_x(TextureSign::Gamma)
+      .with_sign_y(TextureSign::Gamma)
+      .with_sign_z(TextureSign::Gamma),
+  );
+
+  for mipmap_filter in [
+    MipmapFilter::Box,
+    MipmapFilter::Kaiser,
+    MipmapFilter::Lanczos,
+  ] {
+    let options = ImportOptions {
+      mipmap_filter,
+      ..Default::default()
+    };
+
+    for (header, gray) in [(&linear, 128), (&srgb, 188)] {
+      let config = Config::from_metadata(&header.metadata()).unwrap();
+      let tiled = import_texture(&checkerboard, header, &options)
+        .unwrap()
+        .data;
+      let texture = untile_texture(&config, &tiled).unwrap();
+
+      // away from the edges every filter averages the squares evenly, half way between
+      // two values
+      let mip = RgbaImage::from_surface(Format::RGBA8, texture.surface(1, 0, 0).unwrap());
+      let offset = (4 * 8 + 4) * 4;
+      assert!(mip.data[offset..offset + 3]
+        .iter()
+        .all(|&value| value == gray || value == gray - 1));
+      assert_eq!(mip.data[offset + 3], 255);
+
+      let smallest = RgbaImage::from_surface(Format::RGBA8, texture.surface(4, 0, 0).unwrap());
+      assert!((smallest.data[0] as i32 - gray as i32).abs() <= 2);
+      assert_eq!(smallest.data[3], 255);
+    }
+  }
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_import_dds() {
+  use crate::image::{import_dds, ConversionStep, ImageError, ImportOptions, Loss, RgbaImage};
+
+  // red everywhere, the first of two endpoints for every texel
+  let block = [0x00, 0xF8, 0x1F, 0x00, 0, 0, 0, 0];
+  let mut base = Dds::new_d3d(D3DFormat::DXT1, 64, 64, None, Some(1), None).unwrap();
+  base.data = block.repeat(base.data.len() / 8);
+
+  let dds = Dds::new_d3d(D3DFormat::DXT1, 64, 64, None, Some(7), None).unwrap();
+  let header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+  let config = Config::from_metadata(&header.metadata()).unwrap();
+
+  let imported = import_dds(&base, &header, &ImportOptions::default()).unwrap();
+  let texture = untile_texture(&config, &imported.data).unwrap();
+  assert_eq!(texture.surfaces.len(), 7);
+  assert_eq!(texture.surfaces[0].data, base.data);
+
+  // the base level is kept, only the generated mips are encoded
+  assert_eq!(
+    imported.conversion,
+    [
+      ConversionStep {
+        from: Format::Dxt1,
+        to: Format::RGBA8,
+        losses: vec![],
+      },
+      ConversionStep {
+        from: Format::RGBA8,
+        to: Format::Dxt1,
+        losses: vec![Loss::Color],
+      },
+    ]
+  );
+
+  for surface in &texture.surfaces {
+    let image = RgbaImage::from_surface(Format::Dxt1, surface);
+    assert!(image
+      .data
+      .chunks_exact(4)
+      .all(|pixel| pixel == [255, 0, 0, 255]));
+  }
+
+  // surplus mips are dropped, the same way convert_from_dds does
+  let mut full = texture.to_dds().unwrap();
+  let three = Dds::new_d3d(D3DFormat::DXT1, 64, 64, None, Some(3), None).unwrap();
+  let header = TextureHeader::from_dds(&three, &HeaderOptions::default()).unwrap();
+  let config = Config::from_metadata(&header.metadata()).unwrap();
+
+  let tiled = import_dds(&full, &header, &ImportOptions::default())
+    .unwrap()
+    .data;
+  let mut file = Vec::new();
+  full.write(&mut file).unwrap();
+  assert_eq!(
+    tiled,
+    crate::convert_from_dds::<_, Vec<u8>>(&config, &mut file.as_slice()).unwrap()
+  );
+  assert_eq!(untile_texture(&config, &tiled).unwrap().surfaces.len(), 3);
+
+  full.header.width = 32;
+  assert!(matches!(
+    import_dds(&full, &header, &ImportOptions::default()),
+    Err(ImageError::SizeMismatch { .. })
+  ));
+
+  base.data.truncate(100);
+  assert!(matches!(
+    import_dds(&base, &header, &ImportOptions::default()),
+    Err(ImageError::Texture(TextureError::DataTooShort { .. }))
+  ));
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_import_conversion() {
+  use crate::image::{import_dds, ConversionStep, ImportOptions, Loss, RgbaImage};
+
+  let mut rgba = Dds::new_d3d(D3DFormat::A8R8G8B8, 64, 64, None, Some(1), None).unwrap();
+  rgba.data = (0..64 * 64)
+    .flat_map(|i| {
+      [
+        (i / 64 * 4) as u8,
+        (i % 64 * 4) as u8,
+        128,
+        (i % 64 * 4) as u8,
+      ]
+    })
+    .collect();
+  let source = RgbaImage::from_surface(
+    Format::RGBA8,
+    &Texture::from_dds(&rgba).unwrap().surfaces[0],
+  );
+
+  let header = |format| {
+    let dds = Dds::new_d3d(format, 64, 64, None, Some(7), None).unwrap();
+    TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap()
+  };
+
+  let dxt5 = header(D3DFormat::DXT5);
+  let imported = import_dds(&rgba, &dxt5, &ImportOptions::default()).unwrap();
+  assert_eq!(
+    imported.conversion,
+    [ConversionStep {
+      from: Format::RGBA8,
+      to: Format::Dxt5,
+      losses: vec![Loss::Color, Loss::AlphaInterpolated],
+    }]
+  );
+
+  let config = Config::from_metadata(&dxt5.metadata()).unwrap();
+  let texture = untile_texture(&config, &imported.data).unwrap();
+  let decoded = RgbaImage::from_surface(Format::Dxt5, &texture.surfaces[0]);
+  let error: i32 = decoded
+    .data
+    .iter()
+    .zip(&source.data)
+    .map(|(&a, &b)| (a as i32 - b as i32).abs())
+    .sum();
+  assert!(error < 3 * decoded.data.len() as i32, "{}", error);
+
+  // DXT5 into a DXT1 slot goes through RGBA8 and keeps only one bit of alpha
+  let dxt1 = header(D3DFormat::DXT1);
+  let imported = import_dds(&texture.to_dds().unwrap(), &dxt1, &ImportOptions::default()).unwrap();
+  assert_eq!(
+    imported.conversion,
+    [
+      ConversionStep {
+        from: Format::Dxt5,
+        to: Format::RGBA8,
+        losses: vec![],
+      },
+      ConversionStep {
+        from: Format::RGBA8,
+        to: Format::Dxt1,
+        losses: vec![Loss::Color, Loss::AlphaToOneBit],
+      },
+    ]
+  );
+
+  let config = Config::from_metadata(&dxt1.metadata()).unwrap();
+  let texture = untile_texture(&config, &imported.data).unwrap();
+  let decoded = RgbaImage::from_surface(Format::Dxt1, &texture.surfaces[0]);
+  assert!(decoded
+    .data
+    .chunks_exact(4)
+    .all(|pixel| pixel[3] == 0 || pixel[3] == 255));
+
+  let imported = import_dds(&texture.to_dds().unwrap(), &dxt1, &ImportOptions::default()).unwrap();
+  assert!(imported.conversion.is_empty());
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_import_resize() {
+  use crate::image::{
+    import_dds, import_texture, ImageError, ImportOptions, ResizeMode, RgbaImage,
+  };
+
+  let dds = Dds::new_d3d(D3DFormat::DXT1, 64, 64, None, Some(7), None).unwrap();
+  let mut header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+  let metadata = header.metadata().with_clamp_x(ClampMode::ClampToBorder);
+  header.set_metadata(&metadata);
+
+  // a texture further into the file, imported data starts at address 0 in every mode
+  let (base_address, mip_address) = (metadata.base_address(), metadata.mip_address());
+  let mut moved = header.clone();
+  moved.set_metadata(
+    &metadata
+      .with_base_address(base_address + 16)
+      .with_mip_address(mip_address + 16),
+  );
+
+  let image = RgbaImage {
+    width: 128,
+    height: 128,
+    data: (0..128 * 128)
+      .flat_map(|i| [(i % 128 * 2) as u8, (i / 128 * 2) as u8, 64, 255])
+      .collect(),
+  };
+
+  let options = |resize| ImportOptions {
+    resize,
+    ..Default::default()
+  };
+
+  assert!(matches!(
+    import_texture(&image, &header, &options(ResizeMode::Reject)),
+    Err(ImageError::SizeMismatch {
+      expected: (64, 64),
+      found: (128, 128)
+    })
+  ));
+
+  let resampled = import_texture(&image, &moved, &options(ResizeMode::Resample)).unwrap();
+  let resize = resampled.resize.unwrap();
+  assert_eq!(
+    (resize.image_size, resize.texture_size),
+    ((128, 128), (64, 64))
+  );
+  assert_eq!(
+    resampled.header.metadata().into_bytes(),
+    header.metadata().into_bytes()
+  );
+  let unmoved = import_texture(&image, &header, &options(ResizeMode::Resample)).unwrap();
+  assert_eq!(resampled.data, unmoved.data);
+
+  let config = Config::from_metadata(&header.metadata()).unwrap();
+  let texture = untile_texture(&config, &resampled.data).unwrap();
+  let base = RgbaImage::from_surface(Format::Dxt1, &texture.surfaces[0]);
+  assert_eq!(base.width, 64);
+  assert!((base.data[(32 * 64 + 32) * 4] as i32 - 130).abs() <= 8);
+
+  let relayout = import_texture(&image, &moved, &options(ResizeMode::Relayout)).unwrap();
+  let resize = relayout.resize.unwrap();
+  assert_eq!(
+    (resize.image_size, resize.texture_size),
+    ((128, 128), (64, 64))
+  );
+
+  let metadata = relayout.header.metadata();
+  let planned = LayoutRequest {
+    mipmap_levels: Some(7),
+    ..LayoutRequest::new(Format::Dxt1, 128, 128)
+  }
+  .plan()
+  .unwrap()
+  .metadata;
+  assert_eq!(metadata.size(), (128, 128, 1));
+  assert_eq!(metadata.pitch(), planned.pitch());
+  assert_eq!(metadata.mip_address(), planned.mip_address());
+  assert_eq!(metadata.max_mip_level(), 6);
+  assert!(matches!(metadata.clamp_x(), ClampMode::ClampToBorder));
+
+  let config = Config::from_metadata(&metadata).unwrap();
+  let texture = untile_texture(&config, &relayout.data).unwrap();
+  assert_eq!(texture.surfaces.len(), 7);
+  assert_eq!(
+    (texture.surfaces[6].width, texture.surfaces[6].height),
+    (2, 2)
+  );
+
+  // a smaller DDS file can't keep every mip of the header
+  let small = Dds::new_d3d(D3DFormat::DXT1, 16, 16, None, Some(1), None).unwrap();
+  let relayout = import_dds(&small, &header, &options(ResizeMode::Relayout)).unwrap();
+  let metadata = relayout.header.metadata();
+  assert_eq!(metadata.size(), (16, 16, 1));
+  assert_eq!(metadata.max_mip_level(), 4);
+
+  let config = Config::from_metadata(&metadata).unwrap();
+  assert_eq!(
+    untile_texture(&config, &relayout.data)
+      .unwrap()
+      .surfaces
+      .len(),
+    5
+  );
+
+  let resampled = import_dds(&small, &header, &options(ResizeMode::Resample)).unwrap();
+  let texture = untile_texture(
+    &Config::from_metadata(&header.metadata()).unwrap(),
+    &resampled.data,
+  )
+  .unwrap();
+  assert_eq!(texture.surfaces.len(), 7);
+  assert_eq!(texture.surfaces[0].width, 64);
+}
+
+#[cfg(feature = "ktx2")]
+#[test]
+fn test_ktx2_round_trip() {
+  use crate::{convert_from_ktx2, convert_to_ktx2, Ktx2, Ktx2Error, TextureSign};
+
+  let surfaces = |texture: &Texture| {
+    let mut surfaces: Vec<_> = texture
+      .surfaces
+      .iter()
+      .map(|s| (s.mip, s.layer, s.face, s.width, s.height, s.data.clone()))
+      .collect();
+    surfaces.sort_by_key(|s| (s.0, s.1, s.2));
+    surfaces
+  };
+  let dword =
+    |file: &[u8], offset: usize| u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap());
+
+  let layout = LayoutRequest {
+    texture_type: TextureType::Cube,
+    mipmap_levels: Some(3),
+    ..LayoutRequest::new(Format::Dxt1, 32, 32)
+  }
+  .plan()
+  .unwrap();
+  let src: Vec<u8> = (0..layout.size()).map(|i| (i * 7 % 251) as u8).collect();
+  let texture = untile_texture(&layout.config, &src).unwrap();
+
+  let metadata = layout
+    .metadata
+    .clone()
+    .with_sign_x(TextureSign::Gamma)
+    .with_sign_y(TextureSign::Gamma)
+    .with_sign_z(TextureSign::Gamma);
+
+  let mut file = Cursor::new(Vec::new());
+  convert_to_ktx2(&metadata, &src, &mut file).unwrap();
+  let file = file.into_inner();
+
+  assert_eq!(&file[..12], b"\xABKTX 20\xBB\r\n\x1A\n");
+  assert_eq!(dword(&file, 12), 134);
+  assert_eq!(
+    (dword(&file, 32), dword(&file, 36), dword(&file, 40)),
+    (0, 6, 3)
+  );
+
+  // the smallest level comes first
+  let offsets: Vec<u32> = (0..3).map(|level| dword(&file, 80 + level * 24)).collect();
+  assert!(offsets[0] > offsets[1] && offsets[1] > offsets[2]);
+  assert!(offsets.iter().all(|offset| offset % 8 == 0));
+
+  let ktx2 = Ktx2::read(&mut Cursor::new(&file)).unwrap();
+  assert!(ktx2.srgb);
+  assert_eq!(ktx2.texture.texture_type, TextureType::Cube);
+  assert_eq!(surfaces(&ktx2.texture), surfaces(&texture));
+
+  assert_eq!(
+    convert_from_ktx2(&layout.config, &mut Cursor::new(&file)).unwrap(),
+    tile_texture(&layout.config, &texture).unwrap()
+  );
+
+  let convert = |config| convert_from_ktx2(&config, &mut Cursor::new(&file));
+  assert!(matches!(
+    convert(Config {
+      format: Format::Dxt5,
+      ..layout.config
+    }),
+    Err(Ktx2Error::FormatMismatch {
+      expected: Format::Dxt5,
+      found: Format::Dxt1,
+    })
+  ));
+  assert!(matches!(
+    convert(Config {
+      width: 64,
+      ..layout.config
+    }),
+    Err(Ktx2Error::DimensionMismatch {
+      expected: (64, 32, 6),
+      found: (32, 32, 6),
+    })
+  ));
+  assert!(matches!(
+    convert(Config {
+      mipmap_levels: Some(4),
+      ..layout.config
+    }),
+    Err(Ktx2Error::MissingMipmapLevels {
+      expected: 4,
+      found: 3,
+    })
+  ));
+
+  // a corrupt level count is rejected before the level index is read
+  let mut corrupt = file.clone();
+  corrupt[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+  assert!(matches!(
+    Ktx2::read(&mut Cursor::new(&corrupt)),
+    Err(Ktx2Error::Read(binrw::Error::AssertFail { .. }))
+  ));
+
+  // sizes that overflow, are empty or point past the end of the file
+  let crafted = |offset: usize, value: &[u8]| {
+    // behind a few other bytes, so offsets are relative to a nonzero start
+    let mut crafted = [vec![0; 4], file.clone()].concat();
+    crafted[4 + offset..4 + offset + value.len()].copy_from_slice(value);
+    let mut reader = Cursor::new(crafted);
+    reader.set_position(4);
+    Ktx2::read(&mut reader)
+  };
+  assert!(matches!(
+    crafted(20, &u32::MAX.to_le_bytes()),
+    Err(Ktx2Error::SizeMismatch { level: 0 })
+  ));
+  assert!(matches!(
+    crafted(20, &0_u32.to_le_bytes()),
+    Err(Ktx2Error::SizeMismatch { level: 0 })
+  ));
+  assert!(matches!(
+    crafted(80, &u64::MAX.to_le_bytes()),
+    Err(Ktx2Error::SizeMismatch { level: 0 })
+  ));
+  assert!(matches!(
+    crafted(80, &(file.len() as u64 - 8).to_le_bytes()),
+    Err(Ktx2Error::Read(_))
+  ));
+
+  let layout = LayoutRequest {
+    texture_type: TextureType::Array,
+    depth: 3,
+    mipmap_levels: Some(2),
+    ..LayoutRequest::new(Format::RGBA8, 16, 16)
+  }
+  .plan()
+  .unwrap();
+  let src: Vec<u8> = (0..layout.size()).map(|i| (i * 13 % 251) as u8).collect();
+  let texture = untile_texture(&layout.config, &src).unwrap();
+
+  let mut file = Cursor::new(Vec::new());
+  Ktx2 {
+    texture: texture.clone(),
+    srgb: false,
+  }
+  .write(&mut file)
+  .unwrap();
+  let mut file = file.into_inner();
+  assert_eq!((dword(&file, 12), dword(&file, 32)), (44, 3));
+
+  let ktx2 = Ktx2::read(&mut Cursor::new(&file)).unwrap();
+  assert!(!ktx2.srgb);
+  assert_eq!(surfaces(&ktx2.texture), surfaces(&texture));
+
+  // red first, which surfaces store third like A8R8G8B8
+  file[12] = 37;
+  let ktx2 = Ktx2::read(&mut Cursor::new(&file)).unwrap();
+  let (swapped, original) = (&ktx2.texture.surfaces[0].data, &texture.surfaces[0].data);
+  assert_eq!(
+    swapped[..4],
+    [original[2], original[1], original[0], original[3]]
+  );
+}
+
+#[test]
+fn test_dx10_dds() {
+  use dds::{AlphaMode, D3D10ResourceDimension, DxgiFormat};
+
+  let dx10 = |format, layers, cube| {
+    let mut dds = Dds::new_dxgi(
+      format,
+      64,
+      64,
+      None,
+      Some(1),
+      Some(layers),
+      None,
+      cube,
+      D3D10ResourceDimension::Texture2D,
+      AlphaMode::Unknown,
+    )
+    .unwrap();
+    dds.data = (0..dds.data.len()).map(|i| (i % 251) as u8).collect();
+    dds
+  };
+
+  let srgb = dx10(DxgiFormat::BC3_UNorm_sRGB, 1, false);
+  let header = TextureHeader::from_dds(&srgb, &HeaderOptions::default()).unwrap();
+  let metadata = header.metadata();
+  assert!(matches!(metadata.format(), TextureFormat::Dxt4_5));
+  assert!(metadata.is_srgb());
+  assert_eq!(metadata.validate_dds(&srgb), vec![]);
+
+  // the components of a plain BC3 header aren't gamma
+  let unorm = dx10(DxgiFormat::BC3_UNorm, 1, false);
+  let header = TextureHeader::from_dds(&unorm, &HeaderOptions::default()).unwrap();
+  let issues = header.metadata().validate_dds(&srgb);
+  assert_eq!(issues.len(), 1);
+  assert_eq!(issues[0].field, "sign_x");
+  assert_eq!(issues[0].severity, Severity::Error);
+
+  let issues = header
+    .metadata()
+    .validate_dds(&dx10(DxgiFormat::BC1_UNorm, 1, false));
+  assert_eq!(issues[0].field, "format");
+  assert_eq!(issues[0].severity, Severity::Error);
+
+  let unsupported = dx10(DxgiFormat::BC5_UNorm, 1, false);
+  assert_eq!(
+    header.metadata().validate_dds(&unsupported)[0].severity,
+    Severity::Error
+  );
+  let mut file = Vec::new();
+  unsupported.write(&mut file).unwrap();
+  let config = Config::from_metadata(&header.metadata()).unwrap();
+  assert!(matches!(
+    convert_from_dds::<_, Vec<u8>>(&config, &mut file.as_slice()),
+    Err(TextureError::UnsupportedDdsFormat)
+  ));
+  assert!(matches!(
+    TextureHeader::from_dds(&unsupported, &HeaderOptions::default()),
+    Err(TextureError::UnsupportedDdsFormat)
+  ));
+
+  // the signs are checked when tiling against a header
+  let mut file = Vec::new();
+  srgb.write(&mut file).unwrap();
+  assert!(matches!(
+    convert_from_dds_checked(&header.metadata(), &mut file.as_slice()),
+    Err(TextureError::Validation(issues)) if issues[0].field == "sign_x"
+  ));
+  let gamma = TextureHeader::from_dds(&srgb, &HeaderOptions::default()).unwrap();
+  assert_eq!(
+    convert_from_dds_checked(&gamma.metadata(), &mut file.as_slice()).unwrap(),
+    convert_from_dds::<_, Vec<u8>>(&config, &mut file.as_slice()).unwrap()
+  );
+
+  // a DDS file without a DX10 header can't be sRGB, so it isn't checked
+  let legacy = Dds::new_d3d(D3DFormat::DXT5, 64, 64, None, Some(1), None).unwrap();
+  assert_eq!(gamma.metadata().validate_dds(&legacy), vec![]);
+
+  let cube_array = dx10(DxgiFormat::BC1_UNorm, 12, true);
+  assert!(matches!(
+    Texture::from_dds(&cube_array),
+    Err(TextureError::CubeMapArray)
+  ));
+  assert!(matches!(
+    TextureHeader::from_dds(&cube_array, &HeaderOptions::default()),
+    Err(TextureError::CubeMapArray)
+  ));
+  let issues = header.metadata().validate_dds(&cube_array);
+  assert_eq!(issues[0].field, "dimension");
+  assert_eq!(issues[0].severity, Severity::Error);
+
+  // a cube map counts its faces as layers
+  for (dds, texture_type, depth) in [
+    (dx10(DxgiFormat::BC1_UNorm, 3, false), TextureType::Array, 3),
+    (dx10(DxgiFormat::BC1_UNorm, 6, true), TextureType::Cube, 6),
+  ] {
+    let header = TextureHeader::from_dds(&dds, &HeaderOptions::default()).unwrap();
+    let config = Config::from_metadata(&header.metadata()).unwrap();
+    assert_eq!(config.texture_type, texture_type);
+    assert_eq!(config.depth, Some(depth));
+
+    let mut file = Vec::new();
+    dds.write(&mut file).unwrap();
+    let tiled = crate::convert_from_dds::<_, Vec<u8>>(&config, &mut file.as_slice()).unwrap();
+    let texture = untile_texture(&config, &tiled).unwrap();
+    assert_eq!(texture.surfaces.len(), depth as usize);
+    let data: Vec<u8> = texture.surfaces.into_iter().flat_map(|s| s.data).collect();
+    assert_eq!(data, dds.data);
+  }
+
+  // R8G8B8A8 is swapped into the BGRA order of the surfaces
+  let mut rgba = dx10(DxgiFormat::R8G8B8A8_UNorm, 1, false);
+  rgba.data = [1, 2, 3, 4].repeat(64 * 64);
+  let texture = Texture::from_dds(&rgba).unwrap();
+  assert_eq!(texture.surfaces[0].data[..4], [3, 2, 1, 4]);
+}
@@ -0,0 +1,538 @@
+use std::fmt;
+
+use dds::{Caps2, Dds, MiscFlag};
+
+use crate::format::{get_dds_format, get_format_data, get_format_from_dds_file, FormatData};
+use crate::layout::{LayoutError, TextureType};
+use crate::mip_map::TextureInfo;
+use crate::tile::{tile, untile};
+use crate::{build_texture_info, Config, Format, ValidationIssue};
+
+/// Why a texture can't be tiled, untiled or stored in a DDS file.
+#[derive(Debug)]
+pub enum TextureError {
+  Dds(dds::Error),
+  /// A DDS file in a format that can't be tiled, like BC5.
+  UnsupportedDdsFormat,
+  /// Tiled data, the data of a DDS file or that of a surface ending before the
+  /// surfaces of the texture do.
+  DataTooShort {
+    expected: usize,
+    found: usize,
+  },
+  /// A DDS file of another width, height or depth than the texture it's tiled into.
+  SizeMismatch {
+    expected: (u32, u32, u32),
+    found: (u32, u32, u32),
+  },
+  /// A DDS file with fewer mips than the texture it's tiled into.
+  MissingMipmapLevels {
+    expected: u32,
+    found: u32,
+  },
+  /// A texture type the operation doesn't support, like tiled volumes or arrays in a
+  /// DDS file without a DX10 header.
+  UnsupportedTextureType(TextureType),
+  /// A DDS file holding an array of cube maps, which the 360 can't sample.
+  CubeMapArray,
+  /// A texture size the fetch constant can't store.
+  Layout(LayoutError),
+  /// A DDS file that doesn't fit the header, see
+  /// [`TextureMetadata::validate_dds`](crate::TextureMetadata::validate_dds).
+  Validation(Vec<ValidationIssue>),
+  /// A texture of another format than the one it's tiled as.
+  FormatMismatch {
+    expected: Format,
+    found: Format,
+  },
+  /// A surface missing from the texture, or of another size or data length than its
+  /// mip.
+  MissingSurface {
+    mip: u32,
+    layer: u32,
+    face: u32,
+  },
+}
+
+impl fmt::Display for TextureError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TextureError::Dds(error) => write!(f, "{}", error),
+      TextureError::UnsupportedDdsFormat => write!(f, "unsupported DDS format"),
+      TextureError::DataTooShort { expected, found } => write!(
+        f,
+        "texture data is {} bytes, expected were {}",
+        found, expected
+      ),
+      TextureError::SizeMismatch { expected, found } => write!(
+        f,
+        "texture is {}x{}x{}, expected was {}x{}x{}",
+        found.0, found.1, found.2, expected.0, expected.1, expected.2
+      ),
+      TextureError::MissingMipmapLevels { expected, found } => write!(
+        f,
+        "texture has {} mipmap levels, expected were {}",
+        found, expected
+      ),
+      TextureError::UnsupportedTextureType(texture_type) => {
+        write!(f, "unsupported texture type {:?}", texture_type)
+      }
+      TextureError::CubeMapArray => write!(f, "arrays of cube maps aren't supported"),
+      TextureError::Layout(error) => write!(f, "{}", error),
+      TextureError::Validation(issues) => match issues.first() {
+        Some(issue) => write!(f, "{}", issue),
+        None => write!(f, "the texture can't be tiled"),
+      },
+      TextureError::FormatMismatch { expected, found } => write!(
+        f,
+        "texture has format {:?}, expected was {:?}",
+        found, expected
+      ),
+      TextureError::MissingSurface { mip, layer, face } => {
+        write!(f, "missing mip {} of layer {}, face {}", mip, layer, face)
+      }
+    }
+  }
+}
+
+impl std::error::Error for TextureError {}
+
+impl From<dds::Error> for TextureError {
+  fn from(error: dds::Error) -> Self {
+    TextureError::Dds(error)
+  }
+}
+
+impl From<LayoutError> for TextureError {
+  fn from(error: LayoutError) -> Self {
+    TextureError::Layout(error)
+  }
+}
+
+/// A texture with every surface untiled, independent of any file format.
+#[derive(Debug, Clone)]
+pub struct Texture {
+  pub format: Format,
+  pub texture_type: TextureType,
+  pub width: u32,
+  pub height: u32,
+  /// Slices of a volume or layers of an array, 6 for cube maps and 1 otherwise.
+  pub depth: u32,
+  pub mipmap_levels: u32,
+  /// Ordered by mip, then by layer or face.
+  pub surfaces: Vec<Surface>,
+}
+
+/// A single mip of one layer or face. The data is linear, rows of blocks without any
+/// padding, with the bytes swapped into the order DDS files use.
+#[derive(Debug, Clone)]
+pub struct Surface {
+  pub mip: u32,
+  /// Layer of an array or slice of a volume.
+  pub layer: u32,
+  /// Face of a cube map, in the order +X, -X, +Y, -Y, +Z, -Z.
+  pub face: u32,
+  pub width: u32,
+  pub height: u32,
+  pub data: Vec<u8>,
+}
+
+impl Texture {
+  pub fn surface(&self, mip: u32, layer: u32, face: u32) -> Option<&Surface> {
+    self
+      .surfaces
+      .iter()
+      .find(|surface| surface.mip == mip && surface.layer == layer && surface.face == face)
+  }
+
+  /// Drops the surfaces of every mip from `mipmap_levels` on.
+  pub fn truncate_mips(&mut self, mipmap_levels: u32) {
+    self.surfaces.retain(|surface| surface.mip < mipmap_levels);
+    self.mipmap_levels = self.mipmap_levels.min(mipmap_levels);
+  }
+
+  /// Splits the data of `dds` into surfaces, which may have a DX10 header. Fails if
+  /// its format isn't supported, it's an array of cube maps or its data ends early.
+  pub fn from_dds(dds: &Dds) -> Result<Self, TextureError> {
+    let dds_format = get_format_from_dds_file(dds).ok_or(TextureError::UnsupportedDdsFormat)?;
+    let format = dds_format.format;
+
+    let (texture_type, depth) = dds_texture_type(dds)?;
+
+    let mut texture = Texture {
+      format,
+      texture_type,
+      width: dds.get_width(),
+      height: dds.get_height(),
+      depth,
+      mipmap_levels: dds.get_num_mipmap_levels(),
+      surfaces: Vec::new(),
+    };
+
+    let format_data = get_format_data(&format);
+    let mut offset = 0;
+
+    for (mip, slice) in texture.dds_order() {
+      let (width, height) = mip_size(texture.width, texture.height, mip);
+      let size = surface_size(&format_data, width, height);
+
+      let mut data = dds
+        .data
+        .get(offset..offset + size)
+        .ok_or(TextureError::DataTooShort {
+          expected: offset + size,
+          found: dds.data.len(),
+        })?
+        .to_vec();
+      offset += size;
+
+      if dds_format.red_first {
+        data.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+      }
+
+      texture
+        .surfaces
+        .push(new_surface(texture_type, mip, slice, width, height, data));
+    }
+
+    texture.surfaces.sort_by_key(|surface| surface.mip);
+    Ok(texture)
+  }
+
+  /// Builds a DDS file holding every surface. Fails for array textures with more than
+  /// one layer, which need a DX10 header.
+  pub fn to_dds(&self) -> Result<Dds, TextureError> {
+    if self.texture_type == TextureType::Array && self.depth > 1 {
+      return Err(TextureError::UnsupportedTextureType(TextureType::Array));
+    }
+
+    let caps2 = Caps2::CUBEMAP | Caps2::CUBEMAP_ALLFACES;
+    let caps2 = (self.texture_type == TextureType::Cube).then_some(caps2);
+
+    let mut dds = Dds::new_d3d(
+      get_dds_format(&self.format),
+      self.width,
+      self.height,
+      (self.texture_type == TextureType::Volume).then_some(self.depth),
+      Some(self.mipmap_levels),
+      caps2,
+    )?;
+
+    dds.data.clear();
+    for (mip, slice) in self.dds_order() {
+      let (layer, face) = layer_and_face(self.texture_type, slice);
+      match self.surface(mip, layer, face) {
+        Some(surface) => dds.data.extend(&surface.data),
+        None => return Err(TextureError::MissingSurface { mip, layer, face }),
+      }
+    }
+
+    Ok(dds)
+  }
+
+  // DDS files store the mips of every face after another, but all slices of a volume
+  // next to each other
+  fn dds_order(&self) -> Vec<(u32, u32)> {
+    if self.texture_type == TextureType::Volume {
+      (0..self.mipmap_levels)
+        .flat_map(|mip| (0..1.max(self.depth >> mip)).map(move |slice| (mip, slice)))
+        .collect()
+    } else {
+      (0..self.depth)
+        .flat_map(|slice| (0..self.mipmap_levels).map(move |mip| (mip, slice)))
+        .collect()
+    }
+  }
+}
+
+/// Untiles every surface of the texture described by `config`. Like the addresses in
+/// `config`, `src` starts at guest address 0. Fails if `src` ends before a surface does.
+pub fn untile_texture(config: &Config, src: &[u8]) -> Result<Texture, TextureError> {
+  let format_data = get_format_data(&config.format);
+  let info = build_texture_info(config, &format_data);
+  let depth = config.depth.unwrap_or(1);
+  let mipmap_levels = config.mipmap_levels.unwrap_or(1);
+
+  if config.tiled && config.texture_type == TextureType::Volume {
+    return Err(TextureError::UnsupportedTextureType(TextureType::Volume));
+  }
+
+  let mut surfaces = Vec::new();
+
+  for mip in 0..mipmap_levels {
+    let (width, height) = info.get_mip_size(mip);
+    let blocks_x = width.div_ceil(format_data.block_width);
+    let blocks_y = height.div_ceil(format_data.block_height);
+
+    for slice in 0..mip_slices(config.texture_type, depth, mip) {
+      let (address, pitch, offset_x, offset_y) = surface_location(&info, mip, slice);
+
+      // tiled surfaces are read in whole tiles, linear ones up to their last block
+      let size = if config.tiled {
+        let (_, rows) = info.get_mip_pitch(mip);
+        pitch as usize * rows as usize
+      } else {
+        (offset_y + blocks_y - 1) as usize * pitch as usize + (offset_x + blocks_x) as usize
+      };
+      let end = address as usize + size * format_data.bytes_per_block as usize;
+      let input = src
+        .get(address as usize..end)
+        .ok_or(TextureError::DataTooShort {
+          expected: end,
+          found: src.len(),
+        })?;
+
+      let mut data = vec![0; surface_size(&format_data, width, height)];
+
+      if !config.tiled {
+        let start = (offset_y * pitch + offset_x) * format_data.bytes_per_block;
+        swap_rows(
+          &mut data,
+          blocks_x * format_data.bytes_per_block,
+          &input[start as usize..],
+          pitch * format_data.bytes_per_block,
+          blocks_y,
+        );
+      } else if blocks_x == pitch || blocks_y <= 32 {
+        // the pitch only matters past the first row of tiles
+        untile(
+          &mut data,
+          input,
+          &format_data,
+          blocks_x,
+          blocks_y,
+          offset_x,
+          offset_y,
+        );
+      } else {
+        let mut padded = vec![0; (pitch * blocks_y * format_data.bytes_per_block) as usize];
+        untile(&mut padded, input, &format_data, pitch, blocks_y, 0, 0);
+        crop_rows(&mut data, &padded, blocks_x, pitch, &format_data);
+      }
+
+      surfaces.push(new_surface(
+        config.texture_type,
+        mip,
+        slice,
+        width,
+        height,
+        data,
+      ));
+    }
+  }
+
+  Ok(Texture {
+    format: config.format,
+    texture_type: config.texture_type,
+    width: config.width,
+    height: config.height,
+    depth,
+    mipmap_levels,
+    surfaces,
+  })
+}
+
+/// Tiles `texture` into guest memory laid out as described by `config`, the reverse of
+/// [`untile_texture`]. Fails if the format differs or a surface `config` asks for is
+/// missing.
+pub fn tile_texture(config: &Config, texture: &Texture) -> Result<Vec<u8>, TextureError> {
+  let format_data = get_format_data(&config.format);
+  let info = build_texture_info(config, &format_data);
+  let depth = config.depth.unwrap_or(1);
+  let mipmap_levels = config.mipmap_levels.unwrap_or(1);
+
+  if config.tiled && config.texture_type == TextureType::Volume {
+    return Err(TextureError::UnsupportedTextureType(TextureType::Volume));
+  }
+
+  if texture.format != config.format {
+    return Err(TextureError::FormatMismatch {
+      expected: config.format,
+      found: texture.format,
+    });
+  }
+
+  let mut size = info.base_address + info.get_base_size();
+  if mipmap_levels > 1 {
+    size = size.max(info.mip_address + info.get_mips_size(mipmap_levels));
+  }
+
+  let mut output = vec![0; size as usize];
+
+  for mip in 0..mipmap_levels {
+    let (width, height) = info.get_mip_size(mip);
+    let blocks_x = width.div_ceil(format_data.block_width);
+    let blocks_y = height.div_ceil(format_data.block_height);
+
+    for slice in 0..mip_slices(config.texture_type, depth, mip) {
+      let (layer, face) = layer_and_face(config.texture_type, slice);
+      let surface = match texture.surface(mip, layer, face) {
+        Some(surface) if (surface.width, surface.height) == (width, height) => surface,
+        _ => return Err(TextureError::MissingSurface { mip, layer, face }),
+      };
+
+      let expected = surface_size(&format_data, width, height);
+      if surface.data.len() < expected {
+        return Err(TextureError::DataTooShort {
+          expected,
+          found: surface.data.len(),
+        });
+      } else if surface.data.len() != expected {
+        return Err(TextureError::MissingSurface { mip, layer, face });
+      }
+
+      let (address, pitch, offset_x, offset_y) = surface_location(&info, mip, slice);
+      let output = &mut output[address as usize..];
+
+      if !config.tiled {
+        let start = (offset_y * pitch + offset_x) * format_data.bytes_per_block;
+        swap_rows(
+          &mut output[start as usize..],
+          pitch * format_data.bytes_per_block,
+          &surface.data,
+          blocks_x * format_data.bytes_per_block,
+          blocks_y,
+        );
+      } else if blocks_x == pitch || blocks_y <= 32 {
+        tile(
+          output,
+          &surface.data,
+          &format_data,
+          blocks_x,
+          blocks_y,
+          offset_x,
+          offset_y,
+        );
+      } else {
+        let mut padded = vec![0; (pitch * blocks_y * format_data.bytes_per_block) as usize];
+        pad_rows(&mut padded, &surface.data, blocks_x, pitch, &format_data);
+        tile(output, &padded, &format_data, pitch, blocks_y, 0, 0);
+      }
+    }
+  }
+
+  Ok(output)
+}
+
+// Address of a slice of `mip`, its pitch in blocks and its block offset in the packed
+// mip tail.
+fn surface_location(info: &TextureInfo, mip: u32, slice: u32) -> (u32, u32, u32, u32) {
+  let mut offset_x = 0;
+  let mut offset_y = 0;
+  let address = info.get_mip_location(mip, &mut offset_x, &mut offset_y, true);
+
+  // every slice takes up whole tiles
+  let (pitch, rows) = info.get_mip_pitch(mip);
+  let slice_size = pitch * rows * info.format.bytes_per_block;
+
+  (address + slice * slice_size, pitch, offset_x, offset_y)
+}
+
+/// Type of the texture in `dds` and its depth, counted like [`Texture::depth`].
+pub(crate) fn dds_texture_type(dds: &Dds) -> Result<(TextureType, u32), TextureError> {
+  let layers = dds
+    .header10
+    .as_ref()
+    .map_or(1, |header10| header10.array_size);
+  let cube = dds.header.caps2.contains(Caps2::CUBEMAP)
+    || dds
+      .header10
+      .as_ref()
+      .is_some_and(|header10| header10.misc_flag.contains(MiscFlag::TEXTURECUBE));
+
+  if cube {
+    if layers > 1 {
+      return Err(TextureError::CubeMapArray);
+    }
+    Ok((TextureType::Cube, 6))
+  } else if dds.get_depth() > 1 {
+    Ok((TextureType::Volume, dds.get_depth()))
+  } else if layers > 1 {
+    Ok((TextureType::Array, layers))
+  } else {
+    Ok((TextureType::TwoD, 1))
+  }
+}
+
+pub(crate) fn mip_size(width: u32, height: u32, mip: u32) -> (u32, u32) {
+  (1.max(width >> mip), 1.max(height >> mip))
+}
+
+pub(crate) fn mip_slices(texture_type: TextureType, depth: u32, mip: u32) -> u32 {
+  match texture_type {
+    TextureType::Volume => 1.max(depth >> mip),
+    _ => depth,
+  }
+}
+
+pub(crate) fn surface_size(format: &FormatData, width: u32, height: u32) -> usize {
+  let blocks_x = width.div_ceil(format.block_width);
+  let blocks_y = height.div_ceil(format.block_height);
+  blocks_x as usize * blocks_y as usize * format.bytes_per_block as usize
+}
+
+pub(crate) fn layer_and_face(texture_type: TextureType, slice: u32) -> (u32, u32) {
+  match texture_type {
+    TextureType::Cube => (0, slice),
+    _ => (slice, 0),
+  }
+}
+
+pub(crate) fn new_surface(
+  texture_type: TextureType,
+  mip: u32,
+  slice: u32,
+  width: u32,
+  height: u32,
+  data: Vec<u8>,
+) -> Surface {
+  let (layer, face) = layer_and_face(texture_type, slice);
+
+  Surface {
+    mip,
+    layer,
+    face,
+    width,
+    height,
+    data,
+  }
+}
+
+// Copies rows of linear data, swapping every two bytes like `untile` and `tile` do.
+fn swap_rows(output: &mut [u8], output_pitch: u32, input: &[u8], input_pitch: u32, rows: u32) {
+  let row_size = output_pitch.min(input_pitch) as usize;
+
+  for row in 0..rows as usize {
+    let output = &mut output[row * output_pitch as usize..][..row_size];
+    let input = &input[row * input_pitch as usize..][..row_size];
+
+    for (output, input) in output.chunks_exact_mut(2).zip(input.chunks_exact(2)) {
+      output[0] = input[1];
+      output[1] = input[0];
+    }
+  }
+}
+
+fn crop_rows(output: &mut [u8], input: &[u8], blocks_x: u32, pitch: u32, format: &FormatData) {
+  let row_size = (blocks_x * format.bytes_per_block) as usize;
+  let pitch = (pitch * format.bytes_per_block) as usize;
+
+  for (output, input) in output
+    .chunks_exact_mut(row_size)
+    .zip(input.chunks_exact(pitch))
+  {
+    output.copy_from_slice(&input[..row_size]);
+  }
+}
+
+fn pad_rows(output: &mut [u8], input: &[u8], blocks_x: u32, pitch: u32, format: &FormatData) {
+  let row_size = (blocks_x * format.bytes_per_block) as usize;
+  let pitch = (pitch * format.bytes_per_block) as usize;
+
+  for (output, input) in output
+    .chunks_exact_mut(pitch)
+    .zip(input.chunks_exact(row_size))
+  {
+    output[..row_size].copy_from_slice(input);
+  }
+}
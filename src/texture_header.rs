@@ -1,6 +1,15 @@
-use binrw::{BinRead, BinWrite};
+use std::io::{Read, Seek, Write};
+
+use binrw::{BinRead, BinResult, BinWrite};
+use dds::Dds;
+use modular_bitfield::error::OutOfBounds;
 use modular_bitfield::{bitfield, specifiers::*, BitfieldSpecifier};
 
+use crate::format::{get_format_from_dds_file, get_swizzle, Encoding};
+use crate::layout::{LayoutError, LayoutRequest, TextureType};
+use crate::math::{sign_extend, truncate_signed};
+use crate::texture::{dds_texture_type, TextureError};
+
 // https://github.com/xenia-project/xenia/blob/master/src/xenia/gpu/xenos.h
 // see /licenses/xenia.txt
 
@@ -37,6 +46,7 @@ pub struct TextureSizeStack {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 6]
 pub enum TextureFormat {
   _1Reverse = 0,
@@ -106,6 +116,7 @@ pub enum TextureFormat {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 1]
 pub enum NumFormat {
   Fraction = 0,
@@ -113,6 +124,7 @@ pub enum NumFormat {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 2]
 pub enum TextureKind {
   InvalidTexture = 0,
@@ -121,7 +133,8 @@ pub enum TextureKind {
   Vertex = 3,
 }
 
-#[derive(BitfieldSpecifier, Debug)]
+#[derive(BitfieldSpecifier, Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 2]
 pub enum TextureSign {
   Unsigned = 0,
@@ -131,6 +144,7 @@ pub enum TextureSign {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 3]
 pub enum ClampMode {
   Repeat = 0,
@@ -144,6 +158,7 @@ pub enum ClampMode {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 2]
 pub enum Endian {
   None = 0,
@@ -153,13 +168,15 @@ pub enum Endian {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 2]
 pub enum RequestSize {
   _256Bit = 0,
   _512Bit = 1,
 }
 
-#[derive(BitfieldSpecifier, Debug)]
+#[derive(BitfieldSpecifier, Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 3]
 pub enum Swizzle {
   X = 0,
@@ -172,6 +189,7 @@ pub enum Swizzle {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 1]
 pub enum ClampPolicy {
   D3D = 0,
@@ -179,6 +197,7 @@ pub enum ClampPolicy {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 2]
 pub enum Dimension {
   OneD = 0,
@@ -188,6 +207,7 @@ pub enum Dimension {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 2]
 pub enum BorderColor {
   AgbrBlack = 0,
@@ -197,6 +217,7 @@ pub enum BorderColor {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 2]
 pub enum TriClamp {
   Normal = 0,
@@ -206,6 +227,7 @@ pub enum TriClamp {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 1]
 pub enum MinMagFilter {
   Point = 0,
@@ -213,6 +235,7 @@ pub enum MinMagFilter {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 2]
 pub enum MipFilter {
   Point = 0,
@@ -222,6 +245,7 @@ pub enum MipFilter {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 3]
 pub enum AnisoFilter {
   Disabled = 0,
@@ -234,6 +258,7 @@ pub enum AnisoFilter {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 1]
 pub enum SignedRepeatingFractionMode {
   ZeroClampMinusOne = 0,
@@ -241,6 +266,7 @@ pub enum SignedRepeatingFractionMode {
 }
 
 #[derive(BitfieldSpecifier, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[bits = 3]
 pub enum ArbitraryFilter {
   _2x4Sym = 0,
@@ -253,7 +279,7 @@ pub enum ArbitraryFilter {
 }
 
 #[bitfield]
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct TextureMetadata {
   pub kind: TextureKind,
   pub sign_x: TextureSign,
@@ -317,7 +343,72 @@ pub struct TextureMetadata {
   pub mip_address: B20, // u32
 }
 
-#[derive(BinRead, BinWrite, Debug)]
+// D3DRESOURCETYPE, the first bits of `D3DResource::Common`.
+#[derive(BitfieldSpecifier, Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[bits = 6]
+pub enum ResourceType {
+  None = 0,
+  VertexBuffer = 1,
+  IndexBuffer = 2,
+  Texture = 3,
+  Surface = 4,
+  VertexDeclaration = 5,
+  VertexShader = 6,
+  PixelShader = 7,
+  ConstantBuffer = 8,
+  CommandBuffer = 9,
+  AsyncCommandBufferCall = 10,
+  PerfCounterBatch = 11,
+  OcclusionQueryBatch = 12,
+  VolumeTexture = 17,
+  CubeTexture = 18,
+  ArrayTexture = 19,
+  LineTexture = 20,
+}
+
+impl ResourceType {
+  /// The kind of texture, or `None` if the resource isn't a texture.
+  pub fn texture_type(&self) -> Option<TextureType> {
+    match self {
+      ResourceType::Texture | ResourceType::LineTexture => Some(TextureType::TwoD),
+      ResourceType::CubeTexture => Some(TextureType::Cube),
+      ResourceType::VolumeTexture => Some(TextureType::Volume),
+      ResourceType::ArrayTexture => Some(TextureType::Array),
+      _ => None,
+    }
+  }
+}
+
+#[bitfield]
+#[derive(Clone, Debug)]
+pub struct ResourceCommon {
+  pub resource_type: ResourceType,
+  #[skip]
+  _lock: B14,
+  pub d3d_created: bool,
+  pub cpu_cached: bool,
+  #[skip]
+  _unused: B9,
+  pub not_in_memory: bool,
+}
+
+impl ResourceCommon {
+  /// The kind of texture this resource describes. `None` for other resources and
+  /// unknown resource types.
+  pub fn texture_type(&self) -> Option<TextureType> {
+    self.resource_type_or_err().ok()?.texture_type()
+  }
+}
+
+/// Byte order of headers in 360 data, used by the `read_360` and `write_360` helpers.
+pub const DEFAULT_ENDIAN: binrw::Endian = binrw::Endian::Big;
+
+/// `D3DBaseTexture` as stored by the XDK. The byte order isn't fixed, every field
+/// including the fetch constant dwords follows the one passed to binrw: 360 disc
+/// images are big-endian and read with [`TextureHeader::read_360`], dumps from PC ports
+/// with [`BinRead::read_le`].
+#[derive(BinRead, BinWrite, Debug, Clone)]
 pub struct TextureHeader {
   pub common: u32,
   pub reference_count: u32,
@@ -326,141 +417,304 @@ pub struct TextureHeader {
   pub identifier: u32,
   pub base_flush: u32,
   pub mip_flush: u32,
-  metadata: [u8; 24],
+  pub(crate) metadata: [u32; 6],
+}
+
+impl TextureMetadata {
+  /// Width, height and depth of the base level. The depth is the slice count for volumes,
+  /// the layer count for stacked textures and the face count for cube maps.
+  pub fn size(&self) -> (u32, u32, u32) {
+    let bytes = self.texture_size().to_le_bytes();
+
+    match self.dimension() {
+      Dimension::OneD => {
+        let size = TextureSize1D::from_bytes(bytes);
+        (size.width() + 1, 1, 1)
+      }
+      Dimension::TwoDOrStacked => {
+        let size = TextureSize2D::from_bytes(bytes);
+        (
+          size.width() as u32 + 1,
+          size.height() as u32 + 1,
+          size.stack_depth() as u32 + 1,
+        )
+      }
+      Dimension::ThreeD => {
+        let size = TextureSize3D::from_bytes(bytes);
+        (
+          size.width() as u32 + 1,
+          size.height() as u32 + 1,
+          size.depth() as u32 + 1,
+        )
+      }
+      Dimension::CubeMap => {
+        let size = TextureSizeStack::from_bytes(bytes);
+        (
+          size.width() as u32 + 1,
+          size.height() as u32 + 1,
+          size.depth() as u32 + 1,
+        )
+      }
+    }
+  }
+
+  /// Encodes the base level size according to the current `dimension`. Fails if a value
+  /// does not fit the dimension's bitfield.
+  pub fn set_size(&mut self, width: u32, height: u32, depth: u32) -> Result<(), LayoutError> {
+    let out_of_range = LayoutError::SizeOutOfRange {
+      width,
+      height,
+      depth,
+    };
+    let (Some(w), Some(h), Some(d)) = (
+      width.checked_sub(1),
+      height.checked_sub(1),
+      depth.checked_sub(1),
+    ) else {
+      return Err(LayoutError::EmptyDimension);
+    };
+
+    // values not even fitting the integer type are out of range of the field as well
+    let narrow = |value: u32| u16::try_from(value).unwrap_or(u16::MAX);
+    let narrow_u8 = |value: u32| u8::try_from(value).unwrap_or(u8::MAX);
+
+    let bytes = match self.dimension() {
+      Dimension::OneD => TextureSize1D::new()
+        .with_width_checked(w)
+        .map(TextureSize1D::into_bytes),
+      Dimension::TwoDOrStacked => TextureSize2D::new()
+        .with_width_checked(narrow(w))
+        .and_then(|size| size.with_height_checked(narrow(h)))
+        .and_then(|size| size.with_stack_depth_checked(narrow_u8(d)))
+        .map(TextureSize2D::into_bytes),
+      Dimension::ThreeD => TextureSize3D::new()
+        .with_width_checked(narrow(w))
+        .and_then(|size| size.with_height_checked(narrow(h)))
+        .and_then(|size| size.with_depth_checked(narrow(d)))
+        .map(TextureSize3D::into_bytes),
+      Dimension::CubeMap => TextureSizeStack::new()
+        .with_width_checked(narrow(w))
+        .and_then(|size| size.with_height_checked(narrow(h)))
+        .and_then(|size| size.with_depth_checked(narrow_u8(d)))
+        .map(TextureSizeStack::into_bytes),
+    }
+    .map_err(|_| out_of_range)?;
+
+    self.set_texture_size(u32::from_le_bytes(bytes));
+    Ok(())
+  }
+}
+
+// Initial value of `base_flush` and `mip_flush` for resources that were never flushed.
+pub(crate) const D3DFLUSH_INITIAL_VALUE: u32 = 0xFFFF0000;
+
+#[derive(Debug, Copy, Clone)]
+pub struct HeaderOptions {
+  /// Byte offset the allocator assigned to the texture, must be 4 KiB aligned.
+  pub address: u32,
+  pub tiled: bool,
+}
+
+impl Default for HeaderOptions {
+  fn default() -> Self {
+    Self {
+      address: 0,
+      tiled: true,
+    }
+  }
+}
+
+// The signed fields are stored as two's complement numbers of their bit width.
+impl TextureMetadata {
+  pub fn exp_adjust_signed(&self) -> i8 {
+    sign_extend(self.exp_adjust() as u32, 6) as i8
+  }
+
+  pub fn set_exp_adjust_signed(&mut self, value: i8) -> Result<(), OutOfBounds> {
+    let value = truncate_signed(value as i32, 6).ok_or(OutOfBounds)?;
+    self.set_exp_adjust_checked(value as u8)
+  }
+
+  /// LOD bias in mip levels. It's stored as fixed point with five fractional bits,
+  /// limiting it to -16.0..=15.96875.
+  pub fn lod_bias(&self) -> f32 {
+    sign_extend(self.lodbias() as u32, 10) as f32 / 32.0
+  }
+
+  /// Sets the LOD bias, rounded to the nearest 1/32 of a level.
+  pub fn set_lod_bias(&mut self, value: f32) -> Result<(), OutOfBounds> {
+    let fixed = (value * 32.0).round();
+
+    if !fixed.is_finite() {
+      return Err(OutOfBounds);
+    }
+
+    let value = truncate_signed(fixed as i32, 10).ok_or(OutOfBounds)?;
+    self.set_lodbias_checked(value as u16)
+  }
+
+  pub fn grad_exp_adjust_h_signed(&self) -> i8 {
+    sign_extend(self.grad_exp_adjust_h() as u32, 5) as i8
+  }
+
+  pub fn set_grad_exp_adjust_h_signed(&mut self, value: i8) -> Result<(), OutOfBounds> {
+    let value = truncate_signed(value as i32, 5).ok_or(OutOfBounds)?;
+    self.set_grad_exp_adjust_h_checked(value as u8)
+  }
+
+  pub fn grad_exp_adjust_v_signed(&self) -> i8 {
+    sign_extend(self.grad_exp_adjust_v() as u32, 5) as i8
+  }
+
+  pub fn set_grad_exp_adjust_v_signed(&mut self, value: i8) -> Result<(), OutOfBounds> {
+    let value = truncate_signed(value as i32, 5).ok_or(OutOfBounds)?;
+    self.set_grad_exp_adjust_v_checked(value as u8)
+  }
+
+  pub fn aniso_bias_signed(&self) -> i8 {
+    sign_extend(self.aniso_bias() as u32, 4) as i8
+  }
+
+  pub fn set_aniso_bias_signed(&mut self, value: i8) -> Result<(), OutOfBounds> {
+    let value = truncate_signed(value as i32, 4).ok_or(OutOfBounds)?;
+    self.set_aniso_bias_checked(value as u8)
+  }
+
+  /// The components the sampler returns for red, green, blue and alpha.
+  pub fn swizzle(&self) -> [Swizzle; 4] {
+    [
+      self.swizzle_x(),
+      self.swizzle_y(),
+      self.swizzle_z(),
+      self.swizzle_w(),
+    ]
+  }
+
+  /// Whether the sampler converts the color components from sRGB, which file formats
+  /// with a single transfer function for all of them express as an sRGB format.
+  pub fn is_srgb(&self) -> bool {
+    [self.sign_x(), self.sign_y(), self.sign_z()]
+      .iter()
+      .all(|sign| matches!(sign, TextureSign::Gamma))
+  }
 }
 
 impl TextureHeader {
+  /// Reads a header in [`DEFAULT_ENDIAN`], the byte order of 360 data.
+  pub fn read_360<R: Read + Seek>(reader: &mut R) -> BinResult<Self> {
+    Self::read_options(reader, DEFAULT_ENDIAN, ())
+  }
+
+  /// Writes the header in [`DEFAULT_ENDIAN`], the byte order of 360 data.
+  pub fn write_360<W: Write + Seek>(&self, writer: &mut W) -> BinResult<()> {
+    self.write_options(writer, DEFAULT_ENDIAN, ())
+  }
+
+  /// Builds a complete header for the texture stored in `dds`, with the layout from
+  /// [`LayoutRequest::plan`] and the sampler state D3D assigns to fresh textures. The
+  /// signs follow the sRGB or SNORM variant of a DX10 format. Fails if the format isn't
+  /// supported, it's an array of cube maps or the size can't be stored.
+  pub fn from_dds(dds: &Dds, options: &HeaderOptions) -> Result<Self, TextureError> {
+    let dds_format = get_format_from_dds_file(dds).ok_or(TextureError::UnsupportedDdsFormat)?;
+    let format = dds_format.format;
+
+    let (texture_type, depth) = dds_texture_type(dds)?;
+    let resource_type = match texture_type {
+      TextureType::TwoD => ResourceType::Texture,
+      TextureType::Cube => ResourceType::CubeTexture,
+      TextureType::Volume => ResourceType::VolumeTexture,
+      TextureType::Array => ResourceType::ArrayTexture,
+    };
+
+    let (color_sign, alpha_sign) = match dds_format.encoding {
+      None | Some(Encoding::Unorm) => (TextureSign::Unsigned, TextureSign::Unsigned),
+      Some(Encoding::Snorm) => (TextureSign::Signed, TextureSign::Signed),
+      // alpha is always linear
+      Some(Encoding::Srgb) => (TextureSign::Gamma, TextureSign::Unsigned),
+    };
+
+    let request = LayoutRequest {
+      format,
+      texture_type,
+      width: dds.get_width(),
+      height: dds.get_height(),
+      depth,
+      mipmap_levels: Some(dds.get_num_mipmap_levels()),
+      tiled: options.tiled,
+      address: options.address,
+    };
+
+    let [swizzle_x, swizzle_y, swizzle_z, swizzle_w] = get_swizzle(&format);
+
+    let layout = request.plan()?;
+
+    let metadata = layout
+      .metadata
+      .with_sign_x(color_sign)
+      .with_sign_y(color_sign)
+      .with_sign_z(color_sign)
+      .with_sign_w(alpha_sign)
+      .with_num_format(NumFormat::Fraction)
+      .with_swizzle_x(swizzle_x)
+      .with_swizzle_y(swizzle_y)
+      .with_swizzle_z(swizzle_z)
+      .with_swizzle_w(swizzle_w)
+      .with_clamp_x(ClampMode::Repeat)
+      .with_clamp_y(ClampMode::Repeat)
+      .with_clamp_z(ClampMode::Repeat)
+      .with_clamp_policy(ClampPolicy::D3D)
+      .with_mag_filter(MipFilter::Linear)
+      .with_min_filter(MipFilter::Linear)
+      .with_mip_filter(MipFilter::Linear)
+      .with_vol_mag_filter(MinMagFilter::Linear)
+      .with_vol_min_filter(MinMagFilter::Linear)
+      .with_aniso_filter(AnisoFilter::Disabled)
+      .with_border_color(BorderColor::AgbrBlack)
+      .with_tri_clamp(TriClamp::Normal);
+
+    let common = ResourceCommon::new().with_resource_type(resource_type);
+
+    let mut header = TextureHeader {
+      common: u32::from_le_bytes(common.into_bytes()),
+      reference_count: 1,
+      fence: 0,
+      read_fence: 0,
+      identifier: 0,
+      base_flush: D3DFLUSH_INITIAL_VALUE,
+      mip_flush: D3DFLUSH_INITIAL_VALUE,
+      metadata: [0; 6],
+    };
+
+    header.set_metadata(&metadata);
+    Ok(header)
+  }
+
+  pub fn resource_common(&self) -> ResourceCommon {
+    ResourceCommon::from_bytes(self.common.to_le_bytes())
+  }
+
+  pub fn set_resource_common(&mut self, common: &ResourceCommon) {
+    self.common = u32::from_le_bytes(common.clone().into_bytes());
+  }
+
+  /// Decodes the fetch constant. Its dwords follow the byte order the header was read
+  /// in, so headers read with `read_le` no longer have them treated as big-endian.
   pub fn metadata(&self) -> TextureMetadata {
-    let mut metadata = self.metadata;
+    let mut metadata = [0; 24];
 
-    // reversing ever u32 independently
-    for chunk in (0..metadata.len()).step_by(4) {
-      let dword = &mut metadata[chunk..chunk + 4];
-      dword.reverse();
+    // the fetch constant is a run of dwords, already swapped while reading
+    for (chunk, dword) in metadata.chunks_exact_mut(4).zip(self.metadata) {
+      chunk.copy_from_slice(&dword.to_le_bytes());
     }
 
     TextureMetadata::from_bytes(metadata)
   }
-}
 
-// impl Serialize for TextureMetadata {
-//   fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-//     let mut state = serializer.serialize_struct("GpuTextureFetchConstant", 46)?;
-//
-//     state.serialize_field("kind", &self.kind())?;
-//     state.serialize_field("sign_x", &self.sign_x())?;
-//     state.serialize_field("sign_y", &self.sign_y())?;
-//     state.serialize_field("sign_z", &self.sign_z())?;
-//     state.serialize_field("sign_w", &self.sign_w())?;
-//     state.serialize_field("clamp_x", &self.clamp_x())?;
-//     state.serialize_field("clamp_y", &self.clamp_y())?;
-//     state.serialize_field("clamp_z", &self.clamp_z())?;
-//     state.serialize_field(
-//       "signed_repeating_fraction",
-//       &self.signed_repeating_fraction(),
-//     )?;
-//     state.serialize_field("dim_tbd", &self.dim_tbd())?;
-//     state.serialize_field("pitch", &self.pitch())?;
-//     state.serialize_field("tiled", &self.tiled())?;
-//     state.serialize_field("format", &self.format())?;
-//     state.serialize_field("endianness", &self.endianness())?;
-//     state.serialize_field("request_size", &self.request_size())?;
-//     state.serialize_field("stacked", &self.stacked())?;
-//     state.serialize_field("clamp_policy", &self.clamp_policy())?;
-//     state.serialize_field("base_address", &self.base_address())?;
-//
-//     let mut texture_size: [u8; 4] = self.texture_size().to_le_bytes();
-//
-//     match &self.dimension() {
-//       Dimension::OneD => {
-//         state.serialize_field("texture_size", &TextureSize1D::from_bytes(texture_size))?
-//       }
-//       Dimension::TwoDOrStacked => {
-//         state.serialize_field("texture_size", &TextureSize2D::from_bytes(texture_size))?
-//       }
-//       Dimension::ThreeD => {
-//         state.serialize_field("texture_size", &TextureSize3D::from_bytes(texture_size))?
-//       }
-//       Dimension::CubeMap => {
-//         state.serialize_field("texture_size", &TextureSizeStack::from_bytes(texture_size))?
-//       }
-//     };
-//
-//     state.serialize_field("num_format", &self.num_format())?;
-//     state.serialize_field("swizzle_x", &self.swizzle_x())?;
-//     state.serialize_field("swizzle_y", &self.swizzle_y())?;
-//     state.serialize_field("swizzle_z", &self.swizzle_z())?;
-//     state.serialize_field("swizzle_w", &self.swizzle_w())?;
-//     state.serialize_field("exp_adjust", &self.exp_adjust())?;
-//     state.serialize_field("mag_filter", &self.mag_filter())?;
-//     state.serialize_field("min_filter", &self.min_filter())?;
-//     state.serialize_field("mip_filter", &self.mip_filter())?;
-//     state.serialize_field("aniso_filter", &self.aniso_filter())?;
-//     state.serialize_field("arbitrary_filter", &self.arbitrary_filter())?;
-//     state.serialize_field("border_size", &self.border_size())?;
-//     state.serialize_field("vol_mag_filter", &self.vol_mag_filter())?;
-//     state.serialize_field("vol_min_filter", &self.vol_min_filter())?;
-//     state.serialize_field("min_mip_level", &self.min_mip_level())?;
-//     state.serialize_field("max_mip_level", &self.max_mip_level())?;
-//     state.serialize_field("mag_aniso_walk", &self.mag_aniso_walk())?;
-//     state.serialize_field("min_aniso_walk", &self.min_aniso_walk())?;
-//     state.serialize_field("lodbias", &self.lodbias())?;
-//     state.serialize_field("grad_exp_adjust_h", &self.grad_exp_adjust_h())?;
-//     state.serialize_field("grad_exp_adjust_v", &self.grad_exp_adjust_v())?;
-//     state.serialize_field("border_color", &self.border_color())?;
-//     state.serialize_field("force_bc_w_to_max", &self.force_bc_w_to_max())?;
-//     state.serialize_field("tri_clamp", &self.tri_clamp())?;
-//     state.serialize_field("aniso_bias", &self.aniso_bias())?;
-//     state.serialize_field("dimension", &self.dimension())?;
-//     state.serialize_field("packed_mips", &self.packed_mips())?;
-//     state.serialize_field("mip_address", &self.mip_address())?;
-//
-//     state.end()
-//   }
-// }
-//
-// impl Serialize for TextureSize1D {
-//   fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-//     let mut state = serializer.serialize_struct("GpuTextureSize1D", 1)?;
-//
-//     state.serialize_field("width", &self.width())?;
-//
-//     state.end()
-//   }
-// }
-//
-// impl Serialize for TextureSize2D {
-//   fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-//     let mut state = serializer.serialize_struct("GpuTextureSize2D", 1)?;
-//
-//     state.serialize_field("width", &self.width())?;
-//     state.serialize_field("height", &self.height())?;
-//     state.serialize_field("stack_depth", &self.stack_depth())?;
-//
-//     state.end()
-//   }
-// }
-//
-// impl Serialize for TextureSize3D {
-//   fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-//     let mut state = serializer.serialize_struct("GpuTextureSize3D", 1)?;
-//
-//     state.serialize_field("width", &self.width())?;
-//     state.serialize_field("height", &self.height())?;
-//     state.serialize_field("depth", &self.depth())?;
-//
-//     state.end()
-//   }
-// }
-//
-// impl Serialize for TextureSizeStack {
-//   fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-//     let mut state = serializer.serialize_struct("GpuTextureSizeStack", 1)?;
-//
-//     state.serialize_field("width", &self.width())?;
-//     state.serialize_field("height", &self.height())?;
-//     state.serialize_field("depth", &self.depth())?;
-//
-//     state.end()
-//   }
-// }
+  pub fn set_metadata(&mut self, metadata: &TextureMetadata) {
+    let metadata = metadata.clone().into_bytes();
+
+    for (dword, chunk) in self.metadata.iter_mut().zip(metadata.chunks_exact(4)) {
+      *dword = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+  }
+}
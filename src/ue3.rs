@@ -0,0 +1,527 @@
+//! Texture2D exports of Unreal Engine 3 packages cooked for the 360.
+//!
+//! Every mip is stored as its own tiled surface, padded to the tiling alignment of its
+//! format, in bulk data that can be compressed and can live in a texture file cache.
+
+// https://github.com/gildor2/UEViewer/blob/master/Unreal/UnrealMaterial/UnTexture3.cpp
+// https://github.com/gildor2/UEViewer/blob/master/Unreal/UnCoreSerialize.cpp
+
+use std::fmt;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use binrw::{BinRead, Endian};
+use dds::Dds;
+use miniz_oxide::inflate::{decompress_to_vec_zlib_with_limit, TINFLStatus};
+
+use crate::format::{get_dds_format, get_format_data, Format};
+use crate::lzx::{decompress_raw, LzxError, DEFAULT_WINDOW_BITS};
+use crate::math::align;
+use crate::tile::untile;
+use crate::{Config, TextureType};
+
+const BULKDATA_STORE_IN_SEPARATE_FILE: u32 = 0x01;
+const BULKDATA_SERIALIZE_COMPRESSED_ZLIB: u32 = 0x02;
+const BULKDATA_SERIALIZE_COMPRESSED_LZO: u32 = 0x10;
+const BULKDATA_UNUSED: u32 = 0x20;
+const BULKDATA_SERIALIZE_COMPRESSED_LZX: u32 = 0x80;
+
+const PACKAGE_FILE_TAG: u32 = 0x9E2A83C1;
+
+const MAX_MIP_SIZE: u32 = 8192;
+
+// package versions changing the layout of exports
+const VER_NET_INDEX: u16 = 322;
+const VER_BYTE_PROPERTY_ENUM: u16 = 633;
+const VER_BOOL_PROPERTY_BYTE: u16 = 673;
+
+#[derive(Debug)]
+pub enum Ue3Error {
+  Read(binrw::Error),
+  /// A name index outside of the package's name table.
+  InvalidName(i32),
+  MissingProperty(&'static str),
+  UnsupportedFormat(String),
+  /// Bulk data flags naming a compression method that isn't supported.
+  UnsupportedCompression(u32),
+  /// Compressed bulk data not starting with the package file tag.
+  InvalidBulkData,
+  /// Decompressed bulk data with a size differing from the one announced.
+  SizeMismatch,
+  /// A mip larger than the 8192 texels per side the GPU can sample.
+  InvalidMipSize(u32, u32),
+  /// A mip whose data is in a texture file cache that wasn't loaded.
+  MissingTextureFileCache,
+  Lzx(LzxError),
+  Zlib(TINFLStatus),
+  Dds(dds::Error),
+}
+
+impl fmt::Display for Ue3Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Ue3Error::Read(error) => write!(f, "{}", error),
+      Ue3Error::InvalidName(index) => write!(f, "invalid name index {}", index),
+      Ue3Error::MissingProperty(name) => write!(f, "missing property {}", name),
+      Ue3Error::UnsupportedFormat(format) => write!(f, "unsupported pixel format {}", format),
+      Ue3Error::UnsupportedCompression(flags) => {
+        write!(f, "unsupported bulk data compression, flags {:#x}", flags)
+      }
+      Ue3Error::InvalidBulkData => write!(f, "invalid compressed bulk data"),
+      Ue3Error::SizeMismatch => write!(f, "bulk data size mismatch"),
+      Ue3Error::InvalidMipSize(width, height) => write!(f, "invalid mip size {}x{}", width, height),
+      Ue3Error::MissingTextureFileCache => write!(f, "texture file cache not loaded"),
+      Ue3Error::Lzx(error) => write!(f, "{}", error),
+      Ue3Error::Zlib(status) => write!(f, "invalid zlib data: {:?}", status),
+      Ue3Error::Dds(error) => write!(f, "{}", error),
+    }
+  }
+}
+
+impl std::error::Error for Ue3Error {}
+
+impl From<binrw::Error> for Ue3Error {
+  fn from(error: binrw::Error) -> Self {
+    Ue3Error::Read(error)
+  }
+}
+
+impl From<std::io::Error> for Ue3Error {
+  fn from(error: std::io::Error) -> Self {
+    Ue3Error::Read(binrw::Error::Io(error))
+  }
+}
+
+/// What's needed from the package summary to read its exports.
+#[derive(Debug, Clone)]
+pub struct PackageInfo {
+  pub names: Vec<String>,
+  pub version: u16,
+  /// Big for packages cooked for the 360.
+  pub endian: Endian,
+}
+
+#[derive(Debug)]
+pub enum MipData {
+  /// Decompressed tiled data.
+  Loaded(Vec<u8>),
+  /// Data in the texture file cache, see [`Texture2D::load_external`].
+  External { flags: u32, offset: u64, size: u32 },
+  /// Mips in the mip tail have no data of their own.
+  Empty,
+}
+
+#[derive(Debug)]
+pub struct Mip {
+  pub width: u32,
+  pub height: u32,
+  pub data: MipData,
+}
+
+impl Mip {
+  /// The settings the mip is tiled with: a single level padded to the alignment of
+  /// the format.
+  pub fn config(&self, format: Format) -> Config {
+    let alignment = tile_alignment(format);
+    let width = align(self.width, alignment);
+    let height = align(self.height, alignment);
+
+    Config {
+      texture_type: TextureType::TwoD,
+      width,
+      height,
+      depth: None,
+      pitch: width,
+      tiled: true,
+      packed_mips: false,
+      format,
+      mipmap_levels: Some(1),
+      base_address: 0,
+      mip_address: 0,
+    }
+  }
+
+  /// Untiles the mip and crops it to its size. Fails if its data isn't loaded or is
+  /// shorter than the padded mip.
+  pub fn untile(&self, format: Format) -> Result<Vec<u8>, Ue3Error> {
+    let MipData::Loaded(data) = &self.data else {
+      return Err(Ue3Error::MissingTextureFileCache);
+    };
+
+    let config = self.config(format);
+    let format_data = get_format_data(&format);
+    let blocks_x = config.width / format_data.block_width;
+    let blocks_y = config.height / format_data.block_height;
+
+    let size = blocks_x as u64 * blocks_y as u64 * format_data.bytes_per_block as u64;
+    if (data.len() as u64) < size {
+      return Err(Ue3Error::SizeMismatch);
+    }
+
+    let mut linear = vec![0; size as usize];
+    untile(&mut linear, data, &format_data, blocks_x, blocks_y, 0, 0);
+
+    let row = (self.width.div_ceil(format_data.block_width) * format_data.bytes_per_block) as usize;
+    let rows = self.height.div_ceil(format_data.block_height) as usize;
+    let pitch = (blocks_x * format_data.bytes_per_block) as usize;
+
+    Ok(
+      linear
+        .chunks_exact(pitch)
+        .take(rows)
+        .flat_map(|line| &line[..row])
+        .copied()
+        .collect(),
+    )
+  }
+}
+
+#[derive(Debug)]
+pub struct Texture2D {
+  pub size_x: u32,
+  pub size_y: u32,
+  pub format: Format,
+  /// Name of the texture file cache holding external mips, without extension.
+  pub texture_file_cache: Option<String>,
+  pub mips: Vec<Mip>,
+  endian: Endian,
+}
+
+impl Texture2D {
+  /// Reads the serialized data of a Texture2D export. Mips stored in the package
+  /// are decompressed right away, the ones in a texture file cache are left for
+  /// [`Texture2D::load_external`].
+  pub fn read(export: &[u8], package: &PackageInfo) -> Result<Self, Ue3Error> {
+    let mut reader = Cursor::new(export);
+    let mut reader = ExportReader {
+      reader: &mut reader,
+      package,
+    };
+
+    if package.version >= VER_NET_INDEX {
+      reader.i32()?;
+    }
+
+    let mut size_x = None;
+    let mut size_y = None;
+    let mut format = None;
+    let mut texture_file_cache = None;
+
+    while let Some(tag) = reader.property_tag()? {
+      let start = reader.reader.stream_position()?;
+
+      match (tag.name.as_str(), tag.kind.as_str()) {
+        ("SizeX", "IntProperty") => size_x = Some(reader.i32()? as u32),
+        ("SizeY", "IntProperty") => size_y = Some(reader.i32()? as u32),
+        ("Format", "ByteProperty") => {
+          format = Some(if tag.size == 1 {
+            pixel_format_name(reader.u8()?)
+          } else {
+            reader.name()?
+          })
+        }
+        ("TextureFileCacheName", "NameProperty") => texture_file_cache = Some(reader.name()?),
+        _ => {}
+      }
+
+      reader
+        .reader
+        .seek(SeekFrom::Start(start + tag.size as u64))?;
+    }
+
+    let size_x = size_x.ok_or(Ue3Error::MissingProperty("SizeX"))?;
+    let size_y = size_y.ok_or(Ue3Error::MissingProperty("SizeY"))?;
+    let format = format.ok_or(Ue3Error::MissingProperty("Format"))?;
+    let format = pixel_format(&format).ok_or(Ue3Error::UnsupportedFormat(format))?;
+
+    // source art, only kept by the editor
+    reader.bulk_data()?;
+
+    // not preallocated, the count comes straight from the package
+    let mip_count = reader.i32()?;
+    let mut mips = Vec::new();
+
+    for _ in 0..mip_count {
+      let data = reader.bulk_data()?;
+      let width = reader.i32()? as u32;
+      let height = reader.i32()? as u32;
+      if width > MAX_MIP_SIZE || height > MAX_MIP_SIZE {
+        return Err(Ue3Error::InvalidMipSize(width, height));
+      }
+      mips.push(Mip {
+        width,
+        height,
+        data,
+      });
+    }
+
+    Ok(Texture2D {
+      size_x,
+      size_y,
+      format,
+      texture_file_cache,
+      mips,
+      endian: package.endian,
+    })
+  }
+
+  /// Reads the mips stored in the texture file cache `tfc`.
+  pub fn load_external<R: Read + Seek>(&mut self, tfc: &mut R) -> Result<(), Ue3Error> {
+    for mip in &mut self.mips {
+      let MipData::External {
+        flags,
+        offset,
+        size,
+      } = mip.data
+      else {
+        continue;
+      };
+
+      tfc.seek(SeekFrom::Start(offset))?;
+      let data = read_exact(tfc, size)?;
+
+      mip.data = MipData::Loaded(decompress_bulk_data(flags, &data, self.endian)?);
+    }
+
+    Ok(())
+  }
+
+  /// Writes the mips with data, starting at the largest, to a DDS file.
+  pub fn convert_to_dds<W: Write>(&self, output: &mut W) -> Result<(), Ue3Error> {
+    let mips: Vec<&Mip> = self
+      .mips
+      .iter()
+      .take_while(|mip| !matches!(mip.data, MipData::Empty))
+      .collect();
+
+    let first = mips.first().ok_or(Ue3Error::MissingProperty("Mips"))?;
+
+    let mut dds = Dds::new_d3d(
+      get_dds_format(&self.format),
+      first.width,
+      first.height,
+      None,
+      Some(mips.len() as u32),
+      None,
+    )
+    .map_err(Ue3Error::Dds)?;
+
+    dds.data.clear();
+    for mip in mips {
+      dds.data.extend(mip.untile(self.format)?);
+    }
+
+    dds.write(output).map_err(Ue3Error::Dds)
+  }
+}
+
+/// The tiling alignment of a mip, from `X360AlignX` in UEViewer's pixel format table.
+fn tile_alignment(format: Format) -> u32 {
+  match format {
+    Format::Dxt1 | Format::Dxt3 | Format::Dxt5 => 128,
+    Format::RGBA8 => 32,
+  }
+}
+
+// EPixelFormat as serialized by packages predating enum names in byte properties
+fn pixel_format_name(index: u8) -> String {
+  let name = match index {
+    2 => "PF_A8R8G8B8",
+    5 => "PF_DXT1",
+    6 => "PF_DXT3",
+    7 => "PF_DXT5",
+    _ => return format!("EPixelFormat {}", index),
+  };
+
+  name.to_string()
+}
+
+fn pixel_format(name: &str) -> Option<Format> {
+  match name {
+    "PF_A8R8G8B8" => Some(Format::RGBA8),
+    "PF_DXT1" => Some(Format::Dxt1),
+    "PF_DXT3" => Some(Format::Dxt3),
+    "PF_DXT5" => Some(Format::Dxt5),
+    _ => None,
+  }
+}
+
+struct PropertyTag {
+  name: String,
+  kind: String,
+  size: i32,
+}
+
+struct ExportReader<'a, R> {
+  reader: &'a mut R,
+  package: &'a PackageInfo,
+}
+
+impl<R: Read + Seek> ExportReader<'_, R> {
+  fn i32(&mut self) -> Result<i32, Ue3Error> {
+    Ok(i32::read_options(self.reader, self.package.endian, ())?)
+  }
+
+  fn u32(&mut self) -> Result<u32, Ue3Error> {
+    Ok(u32::read_options(self.reader, self.package.endian, ())?)
+  }
+
+  fn u8(&mut self) -> Result<u8, Ue3Error> {
+    Ok(u8::read_options(self.reader, self.package.endian, ())?)
+  }
+
+  fn name(&mut self) -> Result<String, Ue3Error> {
+    let index = self.i32()?;
+    let number = self.i32()?;
+
+    let name = usize::try_from(index)
+      .ok()
+      .and_then(|index| self.package.names.get(index))
+      .ok_or(Ue3Error::InvalidName(index))?;
+
+    // the number tells apart objects sharing a name, it's stored plus one
+    Ok(match number {
+      0 => name.clone(),
+      number => format!("{}_{}", name, number - 1),
+    })
+  }
+
+  fn property_tag(&mut self) -> Result<Option<PropertyTag>, Ue3Error> {
+    let name = self.name()?;
+    if name == "None" {
+      return Ok(None);
+    }
+
+    let kind = self.name()?;
+    let size = self.i32()?;
+    let _array_index = self.i32()?;
+
+    match kind.as_str() {
+      "StructProperty" => {
+        self.name()?;
+      }
+      "BoolProperty" if self.package.version < VER_BOOL_PROPERTY_BYTE => {
+        self.i32()?;
+      }
+      "BoolProperty" => {
+        self.u8()?;
+      }
+      "ByteProperty" if self.package.version >= VER_BYTE_PROPERTY_ENUM => {
+        self.name()?;
+      }
+      _ => {}
+    }
+
+    Ok(Some(PropertyTag { name, kind, size }))
+  }
+
+  fn bulk_data(&mut self) -> Result<MipData, Ue3Error> {
+    let flags = self.u32()?;
+    let _element_count = self.i32()?;
+    let size = self.i32()? as u32;
+    let offset = self.u32()? as u64;
+
+    let external = flags & BULKDATA_STORE_IN_SEPARATE_FILE != 0;
+    if external && flags & BULKDATA_UNUSED == 0 && size > 0 {
+      return Ok(MipData::External {
+        flags,
+        offset,
+        size,
+      });
+    }
+
+    let data = read_exact(self.reader, if external { 0 } else { size })?;
+
+    if flags & BULKDATA_UNUSED != 0 || data.is_empty() {
+      return Ok(MipData::Empty);
+    }
+
+    Ok(MipData::Loaded(decompress_bulk_data(
+      flags,
+      &data,
+      self.package.endian,
+    )?))
+  }
+}
+
+// Reads `size` bytes, growing the buffer as they arrive instead of trusting the size
+// read from the package with an allocation up front.
+fn read_exact<R: Read>(reader: &mut R, size: u32) -> Result<Vec<u8>, Ue3Error> {
+  let mut data = Vec::new();
+  reader.take(size as u64).read_to_end(&mut data)?;
+
+  if data.len() != size as usize {
+    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+  }
+
+  Ok(data)
+}
+
+// Compressed bulk data is a summary of the compressed and uncompressed sizes, one
+// such pair per block and then the blocks, each compressed on its own.
+fn decompress_bulk_data(flags: u32, data: &[u8], endian: Endian) -> Result<Vec<u8>, Ue3Error> {
+  let compression = BULKDATA_SERIALIZE_COMPRESSED_ZLIB
+    | BULKDATA_SERIALIZE_COMPRESSED_LZO
+    | BULKDATA_SERIALIZE_COMPRESSED_LZX;
+
+  match flags & compression {
+    0 => return Ok(data.to_vec()),
+    BULKDATA_SERIALIZE_COMPRESSED_ZLIB | BULKDATA_SERIALIZE_COMPRESSED_LZX => {}
+    _ => return Err(Ue3Error::UnsupportedCompression(flags)),
+  }
+
+  let mut reader = Cursor::new(data);
+  let mut read = || u32::read_options(&mut reader, endian, ());
+
+  if read()? != PACKAGE_FILE_TAG {
+    return Err(Ue3Error::InvalidBulkData);
+  }
+
+  let block_size = read()?.max(1);
+  let _compressed_size = read()?;
+  let uncompressed_size = read()?;
+
+  // the size pairs have to fit in the data before anything is allocated for them
+  let blocks = uncompressed_size.div_ceil(block_size);
+  if blocks as usize > data.len() / 8 {
+    return Err(Ue3Error::InvalidBulkData);
+  }
+
+  let mut sizes = Vec::with_capacity(blocks as usize);
+  for _ in 0..blocks {
+    sizes.push((read()?, read()?));
+  }
+
+  let mut position = reader.position() as usize;
+  let mut output = Vec::new();
+
+  for (compressed, uncompressed) in sizes {
+    let block = data
+      .get(position..position + compressed as usize)
+      .ok_or(Ue3Error::SizeMismatch)?;
+    position += compressed as usize;
+
+    let block = if flags & BULKDATA_SERIALIZE_COMPRESSED_LZX != 0 {
+      decompress_raw(block, DEFAULT_WINDOW_BITS).map_err(Ue3Error::Lzx)?
+    } else {
+      // never more than the summary announces
+      decompress_to_vec_zlib_with_limit(block, uncompressed as usize).map_err(
+        |error| match error.status {
+          TINFLStatus::HasMoreOutput => Ue3Error::SizeMismatch,
+          status => Ue3Error::Zlib(status),
+        },
+      )?
+    };
+
+    if block.len() != uncompressed as usize {
+      return Err(Ue3Error::SizeMismatch);
+    }
+
+    output.extend(block);
+  }
+
+  if output.len() != uncompressed_size as usize {
+    return Err(Ue3Error::SizeMismatch);
+  }
+
+  Ok(output)
+}
@@ -0,0 +1,333 @@
+use std::fmt::{Display, Formatter};
+
+use dds::Dds;
+
+use crate::format::{get_format_data, get_format_from_dds_file, get_format_from_texture, Encoding};
+use crate::math::{align, log2_ceil};
+use crate::mip_map::TextureInfo;
+use crate::texture::{dds_texture_type, TextureError};
+use crate::texture_header::{Dimension, TextureFormat, TextureKind, TextureMetadata, TextureSign};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+  /// Unusual, but the texture can still be converted.
+  Warning,
+  /// The header is garbage or can't be converted.
+  Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+  pub severity: Severity,
+  pub field: &'static str,
+  pub description: String,
+}
+
+impl Display for ValidationIssue {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{:?} in {}: {}",
+      self.severity, self.field, self.description
+    )
+  }
+}
+
+// Collects the issues found. While only counting them, as scoring does, the
+// descriptions are never formatted.
+struct Issues {
+  issues: Option<Vec<ValidationIssue>>,
+  errors: usize,
+  warnings: usize,
+}
+
+impl Issues {
+  fn collect() -> Self {
+    Self {
+      issues: Some(Vec::new()),
+      errors: 0,
+      warnings: 0,
+    }
+  }
+
+  fn count() -> Self {
+    Self {
+      issues: None,
+      errors: 0,
+      warnings: 0,
+    }
+  }
+
+  fn warning(&mut self, field: &'static str, description: impl FnOnce() -> String) {
+    self.warnings += 1;
+    self.push(Severity::Warning, field, description);
+  }
+
+  fn error(&mut self, field: &'static str, description: impl FnOnce() -> String) {
+    self.errors += 1;
+    self.push(Severity::Error, field, description);
+  }
+
+  fn push(
+    &mut self,
+    severity: Severity,
+    field: &'static str,
+    description: impl FnOnce() -> String,
+  ) {
+    if let Some(issues) = &mut self.issues {
+      issues.push(ValidationIssue {
+        severity,
+        field,
+        description: description(),
+      });
+    }
+  }
+
+  fn into_vec(self) -> Vec<ValidationIssue> {
+    self.issues.unwrap_or_default()
+  }
+}
+
+// Formats only the render backend (EDRAM) can use, they never appear in textures.
+fn is_edram_format(format: &TextureFormat) -> bool {
+  matches!(
+    format,
+    TextureFormat::_16_16Edram
+      | TextureFormat::_16_16_16_16Edram
+      | TextureFormat::_8_8_8_8GammaEdram
+      | TextureFormat::_2_10_10_10FloatEdram
+  )
+}
+
+fn is_compressed_format(format: &TextureFormat) -> bool {
+  matches!(
+    format,
+    TextureFormat::Dxt1
+      | TextureFormat::Dxt2_3
+      | TextureFormat::Dxt4_5
+      | TextureFormat::Dxn
+      | TextureFormat::Dxt3A
+      | TextureFormat::Dxt5A
+      | TextureFormat::Ctx1
+      | TextureFormat::Dxt3AAs1_1_1_1
+  )
+}
+
+impl TextureMetadata {
+  /// Checks the fetch constant for values no XDK would produce. Headers found by
+  /// scanning data should be rejected when any issue is an error.
+  pub fn validate(&self) -> Vec<ValidationIssue> {
+    let mut issues = Issues::collect();
+    self.check(&mut issues);
+    issues.into_vec()
+  }
+
+  /// The number of errors and warnings [`TextureMetadata::validate`] would return,
+  /// without allocating their descriptions.
+  pub(crate) fn count_issues(&self) -> (usize, usize) {
+    let mut issues = Issues::count();
+    self.check(&mut issues);
+    (issues.errors, issues.warnings)
+  }
+
+  fn check(&self, issues: &mut Issues) {
+    if !matches!(self.kind(), TextureKind::Texture) {
+      issues.error("kind", || format!("{:?} isn't a texture", self.kind()));
+    }
+
+    let dimension = self.dimension();
+    let format = self.format();
+
+    if is_edram_format(&format) {
+      issues.error("format", || format!("{format:?} is a render target format"));
+    } else if is_compressed_format(&format) && matches!(dimension, Dimension::OneD) {
+      issues.error("format", || {
+        format!("{format:?} can't be used for 1D textures")
+      });
+    } else if get_format_from_texture(&format).is_none() {
+      issues.warning("format", || {
+        format!("{format:?} isn't supported for conversion")
+      });
+    }
+
+    // The size fields are always in range, only the unused bits can be wrong.
+    let (width, height, depth) = self.size();
+
+    match dimension {
+      Dimension::OneD if self.texture_size() >> 24 != 0 => {
+        issues.error("texture_size", || {
+          "1D textures don't use the upper 8 bits".to_string()
+        });
+      }
+      Dimension::TwoDOrStacked if depth > 1 && !self.stacked() => {
+        issues.warning("texture_size", || {
+          format!("{depth} layers, but not stacked")
+        });
+      }
+      Dimension::CubeMap if depth != 6 => {
+        issues.error("texture_size", || {
+          format!("cube maps have 6 faces, not {depth}")
+        });
+      }
+      _ => {}
+    }
+
+    for (field, valid) in [
+      ("request_size", self.request_size_or_err().is_ok()),
+      ("swizzle_x", self.swizzle_x_or_err().is_ok()),
+      ("swizzle_y", self.swizzle_y_or_err().is_ok()),
+      ("swizzle_z", self.swizzle_z_or_err().is_ok()),
+      ("swizzle_w", self.swizzle_w_or_err().is_ok()),
+      ("aniso_filter", self.aniso_filter_or_err().is_ok()),
+      ("arbitrary_filter", self.arbitrary_filter_or_err().is_ok()),
+    ] {
+      if !valid {
+        issues.error(field, || "invalid bit pattern".to_string());
+      }
+    }
+
+    let min_mip_level = self.min_mip_level() as u32;
+    let max_mip_level = self.max_mip_level() as u32;
+
+    let mut largest = width.max(height);
+    if matches!(dimension, Dimension::ThreeD) {
+      largest = largest.max(depth);
+    }
+    let last_mip_level = log2_ceil(largest);
+
+    if max_mip_level < min_mip_level {
+      issues.error("max_mip_level", || {
+        format!("{max_mip_level} is below min_mip_level {min_mip_level}")
+      });
+    }
+
+    if max_mip_level > last_mip_level {
+      issues.error("max_mip_level", || {
+        format!("{max_mip_level} exceeds {last_mip_level}, the last level of {width}x{height}")
+      });
+    }
+
+    let pitch = (self.pitch() as u32) << 5;
+
+    if pitch < width && !matches!(dimension, Dimension::OneD) {
+      issues.error("pitch", || {
+        format!("{pitch} is smaller than the width {width}")
+      });
+    }
+
+    let has_mips = max_mip_level > 0;
+    let mip_address = self.mip_address() << 12;
+    let base_address = self.base_address() << 12;
+
+    if has_mips && mip_address == 0 {
+      issues.warning("mip_address", || "mip levels without mip data".to_string());
+    } else if !has_mips && mip_address != 0 {
+      issues.warning("mip_address", || "mip data without mip levels".to_string());
+    }
+
+    if self.packed_mips() && !has_mips && width.min(height) > 16 {
+      issues.warning("packed_mips", || {
+        "set without anything to pack into the mip tail".to_string()
+      });
+    }
+
+    if let Some(format) = get_format_from_texture(&format) {
+      let format_data = get_format_data(&format);
+      let block_pitch = align(width, format_data.block_width) / format_data.block_width;
+      let expected = align(block_pitch, 32) * format_data.block_width;
+
+      if self.tiled() && pitch > expected {
+        issues.warning("pitch", || {
+          format!("{pitch} is wider than the {expected} tiles need")
+        });
+      }
+
+      let info = TextureInfo {
+        width,
+        height,
+        depth,
+        pitch,
+        tiled: self.tiled(),
+        packed_mips: self.packed_mips(),
+        format: &format_data,
+        base_address,
+        mip_address,
+      };
+
+      // garbage sizes would overflow the extent calculation
+      let texels = pitch.max(width) as u64 * align(height, 128) as u64 * depth as u64;
+      let base_end = if texels * 4 <= u32::MAX as u64 {
+        base_address.saturating_add(info.get_base_size())
+      } else {
+        base_address
+      };
+
+      if mip_address > base_address && mip_address < base_end {
+        issues.error("mip_address", || {
+          format!("{mip_address:#x} overlaps the base level ending at {base_end:#x}")
+        });
+      }
+    }
+  }
+
+  /// Checks that `dds` can be tiled into this texture. A DX10 header can mark the
+  /// data as sRGB or SNORM, which has to agree with the signs of the components, any
+  /// disagreement is an error. Files without one can't, so their signs aren't checked.
+  pub fn validate_dds(&self, dds: &Dds) -> Vec<ValidationIssue> {
+    let mut issues = Issues::collect();
+
+    if let Err(TextureError::CubeMapArray) = dds_texture_type(dds) {
+      issues.error("dimension", || {
+        "the DDS is an array of cube maps".to_string()
+      });
+    }
+
+    let Some(provided) = get_format_from_dds_file(dds) else {
+      issues.error("format", || "the DDS format isn't supported".to_string());
+      return issues.into_vec();
+    };
+
+    match get_format_from_texture(&self.format()) {
+      Some(format) if format != provided.format => {
+        issues.error("format", || {
+          format!("the DDS holds {:?}, not {format:?}", provided.format)
+        });
+      }
+      Some(_) => {}
+      None => issues.error("format", || {
+        "the format of the header can't be tiled".to_string()
+      }),
+    }
+
+    let Some(encoding) = provided.encoding else {
+      return issues.into_vec();
+    };
+
+    let srgb = encoding == Encoding::Srgb;
+    if srgb != self.is_srgb() {
+      issues.error("sign_x", || {
+        if srgb {
+          "the DDS is sRGB, but the components aren't gamma".to_string()
+        } else {
+          "the components are gamma, but the DDS isn't sRGB".to_string()
+        }
+      });
+    }
+
+    let snorm = encoding == Encoding::Snorm;
+    let signed = [self.sign_x(), self.sign_y(), self.sign_z(), self.sign_w()]
+      .iter()
+      .all(|sign| matches!(sign, TextureSign::Signed));
+    if snorm != signed {
+      issues.error("sign_x", || {
+        if snorm {
+          "the DDS is SNORM, but the components aren't signed".to_string()
+        } else {
+          "the components are signed, but the DDS isn't SNORM".to_string()
+        }
+      });
+    }
+
+    issues.into_vec()
+  }
+}
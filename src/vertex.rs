@@ -0,0 +1,85 @@
+use std::io::{Read, Seek, Write};
+
+use binrw::{BinRead, BinResult, BinWrite};
+use modular_bitfield::{bitfield, specifiers::*};
+
+use crate::texture_header::{Endian, RequestSize, ResourceCommon, TextureKind, DEFAULT_ENDIAN};
+
+// https://github.com/xenia-project/xenia/blob/master/src/xenia/gpu/xenos.h
+// see /licenses/xenia.txt
+
+#[bitfield]
+#[derive(Clone, Debug)]
+pub struct VertexFetchConstant {
+  pub kind: TextureKind,
+  // in dwords
+  pub address: B30,
+  pub endianness: Endian,
+  // in dwords
+  pub size: B24,
+  pub request_size: RequestSize,
+  pub clamp_disable: bool,
+  #[skip]
+  _padding0: B3,
+}
+
+impl VertexFetchConstant {
+  pub fn address_bytes(&self) -> u32 {
+    self.address() << 2
+  }
+
+  pub fn size_bytes(&self) -> u32 {
+    self.size() << 2
+  }
+}
+
+/// `D3DVertexBuffer` as stored by the XDK, see [`TextureHeader`](crate::TextureHeader)
+/// for the byte order.
+#[derive(BinRead, BinWrite, Debug)]
+pub struct VertexBufferHeader {
+  pub common: u32,
+  pub reference_count: u32,
+  pub fence: u32,
+  pub read_fence: u32,
+  pub identifier: u32,
+  pub base_flush: u32,
+  fetch_constant: [u32; 2],
+}
+
+impl VertexBufferHeader {
+  /// Reads a header in [`DEFAULT_ENDIAN`], the byte order of 360 data.
+  pub fn read_360<R: Read + Seek>(reader: &mut R) -> BinResult<Self> {
+    Self::read_options(reader, DEFAULT_ENDIAN, ())
+  }
+
+  /// Writes the header in [`DEFAULT_ENDIAN`], the byte order of 360 data.
+  pub fn write_360<W: Write + Seek>(&self, writer: &mut W) -> BinResult<()> {
+    self.write_options(writer, DEFAULT_ENDIAN, ())
+  }
+
+  pub fn resource_common(&self) -> ResourceCommon {
+    ResourceCommon::from_bytes(self.common.to_le_bytes())
+  }
+
+  pub fn fetch_constant(&self) -> VertexFetchConstant {
+    let mut fetch_constant = [0; 8];
+
+    for (chunk, dword) in fetch_constant.chunks_exact_mut(4).zip(self.fetch_constant) {
+      chunk.copy_from_slice(&dword.to_le_bytes());
+    }
+
+    VertexFetchConstant::from_bytes(fetch_constant)
+  }
+
+  pub fn set_fetch_constant(&mut self, fetch_constant: &VertexFetchConstant) {
+    let fetch_constant = fetch_constant.clone().into_bytes();
+
+    for (dword, chunk) in self
+      .fetch_constant
+      .iter_mut()
+      .zip(fetch_constant.chunks_exact(4))
+    {
+      *dword = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+  }
+}
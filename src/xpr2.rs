@@ -0,0 +1,339 @@
+use std::fmt;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+use binrw::{BinRead, BinResult, BinWrite, NullString};
+
+use crate::format::get_format_data;
+use crate::math::align;
+use crate::texture_header::{TextureFormat, TextureHeader, TextureMetadata};
+use crate::{build_texture_info, convert_to_dds, Config, TextureError};
+
+// Offsets in the header are relative to the end of the first three fields.
+const BASE_OFFSET: u64 = 12;
+
+// Texture data has to start on a 4 KiB boundary, addresses are stored in pages.
+const PAGE_SIZE: u32 = 4096;
+
+#[derive(BinRead, BinWrite, Debug)]
+#[brw(big, magic = b"XPR2")]
+struct Xpr2Header {
+  // everything between the first three fields and the data section
+  header_size: u32,
+  data_size: u32,
+  resource_count: u32,
+  #[br(count = resource_count)]
+  entries: Vec<ResourceEntry>,
+}
+
+#[derive(BinRead, BinWrite, Debug, Clone)]
+#[brw(big)]
+struct ResourceEntry {
+  fourcc: [u8; 4],
+  offset: u32,
+  size: u32,
+  name_offset: u32,
+}
+
+#[derive(Debug)]
+pub enum Xpr2Error {
+  Read(binrw::Error),
+  /// An index past the end of [`Xpr2::resources`].
+  MissingResource(usize),
+  /// A resource of another kind where a texture was expected.
+  NotATexture(String),
+  /// A texture format that can't be untiled.
+  UnsupportedFormat(TextureFormat),
+  Texture(TextureError),
+}
+
+impl fmt::Display for Xpr2Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Xpr2Error::Read(error) => write!(f, "{}", error),
+      Xpr2Error::MissingResource(index) => write!(f, "there's no resource {}", index),
+      Xpr2Error::NotATexture(name) => write!(f, "resource {} isn't a texture", name),
+      Xpr2Error::UnsupportedFormat(format) => write!(f, "unsupported texture format {:?}", format),
+      Xpr2Error::Texture(error) => write!(f, "{}", error),
+    }
+  }
+}
+
+impl std::error::Error for Xpr2Error {}
+
+impl From<binrw::Error> for Xpr2Error {
+  fn from(error: binrw::Error) -> Self {
+    Xpr2Error::Read(error)
+  }
+}
+
+impl From<TextureError> for Xpr2Error {
+  fn from(error: TextureError) -> Self {
+    Xpr2Error::Texture(error)
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Xpr2Type {
+  Texture2D,
+  CubeTexture,
+  VolumeTexture,
+  ArrayTexture,
+  VertexBuffer,
+  IndexBuffer,
+  User,
+  Unknown([u8; 4]),
+}
+
+impl Xpr2Type {
+  pub fn from_fourcc(fourcc: [u8; 4]) -> Self {
+    match &fourcc {
+      b"TX2D" => Xpr2Type::Texture2D,
+      b"TXCM" => Xpr2Type::CubeTexture,
+      b"TX3D" => Xpr2Type::VolumeTexture,
+      b"TXAR" => Xpr2Type::ArrayTexture,
+      b"VBUF" => Xpr2Type::VertexBuffer,
+      b"IBUF" => Xpr2Type::IndexBuffer,
+      b"USER" => Xpr2Type::User,
+      _ => Xpr2Type::Unknown(fourcc),
+    }
+  }
+
+  pub fn fourcc(&self) -> [u8; 4] {
+    match self {
+      Xpr2Type::Texture2D => *b"TX2D",
+      Xpr2Type::CubeTexture => *b"TXCM",
+      Xpr2Type::VolumeTexture => *b"TX3D",
+      Xpr2Type::ArrayTexture => *b"TXAR",
+      Xpr2Type::VertexBuffer => *b"VBUF",
+      Xpr2Type::IndexBuffer => *b"IBUF",
+      Xpr2Type::User => *b"USER",
+      Xpr2Type::Unknown(fourcc) => *fourcc,
+    }
+  }
+
+  pub fn is_texture(&self) -> bool {
+    matches!(
+      self,
+      Xpr2Type::Texture2D
+        | Xpr2Type::CubeTexture
+        | Xpr2Type::VolumeTexture
+        | Xpr2Type::ArrayTexture
+    )
+  }
+}
+
+#[derive(Debug)]
+pub struct Xpr2Resource {
+  pub name: String,
+  pub kind: Xpr2Type,
+  /// The resource header exactly as stored in the file.
+  pub header: Vec<u8>,
+}
+
+impl Xpr2Resource {
+  /// Parses the header of texture resources, `None` for every other kind.
+  pub fn texture_header(&self) -> Option<BinResult<TextureHeader>> {
+    if !self.kind.is_texture() {
+      return None;
+    }
+
+    Some(TextureHeader::read_360(&mut Cursor::new(&self.header)))
+  }
+
+  // The texture header, or why there is none.
+  fn texture(&self) -> Result<TextureHeader, Xpr2Error> {
+    match self.texture_header() {
+      Some(header) => Ok(header?),
+      None => Err(Xpr2Error::NotATexture(self.name.clone())),
+    }
+  }
+}
+
+// Sizes come from the file, so the buffer grows with what's actually there instead of
+// being allocated up front.
+fn read_exact<R: Read>(reader: &mut R, size: u32) -> std::io::Result<Vec<u8>> {
+  let mut data = Vec::new();
+  reader.take(size as u64).read_to_end(&mut data)?;
+
+  if data.len() != size as usize {
+    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+  }
+
+  Ok(data)
+}
+
+/// An Xbox Packed Resource file. Texture addresses in the resource headers are
+/// relative to the start of [`Xpr2::data`].
+#[derive(Debug)]
+pub struct Xpr2 {
+  pub resources: Vec<Xpr2Resource>,
+  pub data: Vec<u8>,
+}
+
+impl Xpr2 {
+  pub fn read<R: Read + Seek>(reader: &mut R) -> BinResult<Self> {
+    let start = reader.stream_position()?;
+    let header = Xpr2Header::read(reader)?;
+
+    let mut resources = Vec::with_capacity(header.entries.len());
+
+    for entry in &header.entries {
+      reader.seek(SeekFrom::Start(
+        start + BASE_OFFSET + entry.name_offset as u64,
+      ))?;
+      let name = NullString::read(reader)?.to_string();
+
+      reader.seek(SeekFrom::Start(start + BASE_OFFSET + entry.offset as u64))?;
+      resources.push(Xpr2Resource {
+        name,
+        kind: Xpr2Type::from_fourcc(entry.fourcc),
+        header: read_exact(reader, entry.size)?,
+      });
+    }
+
+    reader.seek(SeekFrom::Start(
+      start + BASE_OFFSET + header.header_size as u64,
+    ))?;
+    let data = read_exact(reader, header.data_size)?;
+
+    Ok(Xpr2 { resources, data })
+  }
+
+  /// Untiles a texture resource into a DDS file.
+  pub fn convert_to_dds<W: Write>(
+    &self,
+    resource: &Xpr2Resource,
+    output: &mut W,
+  ) -> Result<(), Xpr2Error> {
+    let metadata = resource.texture()?.metadata();
+
+    let config = match Config::from_metadata(&metadata) {
+      Some(config) => config,
+      None => return Err(Xpr2Error::UnsupportedFormat(metadata.format())),
+    };
+
+    Ok(convert_to_dds(&config, &self.data, output)?)
+  }
+
+  /// Swaps in new data for a texture resource, which may have a different size than
+  /// before. `data` has to be laid out the way `header` describes for a base address
+  /// of 0, as [`LayoutRequest::plan`](crate::LayoutRequest::plan) does. The data is
+  /// written over the old texture if it fits and appended otherwise, with the
+  /// addresses in the header rewritten to match. Fails without touching anything if
+  /// the resource at `index` isn't a texture.
+  pub fn replace_texture(
+    &mut self,
+    index: usize,
+    mut header: TextureHeader,
+    data: &[u8],
+  ) -> Result<(), Xpr2Error> {
+    let old_header = match self.resources.get(index) {
+      Some(resource) => resource.texture()?,
+      None => return Err(Xpr2Error::MissingResource(index)),
+    };
+
+    let size = align(data.len() as u32, PAGE_SIZE);
+    let old =
+      texture_extent(&old_header.metadata()).filter(|old| old.end as usize <= self.data.len());
+
+    if let Some(old) = &old {
+      self.data[old.start as usize..old.end as usize].fill(0);
+    }
+
+    let start = match old {
+      Some(old) if size <= old.len() as u32 => old.start,
+      _ => {
+        let start = align(self.data.len() as u32, PAGE_SIZE);
+        self.data.resize((start + size) as usize, 0);
+        start
+      }
+    };
+
+    self.data[start as usize..start as usize + data.len()].copy_from_slice(data);
+
+    let mut metadata = header.metadata();
+    let page = start / PAGE_SIZE;
+
+    metadata.set_base_address(metadata.base_address() + page);
+    if metadata.mip_address() != 0 {
+      metadata.set_mip_address(metadata.mip_address() + page);
+    }
+
+    header.set_metadata(&metadata);
+
+    let mut bytes = Cursor::new(Vec::new());
+    header.write_360(&mut bytes)?;
+    self.resources[index].header = bytes.into_inner();
+    Ok(())
+  }
+
+  /// Writes the resource table, headers and names followed by the data section,
+  /// which starts on a 4 KiB boundary. Resource headers are written as they are.
+  pub fn write<W: Write + Seek>(&self, writer: &mut W) -> BinResult<()> {
+    let table_end = 16 + 16 * self.resources.len() as u32;
+
+    let mut offset = table_end - BASE_OFFSET as u32;
+    let mut entries = Vec::with_capacity(self.resources.len());
+
+    for resource in &self.resources {
+      entries.push(ResourceEntry {
+        fourcc: resource.kind.fourcc(),
+        offset,
+        size: resource.header.len() as u32,
+        name_offset: 0,
+      });
+
+      offset += resource.header.len() as u32;
+    }
+
+    for (entry, resource) in entries.iter_mut().zip(&self.resources) {
+      entry.name_offset = offset;
+      offset += resource.name.len() as u32 + 1;
+    }
+
+    let data_start = align(offset + BASE_OFFSET as u32, PAGE_SIZE);
+    let data_size = align(self.data.len() as u32, PAGE_SIZE);
+
+    Xpr2Header {
+      header_size: data_start - BASE_OFFSET as u32,
+      data_size,
+      resource_count: entries.len() as u32,
+      entries,
+    }
+    .write(writer)?;
+
+    for resource in &self.resources {
+      writer.write_all(&resource.header)?;
+    }
+
+    for resource in &self.resources {
+      writer.write_all(resource.name.as_bytes())?;
+      writer.write_all(&[0])?;
+    }
+
+    let padding = data_start - (offset + BASE_OFFSET as u32);
+    writer.write_all(&vec![0; padding as usize])?;
+
+    writer.write_all(&self.data)?;
+    writer.write_all(&vec![0; data_size as usize - self.data.len()])?;
+
+    Ok(())
+  }
+}
+
+// The bytes of the data section a texture uses, `None` if its format isn't supported.
+fn texture_extent(metadata: &TextureMetadata) -> Option<Range<u32>> {
+  let config = Config::from_metadata(metadata)?;
+  let format_data = get_format_data(&config.format);
+  let info = build_texture_info(&config, &format_data);
+
+  let mut end = info.base_address + info.get_base_size();
+
+  if info.mip_address != 0 {
+    let levels = config.mipmap_levels.unwrap_or(1);
+    end = end.max(info.mip_address + info.get_mips_size(levels));
+  }
+
+  Some(info.base_address..end)
+}